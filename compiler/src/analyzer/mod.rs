@@ -1,4 +1,8 @@
-use crate::parser::{AstNode, BinaryOp, UnaryOp, HttpMethod};
+use crate::lexer::Span;
+use crate::parser::{Annotation, AstNode, BinaryOp, UnaryOp, HttpMethod, MatchArm, ObjectKey, Pattern, TemplatePart};
+use crate::project::Project;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use indexmap::IndexMap;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 
@@ -9,8 +13,12 @@ pub enum Type {
     Boolean,
     Null,
     Array(Box<Type>),
-    Object(HashMap<String, Type>),
+    Tuple(Vec<Type>),
+    Object(IndexMap<String, Type>),
     Promise(Option<Box<Type>>),
+    Regex,
+    Nullable(Box<Type>), // the inner type or null
+    Function(usize), // a declared function and its parameter count
     Any,
 }
 
@@ -22,11 +30,18 @@ impl fmt::Display for Type {
             Type::Boolean => write!(f, "boolean"),
             Type::Null => write!(f, "null"),
             Type::Array(inner) => write!(f, "array<{}>", inner),
+            Type::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(Type::to_string).collect();
+                write!(f, "({})", elements.join(", "))
+            }
             Type::Object(_) => write!(f, "object"),
             Type::Promise(inner) => match inner {
                 Some(inner_type) => write!(f, "promise<{}>", inner_type),
                 None => write!(f, "promise<any>"),
             },
+            Type::Regex => write!(f, "regex"),
+            Type::Nullable(inner) => write!(f, "nullable<{}>", inner),
+            Type::Function(_) => write!(f, "function"),
             Type::Any => write!(f, "any"),
         }
     }
@@ -39,34 +54,44 @@ pub struct Symbol {
     pub is_resolved: bool,
     pub dependencies: HashSet<String>,
     pub dependents: HashSet<String>,
-    pub definition_line: usize,
+    pub definition_span: Span,
     pub ast_node: Option<AstNode>,
+    // Dependencies only needed by a conditional branch
+    pub deferred_dependencies: HashSet<String>,
+    // Nothing needs this symbol up front, it is evaluated when a branch uses it
+    pub is_deferred: bool,
+    // Seconds a computed value may be reused by later runs, from `@cache`
+    pub cache_seconds: Option<u64>,
 }
 
 impl Symbol {
-    pub fn new(name: String, symbol_type: Type, definition_line: usize) -> Self {
+    pub fn new(name: String, symbol_type: Type, definition_span: Span) -> Self {
         Self {
             name,
             symbol_type,
             is_resolved: false,
             dependencies: HashSet::new(),
             dependents: HashSet::new(),
-            definition_line,
+            definition_span,
             ast_node: None,
+            deferred_dependencies: HashSet::new(),
+            is_deferred: false,
+            cache_seconds: None,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
-    symbols: HashMap<String, Symbol>,
+    // In declaration order, so the resolution order is the same every run
+    symbols: IndexMap<String, Symbol>,
     resolution_order: Vec<String>,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
-            symbols: HashMap::new(),
+            symbols: IndexMap::new(),
             resolution_order: Vec::new(),
         }
     }
@@ -92,15 +117,55 @@ impl SymbolTable {
         }
     }
 
+    /// Order the symbols so each comes after its dependencies. Every
+    /// dependency must be a symbol of this table.
     pub fn resolve_dependencies(&mut self) -> Result<Vec<String>, AnalyzerError> {
-        // Topological sort to determine resolution order
+        for symbol in self.symbols.values() {
+            if let Some(undefined) = symbol.dependencies.iter().find(|dep| !self.symbols.contains_key(*dep)) {
+                // Point at the reference itself, not the declaration holding it
+                let span = symbol.ast_node.as_ref()
+                    .and_then(|node| SemanticAnalyzer::reference_span(node, undefined))
+                    .unwrap_or(symbol.definition_span);
+                return Err(AnalyzerError::UndefinedSymbol {
+                    name: undefined.clone(),
+                    span,
+                });
+            }
+        }
+        self.resolve_local_dependencies()
+    }
+
+    /// Like `resolve_dependencies`, but names outside this table count as
+    /// already resolved. A function body's table uses this, since the
+    /// function's own symbol carries those names to the enclosing table.
+    pub fn resolve_local_dependencies(&mut self) -> Result<Vec<String>, AnalyzerError> {
+        // A symbol may be referenced before it is defined, so rebuild the
+        // dependents from the dependencies now that every symbol is known
+        let edges: Vec<(String, String)> = self.symbols.values()
+            .flat_map(|symbol| symbol.dependencies.iter().map(|dep| (symbol.name.clone(), dep.clone())))
+            .collect();
+        for symbol in self.symbols.values_mut() {
+            symbol.dependents.clear();
+        }
+        for (dependent, dependency) in edges {
+            if let Some(dep_symbol) = self.symbols.get_mut(&dependency) {
+                dep_symbol.dependents.insert(dependent);
+            }
+        }
+        
+        // Topological sort to determine resolution order; ties keep declaration order
         let mut in_degree: HashMap<String, usize> = HashMap::new();
         let mut graph: HashMap<String, Vec<String>> = HashMap::new();
         
-        // Initialize
+        // Initialize; only dependencies inside this table hold a symbol back
         for (name, symbol) in &self.symbols {
-            in_degree.insert(name.clone(), symbol.dependencies.len());
-            graph.insert(name.clone(), symbol.dependents.iter().cloned().collect());
+            let defined = symbol.dependencies.iter()
+                .filter(|dep| self.symbols.contains_key(*dep))
+                .count();
+            in_degree.insert(name.clone(), defined);
+            for dependency in &symbol.dependencies {
+                graph.entry(dependency.clone()).or_default().push(name.clone());
+            }
         }
         
         // Kahn's algorithm
@@ -108,8 +173,8 @@ impl SymbolTable {
         let mut result = Vec::new();
         
         // Find symbols with no dependencies
-        for (name, &degree) in &in_degree {
-            if degree == 0 {
+        for name in self.symbols.keys() {
+            if in_degree[name] == 0 {
                 queue.push_back(name.clone());
             }
         }
@@ -142,16 +207,53 @@ impl SymbolTable {
         Ok(result)
     }
 
-    pub fn symbols(&self) -> &HashMap<String, Symbol> {
+    pub fn symbols(&self) -> &IndexMap<String, Symbol> {
         &self.symbols
     }
 }
 
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct SemanticAnalyzer {
     symbol_table: SymbolTable,
-    current_scope: String,
+    scopes: Vec<Scope>,
+    anonymous_scopes: usize,
+    defining: Vec<String>,
+    warnings: Vec<AnalyzerWarning>,
     endpoints: Vec<EndpointInfo>,
     tests: Vec<TestInfo>,
+    functions: Vec<FunctionInfo>,
+    function_names: HashSet<String>,
+    // Tables a function body can still look names up in, innermost last
+    enclosing: Vec<SymbolTable>,
+    // Functions whose bodies are being inferred, to stop at recursive calls
+    calling: Vec<String>,
+    // The path and name prefix of each file of a project
+    modules: Vec<(String, Option<String>)>,
+    // The module each import namespace of the file being collected refers to
+    imports: HashMap<String, usize>,
+    // The subject each `match` binding reads and where in it the value is
+    match_bindings: HashMap<String, (String, Vec<PatternStep>)>,
+}
+
+/// One step from a `match` subject into the part a binding holds
+#[derive(Debug, Clone)]
+enum PatternStep {
+    Field(String),
+    Element(usize),
+    // The elements from this position on
+    Rest(usize),
+}
+
+/// Names declared by one object literal (or the program, for globals)
+#[derive(Debug, Clone)]
+struct Scope {
+    prefix: Option<String>,
+    names: HashSet<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -160,7 +262,19 @@ pub struct EndpointInfo {
     pub method: HttpMethod,
     pub path: String,
     pub handler: AstNode,
-    pub line: usize,
+    pub span: Span,
+}
+
+/// A declared function. Its body is analyzed in a symbol table of its own,
+/// where each parameter is a symbol `name.parameter` and the body is `name()`.
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub symbol_table: SymbolTable,
+    pub resolution_order: Vec<String>,
+    pub result: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -169,29 +283,93 @@ pub struct TestInfo {
     pub expect_expression: AstNode,
     pub inputs: HashMap<String, AstNode>,
     pub expected_output: AstNode,
-    pub is_regex: bool,
-    pub line: usize,
+    pub assertion: Assertion,
+    pub span: Span,
+}
+
+/// How a test compares its value with the expected one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assertion {
+    Equals,
+    Matches,
+    GreaterThan,
+    LessThan,
+}
+
+impl Assertion {
+    const ALL: [Assertion; 4] = [Assertion::Equals, Assertion::Matches, Assertion::GreaterThan, Assertion::LessThan];
+
+    /// The configuration field the assertion is written as
+    pub fn field(&self) -> &'static str {
+        match self {
+            Assertion::Equals => "equals",
+            Assertion::Matches => "matches",
+            Assertion::GreaterThan => "greater_than",
+            Assertion::LessThan => "less_than",
+        }
+    }
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
         Self {
             symbol_table: SymbolTable::new(),
-            current_scope: "global".to_string(),
+            scopes: Vec::new(),
+            anonymous_scopes: 0,
+            defining: Vec::new(),
+            warnings: Vec::new(),
             endpoints: Vec::new(),
             tests: Vec::new(),
+            functions: Vec::new(),
+            function_names: HashSet::new(),
+            enclosing: Vec::new(),
+            calling: Vec::new(),
+            modules: Vec::new(),
+            imports: HashMap::new(),
+            match_bindings: HashMap::new(),
         }
     }
 
     pub fn analyze(&mut self, ast: &AstNode) -> Result<AnalyzedProgram, AnalyzerError> {
         // First pass: collect all symbols and their dependencies
-        self.collect_symbols(ast)?;
+        self.register_functions(ast, None);
+        self.collect_symbols(ast, None)?;
+        self.finish_analysis()
+    }
+
+    /// Analyze every file of a project as one program. Each imported file's
+    /// globals are named after its path, such as `shared/helpers.rate`.
+    pub fn analyze_project(&mut self, project: &Project) -> Result<AnalyzedProgram, AnalyzerError> {
+        self.modules = project.modules.iter()
+            .map(|module| (module.path.display().to_string(), module.prefix.clone()))
+            .collect();
         
+        // Functions are registered up front so calls across files are known
+        for module in &project.modules {
+            self.register_functions(&module.ast, module.prefix.as_deref());
+        }
+        for module in &project.modules {
+            self.imports = module.imports.clone();
+            self.collect_symbols(&module.ast, module.prefix.as_deref())?;
+        }
+        self.imports.clear();
+        
+        self.finish_analysis()
+    }
+
+    fn finish_analysis(&mut self) -> Result<AnalyzedProgram, AnalyzerError> {
         // Resolve dependencies and determine evaluation order
-        let resolution_order = self.symbol_table.resolve_dependencies()?;
+        let resolution_order = self.symbol_table.resolve_dependencies()
+            .map_err(|error| self.locate_cycle(error))?;
         
         // Second pass: type inference and promise propagation
         self.infer_types(&resolution_order)?;
+        self.mark_deferred_symbols();
+        for index in 0..self.functions.len() {
+            let table = std::mem::take(&mut self.functions[index].symbol_table);
+            let ((), table) = self.in_function_scope(table, Self::mark_deferred_symbols);
+            self.functions[index].symbol_table = table;
+        }
         
         // Third pass: validate the program
         self.validate_program()?;
@@ -201,196 +379,877 @@ impl SemanticAnalyzer {
             resolution_order,
             endpoints: self.endpoints.clone(),
             tests: self.tests.clone(),
+            functions: self.functions.clone(),
         })
     }
 
-    fn collect_symbols(&mut self, node: &AstNode) -> Result<(), AnalyzerError> {
+    fn register_functions(&mut self, node: &AstNode, prefix: Option<&str>) {
+        for statement in Self::statements(node) {
+            if let AstNode::Function { name, .. } = Self::declaration(statement) {
+                self.function_names.insert(Self::qualify(prefix, name));
+            }
+        }
+    }
+
+    fn statements(node: &AstNode) -> &[AstNode] {
         match node {
-            AstNode::Program(statements) => {
-                for statement in statements {
-                    self.collect_symbols(statement)?;
-                }
+            AstNode::Program(statements) => statements.as_slice(),
+            other => std::slice::from_ref(other),
+        }
+    }
+
+    /// A statement without the annotations written before it
+    fn declaration(statement: &AstNode) -> &AstNode {
+        match statement {
+            AstNode::Annotated { declaration, .. } => declaration,
+            other => other,
+        }
+    }
+
+    fn qualify(prefix: Option<&str>, name: &str) -> String {
+        match prefix {
+            Some(prefix) => format!("{}.{}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// The qualified name of a global of the file being collected
+    fn global_name(&self, name: &str) -> String {
+        Self::qualify(self.scopes.first().and_then(|scope| scope.prefix.as_deref()), name)
+    }
+
+    /// A cycle through symbols of several files is reported with the files
+    fn locate_cycle(&self, error: AnalyzerError) -> AnalyzerError {
+        let AnalyzerError::CircularDependency(unresolved) = error else { return error };
+        
+        // Drop symbols that only wait on the cycle, nothing in the cycle needs them
+        let mut symbols: HashSet<String> = unresolved.iter().cloned().collect();
+        loop {
+            let needed: HashSet<String> = symbols.iter()
+                .filter_map(|name| self.symbol_table.get_symbol(name))
+                .flat_map(|symbol| symbol.dependencies.iter().cloned())
+                .collect();
+            let before = symbols.len();
+            symbols.retain(|name| needed.contains(name));
+            if symbols.len() == before {
+                break;
             }
-            
-            AstNode::Assignment { name, value, line } => {
-                // Create symbol for this assignment
-                let symbol_type = self.infer_type_from_ast(value)?;
-                let mut symbol = Symbol::new(name.clone(), symbol_type, *line);
-                symbol.ast_node = Some(value.as_ref().clone());
-                
-                // Collect dependencies from the value expression
-                let dependencies = self.collect_dependencies(value)?;
-                for dep in &dependencies {
-                    symbol.dependencies.insert(dep.clone());
+        }
+        
+        let mut files: Vec<String> = symbols.iter()
+            .filter_map(|name| self.module_of(name))
+            .collect();
+        files.sort();
+        files.dedup();
+        if files.len() < 2 {
+            return AnalyzerError::CircularDependency(unresolved);
+        }
+        let mut symbols: Vec<String> = symbols.into_iter().collect();
+        symbols.sort();
+        AnalyzerError::CircularImport { files, symbols }
+    }
+
+    /// The path of the file declaring a symbol, for a project
+    fn module_of(&self, name: &str) -> Option<String> {
+        self.modules.iter()
+            .find(|(_, prefix)| prefix.as_ref().is_some_and(|prefix| name.starts_with(&format!("{}.", prefix))))
+            .or_else(|| self.modules.first())
+            .map(|(path, _)| path.clone())
+    }
+
+    fn collect_symbols(&mut self, node: &AstNode, prefix: Option<&str>) -> Result<(), AnalyzerError> {
+        let statements = Self::statements(node);
+        
+        // Every global name is visible from every scope, including forward references
+        let mut global_names = HashSet::new();
+        for statement in statements {
+            match Self::declaration(statement) {
+                AstNode::Assignment { name, .. } => {
+                    global_names.insert(name.clone());
                 }
-                
-                self.symbol_table.add_symbol(symbol);
-                
-                // Establish bidirectional dependencies in the symbol table
-                for dep in dependencies {
-                    self.symbol_table.add_dependency(name, &dep);
+                AstNode::Destructure { names, .. } => {
+                    global_names.extend(names.iter().cloned());
+                }
+                AstNode::Function { name, .. } => {
+                    global_names.insert(name.clone());
                 }
+                // A root object literal declares its fields as globals
+                AstNode::Object { fields, .. } => {
+                    global_names.extend(fields.iter().filter_map(|(key, _)| key.name()).map(str::to_string));
+                }
+                _ => {}
+            }
+        }
+        self.scopes.push(Scope { prefix: prefix.map(str::to_string), names: global_names });
+        
+        for statement in statements {
+            self.collect_statement(statement)?;
+        }
+        
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn collect_statement(&mut self, node: &AstNode) -> Result<(), AnalyzerError> {
+        match node {
+            AstNode::Assignment { name, value, span } => {
+                self.define_symbol(self.global_name(name), value, *span)?;
             }
             
-            AstNode::Endpoint { name, method, path, handler, line } => {
-                // Register the endpoint
-                self.endpoints.push(EndpointInfo {
-                    name: name.clone(),
-                    method: method.clone(),
-                    path: path.clone(),
-                    handler: handler.as_ref().clone(),
-                    line: *line,
-                });
-                
-                // Analyze the handler
-                self.collect_symbols(handler)?;
+            AstNode::Destructure { names, value, span } => {
+                self.define_destructure(names, &[], value, *span)?;
             }
             
-            AstNode::Test { name, expect_expression, inputs, expected_output, is_regex, line } => {
-                // Register the test
-                self.tests.push(TestInfo {
-                    name: name.clone(),
-                    expect_expression: expect_expression.as_ref().clone(),
-                    inputs: inputs.clone(),
-                    expected_output: expected_output.as_ref().clone(),
-                    is_regex: *is_regex,
-                    line: *line,
-                });
-                
-                // Analyze the expect expression, inputs and expected output
-                self.collect_symbols(expect_expression)?;
-                for (_, input_value) in inputs {
-                    self.collect_symbols(input_value)?;
+            // The project loader resolved the path, only the namespace is left
+            AstNode::Import { namespace, path, span } => {
+                if !self.imports.contains_key(namespace) {
+                    return Err(AnalyzerError::UnresolvedImport { path: path.clone(), span: *span });
                 }
-                self.collect_symbols(expected_output)?;
             }
             
-            AstNode::Object { fields, line } => {
-                for (field_name, field_value) in fields {
-                    // Treat each field as a symbol definition
-                    let symbol_type = self.infer_type_from_ast(field_value)?;
-                    let mut symbol = Symbol::new(field_name.clone(), symbol_type, *line);
-                    symbol.ast_node = Some(field_value.clone());
-                    
-                    // Collect dependencies from the field value expression
-                    let dependencies = self.collect_dependencies(field_value)?;
-                    for dep in &dependencies {
-                        symbol.dependencies.insert(dep.clone());
-                    }
-                    
-                    self.symbol_table.add_symbol(symbol);
-                    
-                    // Establish bidirectional dependencies in the symbol table
-                    for dep in dependencies {
-                        self.symbol_table.add_dependency(field_name, &dep);
-                    }
-                    
-                    // Also analyze the field value for nested structures
-                    self.collect_symbols(field_value)?;
-                }
+            AstNode::Function { name, parameters, body, span } => {
+                self.define_function(&self.global_name(name), parameters, body, *span)?;
             }
             
-            AstNode::Array { elements, .. } => {
-                for element in elements {
-                    self.collect_symbols(element)?;
+            AstNode::Object { fields, span } => {
+                for (key, field_value) in fields {
+                    // Globals need names, so a top-level object cannot have complex keys
+                    let ObjectKey::Name(field_name) = key else {
+                        return Err(AnalyzerError::UnnamedGlobal { span: *span });
+                    };
+                    self.define_symbol(self.global_name(field_name), field_value, field_value.span())?;
                 }
             }
             
-            AstNode::Binary { left, right, .. } => {
-                self.collect_symbols(left)?;
-                self.collect_symbols(right)?;
-            }
+            // The annotations apply to the declared value, as on an object field
+            AstNode::Annotated { annotations, declaration, .. } => match declaration.as_ref() {
+                AstNode::Assignment { name, value, span } => {
+                    self.define_annotated(self.global_name(name), annotations, value, *span)?;
+                }
+                AstNode::Destructure { names, value, span } => {
+                    self.define_destructure(names, annotations, value, *span)?;
+                }
+                AstNode::Function { name, parameters, body, span } => {
+                    let name = self.global_name(name);
+                    self.define_function(&name, parameters, body, *span)?;
+                    self.annotate_function(&name, parameters.len(), annotations, *span)?;
+                }
+                _ => {
+                    let annotation = &annotations[0];
+                    return Err(Self::invalid_annotation(annotation, "only a declaration can be annotated", annotation.span));
+                }
+            },
             
-            AstNode::Unary { operand, .. } => {
-                self.collect_symbols(operand)?;
+            other => {
+                let scope = self.anonymous_scope("");
+                self.lower_expression(other, &scope)?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Register a symbol under its qualified name, with every name in its
+    /// value resolved against the enclosing scopes
+    fn define_symbol(&mut self, qualified_name: String, value: &AstNode, span: Span) -> Result<(), AnalyzerError> {
+        if let AstNode::Annotated { annotations, declaration, .. } = value {
+            return self.define_annotated(qualified_name, annotations, declaration, declaration.span());
+        }
+        
+        // A dotted field such as `"a.b"` has the same name as `b` nested in `a`
+        if self.symbol_table.get_symbol(&qualified_name).is_some() {
+            return Err(AnalyzerError::DuplicateSymbol { name: qualified_name, span });
+        }
+        
+        self.defining.push(qualified_name.clone());
+        let lowered = self.lower_expression(value, &qualified_name);
+        self.defining.pop();
+        let lowered = lowered?;
+        
+        // The type is inferred once the dependencies have been ordered
+        let mut symbol = Symbol::new(qualified_name.clone(), Type::Any, span);
+        let dependencies = self.collect_dependencies(&lowered, true)?;
+        let eager_dependencies = self.collect_dependencies(&lowered, false)?;
+        symbol.deferred_dependencies = dependencies.difference(&eager_dependencies).cloned().collect();
+        symbol.dependencies = dependencies.clone();
+        symbol.ast_node = Some(lowered);
+        
+        self.symbol_table.add_symbol(symbol);
+        for dep in dependencies {
+            self.symbol_table.add_dependency(&qualified_name, &dep);
+        }
+        
+        Ok(())
+    }
+
+    /// Define a declared value and run the processor of each annotation on
+    /// it. `@http` makes the value a request before it is defined; the others
+    /// record what they need once the symbol exists.
+    fn define_annotated(&mut self, name: String, annotations: &[Annotation], value: &AstNode, span: Span) -> Result<(), AnalyzerError> {
+        let mut value = value.clone();
+        for annotation in annotations.iter().filter(|annotation| annotation.name == "http") {
+            value = Self::http_request(annotation, value)?;
+        }
+        self.define_symbol(name.clone(), &value, span)?;
+        
+        for annotation in annotations {
+            match annotation.name.as_str() {
+                "http" => {}
+                "endpoint" => self.annotate_endpoint(annotation, &name, AstNode::Identifier(name.clone(), span))?,
+                "test" => self.annotate_test(annotation, &name, span)?,
+                "cache" => self.annotate_cache(annotation, &name)?,
+                _ => return Err(AnalyzerError::UnknownAnnotation { name: annotation.name.clone(), span: annotation.span }),
+            }
+        }
+        Ok(())
+    }
+
+    /// The tuple is a symbol of its own, and each name reads one element of
+    /// it. Annotations apply to the tuple.
+    fn define_destructure(&mut self, names: &[String], annotations: &[Annotation], value: &AstNode, span: Span) -> Result<(), AnalyzerError> {
+        let source = self.global_name(&format!("({})", names.join(", ")));
+        self.define_annotated(source.clone(), annotations, value, span)?;
+        for (position, name) in names.iter().enumerate() {
+            let element = AstNode::Index {
+                object: Box::new(AstNode::Identifier(source.clone(), span)),
+                index: Box::new(AstNode::Number(BigDecimal::from(position as u64), span)),
+                span,
+            };
+            self.define_symbol(self.global_name(name), &element, span)?;
+        }
+        Ok(())
+    }
+
+    /// Run the processors that apply to a function. An endpoint calls it with
+    /// the request, and a test calls it from its `expect` expression; `@http`
+    /// and `@cache` need a value and are rejected.
+    fn annotate_function(&mut self, name: &str, parameter_count: usize, annotations: &[Annotation], span: Span) -> Result<(), AnalyzerError> {
+        for annotation in annotations {
+            match annotation.name.as_str() {
+                "endpoint" if parameter_count != 1 => {
+                    return Err(Self::invalid_annotation(annotation, "an endpoint function takes one parameter, the request", span));
+                }
+                "endpoint" => {
+                    let handler = AstNode::FunctionCall {
+                        name: name.to_string(),
+                        arguments: vec![AstNode::Identifier("request".to_string(), span)],
+                        span,
+                    };
+                    self.annotate_endpoint(annotation, name, handler)?;
+                }
+                "test" if annotation.field("expect").is_none() => {
+                    return Err(Self::invalid_annotation(annotation, "a function's test needs an `expect` expression that calls it", annotation.span));
+                }
+                "test" => self.annotate_test(annotation, name, span)?,
+                "http" | "cache" => {
+                    return Err(Self::invalid_annotation(annotation, "only a `name: value` declaration can have this annotation, not a function", annotation.span));
+                }
+                _ => return Err(AnalyzerError::UnknownAnnotation { name: annotation.name.clone(), span: annotation.span }),
+            }
+        }
+        Ok(())
+    }
+
+    /// `@endpoint(METHOD, "/path")` answers requests with the handler's value
+    fn annotate_endpoint(&mut self, annotation: &Annotation, name: &str, handler: AstNode) -> Result<(), AnalyzerError> {
+        let method = match annotation.argument(0, "method") {
+            Some(method) => Self::http_method(annotation, method)?,
+            None => return Err(Self::invalid_annotation(annotation, "expected a method and a path, such as @endpoint(GET, \"/status\")", annotation.span)),
+        };
+        let path = match annotation.argument(1, "path") {
+            Some(AstNode::String(path, _)) => path.clone(),
+            other => return Err(Self::invalid_annotation(annotation, "expected the path as a string", other.map_or(annotation.span, AstNode::span))),
+        };
+        
+        self.endpoints.push(EndpointInfo {
+            name: name.to_string(),
+            method,
+            path,
+            handler,
+            span: annotation.span,
+        });
+        Ok(())
+    }
+
+    /// `@test("description") { equals: value }` checks the declared value, or
+    /// the `expect` expression when there is one
+    fn annotate_test(&mut self, annotation: &Annotation, name: &str, span: Span) -> Result<(), AnalyzerError> {
+        let test_name = match annotation.argument(0, "name") {
+            Some(AstNode::String(test_name, _)) => test_name.clone(),
+            Some(other) => return Err(Self::invalid_annotation(annotation, "expected the test's name as a string", other.span())),
+            None => name.to_string(),
+        };
+        let scope = format!("@test:{}", test_name);
+        let expect_expression = match annotation.field("expect") {
+            Some(expression) => self.lower_expression(expression, &scope)?,
+            None => AstNode::Identifier(name.to_string(), span),
+        };
+        
+        let assertion = Assertion::ALL.into_iter()
+            .find_map(|assertion| annotation.field(assertion.field()).map(|expected| (assertion, expected)));
+        let Some((assertion, expected)) = assertion else {
+            return Err(Self::invalid_annotation(annotation, "expected one of equals, matches, greater_than or less_than", annotation.span));
+        };
+        let expected_output = self.lower_expression(expected, &scope)?;
+        
+        self.tests.push(TestInfo {
+            name: test_name,
+            expect_expression,
+            inputs: HashMap::new(),
+            expected_output,
+            assertion,
+            span: annotation.span,
+        });
+        Ok(())
+    }
+
+    /// `@cache(seconds)` lets later runs reuse the declared value for a while
+    fn annotate_cache(&mut self, annotation: &Annotation, name: &str) -> Result<(), AnalyzerError> {
+        let seconds = match annotation.argument(0, "ttl") {
+            Some(AstNode::Number(seconds, _)) if seconds.is_integer() => seconds.to_u64().filter(|seconds| *seconds > 0),
+            _ => None,
+        };
+        let Some(seconds) = seconds else {
+            return Err(Self::invalid_annotation(annotation, "expected a whole number of seconds, such as @cache(60)", annotation.span));
+        };
+        
+        if let Some(symbol) = self.symbol_table.get_symbol_mut(name) {
+            symbol.cache_seconds = Some(seconds);
+        }
+        Ok(())
+    }
+
+    /// `@http(METHOD) { body, headers }` sends a request to the declared url,
+    /// and the response becomes the value
+    fn http_request(annotation: &Annotation, url: AstNode) -> Result<AstNode, AnalyzerError> {
+        let method = match annotation.argument(0, "method") {
+            Some(method) => Self::http_method(annotation, method)?,
+            None => HttpMethod::Get,
+        };
+        let headers = match annotation.field("headers") {
+            Some(AstNode::Object { fields, .. }) if fields.iter().all(|(key, _)| key.name().is_some()) => Some(fields.iter()
+                .filter_map(|(key, value)| Some((key.name()?.to_string(), value.clone())))
+                .collect()),
+            Some(other) => return Err(Self::invalid_annotation(annotation, "expected headers as an object with named fields", other.span())),
+            None => None,
+        };
+        
+        Ok(AstNode::HttpCall {
+            span: url.span(),
+            url: Box::new(url),
+            method,
+            body: annotation.field("body").cloned().map(Box::new),
+            headers,
+        })
+    }
+
+    /// A method written as `GET` or `"GET"`
+    fn http_method(annotation: &Annotation, node: &AstNode) -> Result<HttpMethod, AnalyzerError> {
+        let method = match node {
+            AstNode::Identifier(name, _) | AstNode::String(name, _) => HttpMethod::from_name(name),
+            _ => None,
+        };
+        method.ok_or_else(|| Self::invalid_annotation(annotation, "expected GET, POST, PUT, DELETE or PATCH", node.span()))
+    }
+
+    fn invalid_annotation(annotation: &Annotation, message: &str, span: Span) -> AnalyzerError {
+        AnalyzerError::InvalidAnnotation { name: annotation.name.clone(), message: message.to_string(), span }
+    }
+
+    /// Lower a function body into a symbol table of its own. The function is
+    /// also a global symbol depending on every outside name its body reads,
+    /// so calls are ordered after those names.
+    fn define_function(&mut self, name: &str, parameters: &[String], body: &AstNode, span: Span) -> Result<(), AnalyzerError> {
+        let mut table = SymbolTable::new();
+        let qualified_parameters: Vec<String> = parameters.iter()
+            .map(|parameter| format!("{}.{}", name, parameter))
+            .collect();
+        for parameter in &qualified_parameters {
+            table.add_symbol(Symbol::new(parameter.clone(), Type::Any, span));
+        }
+        
+        let result = format!("{}()", name);
+        self.scopes.push(Scope {
+            prefix: Some(name.to_string()),
+            names: parameters.iter().cloned().collect(),
+        });
+        let (defined, mut table) = self.in_function_scope(table, |analyzer| analyzer.define_symbol(result.clone(), body, span));
+        self.scopes.pop();
+        defined?;
+        let resolution_order = table.resolve_local_dependencies()?;
+        
+        // A cached value outlives the call, so it cannot read the arguments
+        for symbol in table.symbols().values().filter(|symbol| symbol.cache_seconds.is_some()) {
+            if Self::reads_any(&table, &symbol.name, &qualified_parameters) {
+                return Err(AnalyzerError::InvalidAnnotation {
+                    name: "cache".to_string(),
+                    message: format!("`{}` reads the function's parameters, so it cannot be cached", symbol.name),
+                    span: symbol.definition_span,
+                });
+            }
+        }
+        
+        // A recursive call is not a dependency on the function itself
+        let mut symbol = Symbol::new(name.to_string(), Type::Function(parameters.len()), span);
+        symbol.dependencies = table.symbols().values()
+            .flat_map(|local| local.dependencies.iter())
+            .filter(|dependency| table.get_symbol(dependency).is_none() && *dependency != name)
+            .cloned()
+            .collect();
+        self.symbol_table.add_symbol(symbol);
+        
+        self.functions.push(FunctionInfo {
+            name: name.to_string(),
+            parameters: qualified_parameters,
+            symbol_table: table,
+            resolution_order,
+            result,
+            span,
+        });
+        Ok(())
+    }
+
+    /// Whether `name` depends on any of `targets`, directly or through other symbols of `table`
+    fn reads_any(table: &SymbolTable, name: &str, targets: &[String]) -> bool {
+        let mut seen = HashSet::from([name]);
+        let mut pending = vec![name];
+        while let Some(current) = pending.pop() {
+            let Some(symbol) = table.get_symbol(current) else { continue };
+            for dependency in &symbol.dependencies {
+                if targets.contains(dependency) {
+                    return true;
+                }
+                if seen.insert(dependency.as_str()) {
+                    pending.push(dependency);
+                }
             }
+        }
+        false
+    }
+
+    /// Run `f` with a function's own symbol table in place of the globals,
+    /// which stay visible for lookups; returns the function's table after
+    fn in_function_scope<T>(&mut self, table: SymbolTable, f: impl FnOnce(&mut Self) -> T) -> (T, SymbolTable) {
+        let globals = std::mem::replace(&mut self.symbol_table, table);
+        self.enclosing.push(globals);
+        let result = f(self);
+        let globals = self.enclosing.pop().expect("enclosing symbol table");
+        (result, std::mem::replace(&mut self.symbol_table, globals))
+    }
+
+    fn lookup_symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbol_table.get_symbol(name)
+            .or_else(|| self.enclosing.iter().rev().find_map(|table| table.get_symbol(name)))
+    }
+
+    /// Infer a function's result for one set of argument types. Each call
+    /// site gets its own inference, so `first(["a"])` is a string while
+    /// `first([1])` is a number.
+    fn infer_call(&mut self, name: &str, argument_types: &[Type], span: Span) -> Result<(Type, SymbolTable), AnalyzerError> {
+        let Some(function) = self.functions.iter().find(|function| function.name == name).cloned() else {
+            return Ok((Type::Promise(Some(Box::new(Type::Any))), SymbolTable::new()));
+        };
+        if argument_types.len() != function.parameters.len() {
+            return Err(AnalyzerError::FunctionArgumentCount {
+                function: name.to_string(),
+                expected: function.parameters.len(),
+                found: argument_types.len(),
+                span,
+            });
+        }
+        // The type of a recursive call is not known until the call returns
+        if self.calling.iter().any(|calling| calling == name) {
+            return Ok((Type::Any, function.symbol_table));
+        }
+        
+        // Arguments are awaited before the call, so parameters hold settled values
+        let mut table = function.symbol_table.clone();
+        for (parameter, argument_type) in function.parameters.iter().zip(argument_types) {
+            if let Some(symbol) = table.get_symbol_mut(parameter) {
+                symbol.symbol_type = Self::settled_type(argument_type);
+            }
+        }
+        
+        self.calling.push(name.to_string());
+        let (inferred, table) = self.in_function_scope(table, |analyzer| analyzer.infer_types(&function.resolution_order));
+        self.calling.pop();
+        inferred?;
+        
+        let result_type = table.get_symbol(&function.result)
+            .map(|symbol| symbol.symbol_type.clone())
+            .unwrap_or(Type::Any);
+        Ok((result_type, table))
+    }
+
+    /// Rewrite an expression so identifiers refer to qualified symbol names.
+    /// Each object literal opens a scope named `path`, whose fields become
+    /// symbols that the rewritten object refers to.
+    fn lower_expression(&mut self, node: &AstNode, path: &str) -> Result<AstNode, AnalyzerError> {
+        let lowered = match node {
+            AstNode::Identifier(name, span) => AstNode::Identifier(self.resolve_name(name), *span),
             
-            AstNode::MemberAccess { object, .. } => {
-                self.collect_symbols(object)?;
+            AstNode::Object { fields, span } => {
+                self.scopes.push(Scope {
+                    prefix: Some(path.to_string()),
+                    names: fields.iter().filter_map(|(key, _)| key.name()).map(str::to_string).collect(),
+                });
+                let lowered = self.lower_object(fields, path, *span);
+                self.scopes.pop();
+                lowered?
             }
             
-            AstNode::FunctionCall { arguments, .. } => {
-                for arg in arguments {
-                    self.collect_symbols(arg)?;
+            AstNode::Array { elements, span } => {
+                let mut lowered_elements = Vec::new();
+                for (index, element) in elements.iter().enumerate() {
+                    lowered_elements.push(self.lower_expression(element, &format!("{}[{}]", path, index))?);
                 }
+                AstNode::Array { elements: lowered_elements, span: *span }
             }
             
-            AstNode::Promise { expression, .. } => {
-                self.collect_symbols(expression)?;
+            AstNode::Tuple { elements, span } => {
+                let mut lowered_elements = Vec::new();
+                for (index, element) in elements.iter().enumerate() {
+                    lowered_elements.push(self.lower_expression(element, &format!("{}[{}]", path, index))?);
+                }
+                AstNode::Tuple { elements: lowered_elements, span: *span }
             }
             
-            AstNode::HttpCall { url, body, headers, .. } => {
-                self.collect_symbols(url)?;
-                if let Some(body) = body {
-                    self.collect_symbols(body)?;
+            AstNode::Binary { left, operator, right, span } => AstNode::Binary {
+                left: Box::new(self.lower_operand(left, path)?),
+                operator: operator.clone(),
+                // The fallback of `??` is only evaluated when needed, like a branch
+                right: Box::new(if *operator == BinaryOp::Coalesce {
+                    self.lower_branch(right, path)?
+                } else {
+                    self.lower_operand(right, path)?
+                }),
+                span: *span,
+            },
+            
+            AstNode::Unary { operator, operand, span } => AstNode::Unary {
+                operator: operator.clone(),
+                operand: Box::new(self.lower_operand(operand, path)?),
+                span: *span,
+            },
+            
+            AstNode::MemberAccess { object, property, null_safe, span } => match self.imported_name(object, property) {
+                Some(name) => AstNode::Identifier(name, *span),
+                None => AstNode::MemberAccess {
+                    object: Box::new(self.lower_operand(object, path)?),
+                    property: property.clone(),
+                    null_safe: *null_safe,
+                    span: *span,
+                },
+            },
+            
+            AstNode::Index { object, index, span } => AstNode::Index {
+                object: Box::new(self.lower_operand(object, path)?),
+                index: Box::new(self.lower_operand(index, path)?),
+                span: *span,
+            },
+            
+            AstNode::Conditional { condition, then_branch, else_branch, span } => AstNode::Conditional {
+                condition: Box::new(self.lower_operand(condition, path)?),
+                then_branch: Box::new(self.lower_branch(then_branch, path)?),
+                else_branch: Box::new(self.lower_branch(else_branch, path)?),
+                span: *span,
+            },
+            
+            AstNode::Match { subject, arms, span } => self.lower_match(subject, arms, path, *span)?,
+            
+            AstNode::MethodCall { object, method, arguments, span } => match self.imported_name(object, method) {
+                // `namespace.function(...)` calls a function of an imported file
+                Some(name) => AstNode::FunctionCall {
+                    name,
+                    arguments: self.lower_operands(arguments, path)?,
+                    span: *span,
+                },
+                None => AstNode::MethodCall {
+                    object: Box::new(self.lower_operand(object, path)?),
+                    method: method.clone(),
+                    arguments: self.lower_operands(arguments, path)?,
+                    span: *span,
+                },
+            },
+            
+            AstNode::Template { parts, span } => {
+                let mut lowered_parts = Vec::new();
+                for part in parts {
+                    lowered_parts.push(match part {
+                        TemplatePart::Text(text) => TemplatePart::Text(text.clone()),
+                        TemplatePart::Expression(expression) => {
+                            TemplatePart::Expression(self.lower_operand(expression, path)?)
+                        }
+                    });
                 }
-                if let Some(headers) = headers {
-                    for value in headers.values() {
-                        self.collect_symbols(value)?;
+                AstNode::Template { parts: lowered_parts, span: *span }
+            }
+            
+            AstNode::FunctionCall { name, arguments, span } => AstNode::FunctionCall {
+                // A declared function is a global of its file, other names are external
+                name: Some(self.resolve_name(name))
+                    .filter(|qualified| self.function_names.contains(qualified))
+                    .unwrap_or_else(|| name.clone()),
+                arguments: self.lower_operands(arguments, path)?,
+                span: *span,
+            },
+            
+            AstNode::Promise { expression, span } => AstNode::Promise {
+                expression: Box::new(self.lower_operand(expression, path)?),
+                span: *span,
+            },
+            
+            AstNode::HttpCall { url, method, body, headers, span } => {
+                let url = self.lower_operand(url, path)?;
+                let body = match body {
+                    Some(body) => Some(Box::new(self.lower_operand(body, path)?)),
+                    None => None,
+                };
+                let headers = match headers {
+                    Some(headers) => {
+                        let mut lowered_headers = IndexMap::new();
+                        for (key, value) in headers {
+                            lowered_headers.insert(key.clone(), self.lower_operand(value, path)?);
+                        }
+                        Some(lowered_headers)
                     }
-                }
+                    None => None,
+                };
+                AstNode::HttpCall { url: Box::new(url), method: method.clone(), body, headers, span: *span }
             }
             
-            // Leaf nodes don't need collection
-            _ => {}
+            other => other.clone(),
+        };
+        
+        Ok(lowered)
+    }
+
+    /// Named fields become symbols; complex keys and their values are
+    /// evaluated in the object's scope like any other operand
+    fn lower_object(&mut self, fields: &[(ObjectKey, AstNode)], path: &str, span: Span) -> Result<AstNode, AnalyzerError> {
+        let mut lowered_fields = Vec::new();
+        for (key, value) in fields {
+            lowered_fields.push(match key {
+                ObjectKey::Name(field_name) => {
+                    let qualified_name = format!("{}.{}", path, field_name);
+                    self.define_symbol(qualified_name.clone(), value, value.span())?;
+                    (key.clone(), AstNode::Identifier(qualified_name, value.span()))
+                }
+                ObjectKey::Computed(key) => {
+                    (ObjectKey::Computed(self.lower_operand(key, path)?), self.lower_operand(value, path)?)
+                }
+            });
         }
         
-        Ok(())
+        Ok(AstNode::Object { fields: lowered_fields, span })
+    }
+
+    /// Lower a sub-expression; object literals here get an anonymous scope
+    fn lower_operand(&mut self, node: &AstNode, path: &str) -> Result<AstNode, AnalyzerError> {
+        let scope = self.anonymous_scope(path);
+        self.lower_expression(node, &scope)
+    }
+
+    /// Each conditional branch becomes its own symbol, so the branch and
+    /// everything only it needs can be evaluated after the condition
+    fn lower_branch(&mut self, node: &AstNode, path: &str) -> Result<AstNode, AnalyzerError> {
+        let name = self.anonymous_scope(path);
+        self.define_symbol(name.clone(), node, node.span())?;
+        Ok(AstNode::Identifier(name, node.span()))
+    }
+
+    /// The subject becomes a symbol evaluated before any arm, and each arm a
+    /// branch in a scope of its own. A binding is a symbol without a value,
+    /// set when its arm is chosen.
+    fn lower_match(&mut self, subject: &AstNode, arms: &[MatchArm], path: &str, span: Span) -> Result<AstNode, AnalyzerError> {
+        let subject_name = self.anonymous_scope(path);
+        self.define_symbol(subject_name.clone(), subject, subject.span())?;
+        
+        let mut lowered_arms = Vec::new();
+        for arm in arms {
+            let scope = self.anonymous_scope(path);
+            let pattern = self.lower_pattern(&arm.pattern, &scope, &subject_name, Vec::new());
+            self.scopes.push(Scope {
+                prefix: Some(scope.clone()),
+                names: arm.pattern.bindings().into_iter().map(|(name, _)| name.to_string()).collect(),
+            });
+            let value = self.lower_branch(&arm.value, &scope);
+            self.scopes.pop();
+            lowered_arms.push(MatchArm { pattern, value: value?, span: arm.span });
+        }
+        
+        Ok(AstNode::Match {
+            subject: Box::new(AstNode::Identifier(subject_name, subject.span())),
+            arms: lowered_arms,
+            span,
+        })
+    }
+
+    /// Define the pattern's bindings in `scope` and return the pattern with
+    /// their qualified names
+    fn lower_pattern(&mut self, pattern: &Pattern, scope: &str, subject: &str, steps: Vec<PatternStep>) -> Pattern {
+        let extend = |step: PatternStep| steps.iter().cloned().chain(std::iter::once(step)).collect::<Vec<_>>();
+        match pattern {
+            Pattern::Binding(name, span) => {
+                let qualified_name = format!("{}.{}", scope, name);
+                self.symbol_table.add_symbol(Symbol::new(qualified_name.clone(), Type::Any, *span));
+                self.symbol_table.add_dependency(&qualified_name, subject);
+                self.match_bindings.insert(qualified_name.clone(), (subject.to_string(), steps));
+                Pattern::Binding(qualified_name, *span)
+            }
+            Pattern::Array { elements, rest } => Pattern::Array {
+                elements: elements.iter().enumerate()
+                    .map(|(index, element)| self.lower_pattern(element, scope, subject, extend(PatternStep::Element(index))))
+                    .collect(),
+                rest: rest.as_ref()
+                    .map(|rest| Box::new(self.lower_pattern(rest, scope, subject, extend(PatternStep::Rest(elements.len()))))),
+            },
+            Pattern::Object(fields) => Pattern::Object(fields.iter()
+                .map(|(field, value)| (field.clone(), self.lower_pattern(value, scope, subject, extend(PatternStep::Field(field.clone())))))
+                .collect()),
+            other => other.clone(),
+        }
     }
 
-    fn collect_dependencies(&self, node: &AstNode) -> Result<HashSet<String>, AnalyzerError> {
+    fn lower_operands(&mut self, nodes: &[AstNode], path: &str) -> Result<Vec<AstNode>, AnalyzerError> {
+        nodes.iter().map(|node| self.lower_operand(node, path)).collect()
+    }
+
+    fn anonymous_scope(&mut self, path: &str) -> String {
+        self.anonymous_scopes += 1;
+        format!("{}#{}", path, self.anonymous_scopes)
+    }
+
+    /// The global `name` of an imported file, when `object` is an import
+    /// namespace; a declared name shadows a namespace
+    fn imported_name(&self, object: &AstNode, name: &str) -> Option<String> {
+        let AstNode::Identifier(namespace, _) = object else { return None };
+        let module = *self.imports.get(namespace)?;
+        if self.scopes.iter().any(|scope| scope.names.contains(namespace)) {
+            return None;
+        }
+        let (_, prefix) = self.modules.get(module)?;
+        Some(Self::qualify(prefix.as_deref(), name))
+    }
+
+    /// Find the innermost scope declaring `name`; unknown names are left as is
+    /// so validation can report them. A field never sees itself, so
+    /// `{ version: version }` refers to an enclosing `version`.
+    fn resolve_name(&self, name: &str) -> String {
+        for scope in self.scopes.iter().rev() {
+            if scope.names.contains(name) {
+                let qualified_name = match &scope.prefix {
+                    Some(prefix) => format!("{}.{}", prefix, name),
+                    None => name.to_string(),
+                };
+                if !self.defining.contains(&qualified_name) {
+                    return qualified_name;
+                }
+            }
+        }
+        name.to_string()
+    }
+
+    /// Names an expression refers to. Without `include_branches` only the names
+    /// needed before any conditional branch is chosen are collected.
+    fn collect_dependencies(&self, node: &AstNode, include_branches: bool) -> Result<HashSet<String>, AnalyzerError> {
         let mut dependencies = HashSet::new();
         
         match node {
-            AstNode::Identifier(name) => {
+            AstNode::Identifier(name, _) => {
                 dependencies.insert(name.clone());
             }
             
-            AstNode::Binary { left, right, .. } => {
-                dependencies.extend(self.collect_dependencies(left)?);
-                dependencies.extend(self.collect_dependencies(right)?);
+            AstNode::Binary { left, operator, right, .. } => {
+                dependencies.extend(self.collect_dependencies(left, include_branches)?);
+                if include_branches || *operator != BinaryOp::Coalesce {
+                    dependencies.extend(self.collect_dependencies(right, include_branches)?);
+                }
             }
             
             AstNode::Unary { operand, .. } => {
-                dependencies.extend(self.collect_dependencies(operand)?);
+                dependencies.extend(self.collect_dependencies(operand, include_branches)?);
             }
             
             AstNode::MemberAccess { object, .. } => {
-                dependencies.extend(self.collect_dependencies(object)?);
+                dependencies.extend(self.collect_dependencies(object, include_branches)?);
+            }
+            
+            AstNode::Index { object, index, .. } => {
+                dependencies.extend(self.collect_dependencies(object, include_branches)?);
+                dependencies.extend(self.collect_dependencies(index, include_branches)?);
+            }
+            
+            AstNode::Template { parts, .. } => {
+                for part in parts {
+                    if let TemplatePart::Expression(expression) = part {
+                        dependencies.extend(self.collect_dependencies(expression, include_branches)?);
+                    }
+                }
+            }
+            
+            AstNode::Conditional { condition, then_branch, else_branch, .. } => {
+                dependencies.extend(self.collect_dependencies(condition, include_branches)?);
+                if include_branches {
+                    dependencies.extend(self.collect_dependencies(then_branch, include_branches)?);
+                    dependencies.extend(self.collect_dependencies(else_branch, include_branches)?);
+                }
+            }
+            
+            AstNode::Match { subject, arms, .. } => {
+                dependencies.extend(self.collect_dependencies(subject, include_branches)?);
+                if include_branches {
+                    for arm in arms {
+                        dependencies.extend(self.collect_dependencies(&arm.value, include_branches)?);
+                    }
+                }
+            }
+            
+            AstNode::MethodCall { object, arguments, .. } => {
+                dependencies.extend(self.collect_dependencies(object, include_branches)?);
+                for arg in arguments {
+                    dependencies.extend(self.collect_dependencies(arg, include_branches)?);
+                }
             }
             
             AstNode::Object { fields, .. } => {
-                for value in fields.values() {
-                    dependencies.extend(self.collect_dependencies(value)?);
+                for (key, value) in fields {
+                    if let ObjectKey::Computed(key) = key {
+                        dependencies.extend(self.collect_dependencies(key, include_branches)?);
+                    }
+                    dependencies.extend(self.collect_dependencies(value, include_branches)?);
                 }
             }
             
-            AstNode::Array { elements, .. } => {
+            AstNode::Array { elements, .. } | AstNode::Tuple { elements, .. } => {
                 for element in elements {
-                    dependencies.extend(self.collect_dependencies(element)?);
+                    dependencies.extend(self.collect_dependencies(element, include_branches)?);
                 }
             }
             
             AstNode::Promise { expression, .. } => {
-                dependencies.extend(self.collect_dependencies(expression)?);
+                dependencies.extend(self.collect_dependencies(expression, include_branches)?);
             }
             
             AstNode::HttpCall { url, body, headers, .. } => {
-                dependencies.extend(self.collect_dependencies(url)?);
+                dependencies.extend(self.collect_dependencies(url, include_branches)?);
                 if let Some(body) = body {
-                    dependencies.extend(self.collect_dependencies(body)?);
+                    dependencies.extend(self.collect_dependencies(body, include_branches)?);
                 }
                 if let Some(headers) = headers {
                     for value in headers.values() {
-                        dependencies.extend(self.collect_dependencies(value)?);
+                        dependencies.extend(self.collect_dependencies(value, include_branches)?);
                     }
                 }
             }
             
-            AstNode::FunctionCall { arguments, .. } => {
-                // Collect dependencies from function arguments
+            AstNode::FunctionCall { name, arguments, .. } => {
+                // A declared function is a symbol, its body reads names of its own
+                if self.function_names.contains(name) {
+                    dependencies.insert(name.clone());
+                }
                 for arg in arguments {
-                    dependencies.extend(self.collect_dependencies(arg)?);
+                    dependencies.extend(self.collect_dependencies(arg, include_branches)?);
                 }
             }
             
@@ -401,15 +1260,28 @@ impl SemanticAnalyzer {
         Ok(dependencies)
     }
 
-    fn infer_type_from_ast(&self, node: &AstNode) -> Result<Type, AnalyzerError> {
+    fn infer_type_from_ast(&mut self, node: &AstNode) -> Result<Type, AnalyzerError> {
         match node {
-            AstNode::String(_) => Ok(Type::String),
-            AstNode::Number(_) => Ok(Type::Number),
-            AstNode::Boolean(_) => Ok(Type::Boolean),
-            AstNode::Null => Ok(Type::Null),
+            AstNode::String(..) => Ok(Type::String),
+            AstNode::Number(..) => Ok(Type::Number),
+            AstNode::Boolean(..) => Ok(Type::Boolean),
+            AstNode::Null(_) => Ok(Type::Null),
+            AstNode::Regex { .. } => Ok(Type::Regex),
+            
+            AstNode::Template { parts, .. } => {
+                // A template is a string, deferred if any embedded expression is
+                for part in parts {
+                    if let TemplatePart::Expression(expression) = part {
+                        if matches!(self.infer_type_from_ast(expression)?, Type::Promise(_)) {
+                            return Ok(Type::Promise(Some(Box::new(Type::String))));
+                        }
+                    }
+                }
+                Ok(Type::String)
+            }
             
-            AstNode::Identifier(name) => {
-                if let Some(symbol) = self.symbol_table.get_symbol(name) {
+            AstNode::Identifier(name, _) => {
+                if let Some(symbol) = self.lookup_symbol(name) {
                     Ok(symbol.symbol_type.clone())
                 } else {
                     // Forward reference - assume it will be defined later
@@ -417,81 +1289,36 @@ impl SemanticAnalyzer {
                 }
             }
             
-            AstNode::Binary { left, right, operator, line } => {
+            AstNode::Binary { left, right, operator, span } => {
                 let left_type = self.infer_type_from_ast(left)?;
                 let right_type = self.infer_type_from_ast(right)?;
                 
-                match operator {
-                    BinaryOp::Add => {
-                        match (&left_type, &right_type) {
-                            (Type::Number, Type::Number) => Ok(Type::Number),
-                            (Type::String, _) | (_, Type::String) => Ok(Type::String),
-                            (Type::Promise(inner_left), Type::Promise(inner_right)) => {
-                                // Both are promises, result is a promise of the add operation
-                                if let (Some(left), Some(right)) = (inner_left.as_ref(), inner_right.as_ref()) {
-                                    let result_type = self.infer_binary_result_type(left, right, operator)?;
-                                    Ok(Type::Promise(Some(Box::new(result_type))))
-                                } else {
-                                    Ok(Type::Promise(Some(Box::new(Type::Any))))
-                                }
-                            }
-                            (Type::Promise(inner), _) | (_, Type::Promise(inner)) => {
-                                // One operand is a promise, result becomes a promise
-                                let other_type = if matches!(left_type, Type::Promise(_)) {
-                                    &right_type
-                                } else {
-                                    &left_type
-                                };
-                                if let Some(inner_type) = inner.as_ref() {
-                                    let result_type = self.infer_binary_result_type(inner_type, other_type, operator)?;
-                                    Ok(Type::Promise(Some(Box::new(result_type))))
-                                } else {
-                                    Ok(Type::Promise(Some(Box::new(Type::Any))))
-                                }
-                            }
-                            _ => Ok(Type::Any),
-                        }
-                    }
-                    
-                    BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
-                        match (&left_type, &right_type) {
-                            (Type::Number, Type::Number) => Ok(Type::Number),
-                            (Type::Promise(inner_left), Type::Promise(inner_right)) => {
-                                if let (Some(left), Some(right)) = (inner_left.as_ref(), inner_right.as_ref()) {
-                                    let result_type = self.infer_binary_result_type(left, right, operator)?;
-                                    Ok(Type::Promise(Some(Box::new(result_type))))
-                                } else {
-                                    Ok(Type::Promise(Some(Box::new(Type::Number))))
-                                }
-                            }
-                            (Type::Promise(inner), Type::Number) | (Type::Number, Type::Promise(inner)) => {
-                                if let Some(inner_type) = inner.as_ref() {
-                                    if matches!(**inner_type, Type::Number) {
-                                        Ok(Type::Promise(Some(Box::new(Type::Number))))
-                                    } else {
-                                        Err(AnalyzerError::TypeError {
-                                            expected: "number".to_string(),
-                                            found: format!("{:?}", inner_type),
-                                            line: *line,
-                                        })
-                                    }
-                                } else {
-                                    Ok(Type::Promise(Some(Box::new(Type::Number))))
-                                }
-                            }
-                            _ => Err(AnalyzerError::TypeError {
-                                expected: "number".to_string(),
-                                found: format!("{} and {}", left_type, right_type),
-                                line: *line,
-                            }),
-                        }
+                // Operators look through promises and defer the result
+                let deferred = matches!(left_type, Type::Promise(_)) || matches!(right_type, Type::Promise(_));
+                let mut result_type = self.infer_binary_result_type(
+                    &Self::settled_type(&left_type),
+                    &Self::settled_type(&right_type),
+                    operator,
+                    *span,
+                )?;
+                
+                // Removing or keeping literal keys narrows the object's fields
+                if let (Type::Object(fields), Some(keys)) = (&mut result_type, Self::literal_keys(right)) {
+                    match operator {
+                        BinaryOp::Subtract => fields.retain(|key, _| !keys.contains(key)),
+                        BinaryOp::Multiply => fields.retain(|key, _| keys.contains(key)),
+                        _ => {}
                     }
-                    
-                    BinaryOp::Equal => Ok(Type::Boolean),
+                }
+                
+                if deferred {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
                 }
             }
             
-            AstNode::Unary { operand, operator, .. } => {
+            AstNode::Unary { operand, operator, span } => {
                 let operand_type = self.infer_type_from_ast(operand)?;
                 match operator {
                     UnaryOp::Negate => {
@@ -503,7 +1330,21 @@ impl SemanticAnalyzer {
                             _ => Ok(Type::Any),
                         }
                     }
-                    UnaryOp::Not => Ok(Type::Boolean),
+                    UnaryOp::Not => {
+                        let operand_inner = Self::settled_type(&operand_type);
+                        if !matches!(operand_inner, Type::Boolean | Type::Any) {
+                            return Err(AnalyzerError::TypeError {
+                                expected: "boolean".to_string(),
+                                found: operand_inner.to_string(),
+                                span: *span,
+                            });
+                        }
+                        if matches!(operand_type, Type::Promise(_)) {
+                            Ok(Type::Promise(Some(Box::new(Type::Boolean))))
+                        } else {
+                            Ok(Type::Boolean)
+                        }
+                    }
                 }
             }
             
@@ -516,11 +1357,34 @@ impl SemanticAnalyzer {
                 }
             }
             
-            AstNode::Object { fields, .. } => {
-                let mut object_fields = HashMap::new();
+            AstNode::Tuple { elements, .. } => {
+                let mut element_types = Vec::new();
+                for element in elements {
+                    element_types.push(self.infer_type_from_ast(element)?);
+                }
+                Ok(Type::Tuple(element_types))
+            }
+            
+            AstNode::Object { fields, span } => {
+                let mut object_fields = IndexMap::new();
                 for (key, value) in fields {
                     let value_type = self.infer_type_from_ast(value)?;
-                    object_fields.insert(key.clone(), value_type);
+                    match key {
+                        ObjectKey::Name(name) => {
+                            object_fields.insert(name.clone(), value_type);
+                        }
+                        // Complex keys are hashed by value, which a regex does not have
+                        ObjectKey::Computed(key) => {
+                            let key_type = Self::settled_type(&self.infer_type_from_ast(key)?);
+                            if matches!(key_type, Type::Regex) {
+                                return Err(AnalyzerError::TypeError {
+                                    expected: "a key with a value".to_string(),
+                                    found: key_type.to_string(),
+                                    span: *span,
+                                });
+                            }
+                        }
+                    }
                 }
                 Ok(Type::Object(object_fields))
             }
@@ -531,81 +1395,484 @@ impl SemanticAnalyzer {
             }
             
             AstNode::HttpCall { .. } => {
-                // HTTP calls return promises by default
-                Ok(Type::Promise(Some(Box::new(Type::Any))))
+                // A promise of the response body
+                Ok(Type::Promise(Some(Box::new(Type::String))))
             }
             
-            AstNode::FunctionCall { name: _, arguments: _, .. } => {
-                // Function calls are automatically treated as promises
-                Ok(Type::Promise(Some(Box::new(Type::Any))))
+            AstNode::FunctionCall { name, arguments, span } => {
+                let mut argument_types = Vec::new();
+                for argument in arguments {
+                    argument_types.push(self.infer_type_from_ast(argument)?);
+                }
+                
+                // Calls to anything but a declared function are external and return promises
+                let (result_type, _) = self.infer_call(name, &argument_types, *span)?;
+                let deferred = argument_types.iter().any(|argument_type| matches!(argument_type, Type::Promise(_)));
+                if deferred && !matches!(result_type, Type::Promise(_)) {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
+                }
             }
 
-            AstNode::MemberAccess { object, property, .. } => {
+            AstNode::MemberAccess { object, property, null_safe, span } => {
                 let object_type = self.infer_type_from_ast(object)?;
-                match object_type {
-                    Type::Object(fields) => {
-                        if let Some(field_type) = fields.get(property) {
-                            Ok(field_type.clone())
-                        } else {
-                            Ok(Type::Any)
-                        }
-                    }
-                    Type::Promise(inner) => {
-                        // Member access on a promise returns a promise
-                        if let Some(inner_type) = inner.as_ref() {
-                            match inner_type.as_ref() {
-                                Type::Object(fields) => {
-                                    if let Some(field_type) = fields.get(property) {
-                                        Ok(Type::Promise(Some(Box::new(field_type.clone()))))
-                                    } else {
-                                        Ok(Type::Promise(Some(Box::new(Type::Any))))
-                                    }
-                                }
-                                _ => Ok(Type::Promise(Some(Box::new(Type::Any))))
-                            }
-                        } else {
-                            Ok(Type::Promise(Some(Box::new(Type::Any))))
-                        }
-                    }
-                    _ => Ok(Type::Any),
+                let receiver = self.non_null_receiver(&object_type, property, *null_safe, *span);
+                
+                let field_type = match &receiver {
+                    Type::Object(fields) => fields.get(property).cloned().unwrap_or(Type::Any),
+                    _ => Type::Any,
+                };
+                // `?.` on a value that may be null may itself be null
+                let may_be_null = matches!(Self::settled_type(&object_type), Type::Null | Type::Nullable(_));
+                let result_type = if *null_safe && may_be_null {
+                    Self::nullable(field_type)
+                } else {
+                    field_type
+                };
+                
+                // Member access on a promise returns a promise
+                if matches!(object_type, Type::Promise(_)) {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
                 }
             }
             
-            AstNode::Test { .. } => {
-                // Test declarations don't have a runtime type, they are metadata
-                Ok(Type::Any)
+            AstNode::Conditional { condition, then_branch, else_branch, span } => {
+                let condition_type = self.infer_type_from_ast(condition)?;
+                let settled_condition = Self::settled_type(&condition_type);
+                if !matches!(settled_condition, Type::Boolean | Type::Any) {
+                    return Err(AnalyzerError::TypeError {
+                        expected: "boolean".to_string(),
+                        found: settled_condition.to_string(),
+                        span: *span,
+                    });
+                }
+                
+                let then_type = self.infer_type_from_ast(then_branch)?;
+                let else_type = self.infer_type_from_ast(else_branch)?;
+                let deferred = [&condition_type, &then_type, &else_type].iter()
+                    .any(|branch_type| matches!(branch_type, Type::Promise(_)));
+                
+                let result_type = Self::unify_types(&Self::settled_type(&then_type), &Self::settled_type(&else_type));
+                
+                if deferred {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
+                }
+            }
+            
+            AstNode::Match { subject, arms, span } => {
+                let subject_type = self.infer_type_from_ast(subject)?;
+                let patterns: Vec<&Pattern> = arms.iter().map(|arm| &arm.pattern).collect();
+                if !Self::exhaustive(&patterns, &Self::settled_type(&subject_type)) {
+                    return Err(AnalyzerError::NonExhaustiveMatch {
+                        found: Self::settled_type(&subject_type).to_string(),
+                        span: *span,
+                    });
+                }
+                
+                let mut deferred = matches!(subject_type, Type::Promise(_));
+                let mut result_type: Option<Type> = None;
+                for arm in arms {
+                    let arm_type = self.infer_type_from_ast(&arm.value)?;
+                    deferred |= matches!(arm_type, Type::Promise(_));
+                    let arm_type = Self::settled_type(&arm_type);
+                    result_type = Some(match result_type {
+                        Some(result_type) => Self::unify_types(&result_type, &arm_type),
+                        None => arm_type,
+                    });
+                }
+                
+                let result_type = result_type.unwrap_or(Type::Any);
+                if deferred {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
+                }
+            }
+            
+            AstNode::Index { object, index, span } => {
+                let object_type = self.infer_type_from_ast(object)?;
+                let index_type = self.infer_type_from_ast(index)?;
+                let deferred = matches!(object_type, Type::Promise(_)) || matches!(index_type, Type::Promise(_));
+                
+                let element_type = match (Self::settled_type(&object_type), Self::settled_type(&index_type)) {
+                    (Type::Array(element), Type::Number | Type::Any) => *element,
+                    // A tuple's size is fixed, so a literal position must be inside it
+                    (Type::Tuple(elements), Type::Number | Type::Any) => match index.as_ref() {
+                        AstNode::Number(position, _) => position.to_usize()
+                            .and_then(|position| elements.get(position).cloned())
+                            .ok_or_else(|| AnalyzerError::TypeError {
+                                expected: format!("a position in a tuple of {}", elements.len()),
+                                found: position.to_string(),
+                                span: *span,
+                            })?,
+                        _ => Type::Any,
+                    },
+                    (Type::Tuple(_), found) => return Err(AnalyzerError::TypeError {
+                        expected: "number".to_string(),
+                        found: found.to_string(),
+                        span: *span,
+                    }),
+                    // Any value can be a key, but only string keys have known types
+                    (Type::Object(fields), _) => match index.as_ref() {
+                        AstNode::String(key, _) => fields.get(key).cloned().unwrap_or(Type::Any),
+                        _ => Type::Any,
+                    },
+                    (Type::Array(_), found) => return Err(AnalyzerError::TypeError {
+                        expected: "number".to_string(),
+                        found: found.to_string(),
+                        span: *span,
+                    }),
+                    (Type::Any | Type::Null | Type::Nullable(_), _) => Type::Any,
+                    (found, _) => return Err(AnalyzerError::TypeError {
+                        expected: "array or object".to_string(),
+                        found: found.to_string(),
+                        span: *span,
+                    }),
+                };
+                
+                if deferred {
+                    Ok(Type::Promise(Some(Box::new(element_type))))
+                } else {
+                    Ok(element_type)
+                }
+            }
+
+            AstNode::MethodCall { object, method, arguments, span } => {
+                let object_type = self.infer_type_from_ast(object)?;
+                let receiver = self.non_null_receiver(&object_type, method, false, *span);
+                let result_type = self.infer_method_result_type(&receiver, method, arguments.len(), *span)?;
+                
+                // Calling a method on a promise yields a promise of the result
+                if matches!(object_type, Type::Promise(_)) {
+                    Ok(Type::Promise(Some(Box::new(result_type))))
+                } else {
+                    Ok(result_type)
+                }
             }
             
             _ => Ok(Type::Any),
         }
     }
 
-    fn infer_binary_result_type(&self, left: &Type, right: &Type, operator: &BinaryOp) -> Result<Type, AnalyzerError> {
+    /// Whether some arm matches every value of `subject`. Strings and values
+    /// of unknown type are only covered by a pattern that matches anything.
+    fn exhaustive(patterns: &[&Pattern], subject: &Type) -> bool {
+        if patterns.iter().any(|pattern| Self::covers(pattern, subject)) {
+            return true;
+        }
+        match subject {
+            Type::Nullable(inner) => Self::exhaustive(patterns, &Type::Null) && Self::exhaustive(patterns, inner),
+            Type::Boolean => [true, false].iter().all(|value| patterns.iter()
+                .any(|pattern| matches!(pattern, Pattern::Literal(AstNode::Boolean(literal, _)) if literal == value))),
+            Type::Number => Self::covers_numbers(patterns),
+            Type::Array(element) => {
+                // Every length up to the shortest `[.., ..rest]` needs an arm of its own
+                let covering: Vec<(usize, bool)> = patterns.iter()
+                    .filter_map(|pattern| match pattern {
+                        Pattern::Array { elements, rest } if elements.iter().all(|item| Self::covers(item, element)) => {
+                            Some((elements.len(), rest.is_some()))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                let Some(open) = covering.iter().filter(|(_, rest)| *rest).map(|(length, _)| *length).min() else {
+                    return false;
+                };
+                (0..open).all(|length| covering.contains(&(length, false)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether one pattern matches every value of `value_type`
+    fn covers(pattern: &Pattern, value_type: &Type) -> bool {
+        match (pattern, value_type) {
+            (Pattern::Wildcard | Pattern::Binding(..), _) => true,
+            (_, Type::Nullable(inner)) => Self::covers(pattern, &Type::Null) && Self::covers(pattern, inner),
+            (Pattern::Literal(AstNode::Null(_)), Type::Null) => true,
+            (Pattern::Array { elements, rest }, Type::Tuple(types)) => {
+                let length_matches = match rest {
+                    Some(_) => elements.len() <= types.len(),
+                    None => elements.len() == types.len(),
+                };
+                length_matches && elements.iter().zip(types).all(|(element, element_type)| Self::covers(element, element_type))
+            }
+            (Pattern::Array { elements, rest: Some(_) }, Type::Array(_)) => elements.is_empty(),
+            (Pattern::Object(fields), Type::Object(types)) => fields.iter()
+                .all(|(field, pattern)| types.get(field).is_some_and(|field_type| Self::covers(pattern, field_type))),
+            _ => false,
+        }
+    }
+
+    /// Whether the number literals and ranges together leave no gap from
+    /// negative to positive infinity
+    fn covers_numbers(patterns: &[&Pattern]) -> bool {
+        let mut ranges: Vec<(Option<&BigDecimal>, Option<&BigDecimal>)> = patterns.iter()
+            .filter_map(|pattern| match pattern {
+                Pattern::Range { start, end } => Some((start.as_ref(), end.as_ref())),
+                Pattern::Literal(AstNode::Number(number, _)) => Some((Some(number), Some(number))),
+                _ => None,
+            })
+            .collect();
+        // Ranges open below sort first
+        ranges.sort_by(|a, b| a.0.cmp(&b.0));
+        
+        let mut reached = None;
+        for (start, end) in ranges {
+            match (start, reached) {
+                (None, _) => {}
+                (Some(start), Some(reached)) if start <= reached => {}
+                _ => return false,
+            }
+            match end {
+                Some(end) => reached = reached.max(Some(end)),
+                None => return true,
+            }
+        }
+        false
+    }
+
+    /// The part of a settled `match` subject a binding holds. Matching a
+    /// field or element means the value was not null.
+    fn binding_type(subject: &Type, steps: &[PatternStep]) -> Type {
+        steps.iter().fold(subject.clone(), |value_type, step| {
+            let value_type = match value_type {
+                Type::Nullable(inner) => *inner,
+                other => other,
+            };
+            match (value_type, step) {
+                (Type::Object(mut fields), PatternStep::Field(field)) => fields.swap_remove(field).unwrap_or(Type::Any),
+                (Type::Array(element), PatternStep::Element(_)) => *element,
+                (Type::Tuple(mut elements), PatternStep::Element(index)) if *index < elements.len() => elements.swap_remove(*index),
+                (Type::Array(element), PatternStep::Rest(_)) => Type::Array(element),
+                (Type::Tuple(elements), PatternStep::Rest(start)) => Type::Tuple(elements.into_iter().skip(*start).collect()),
+                _ => Type::Any,
+            }
+        })
+    }
+
+    /// The type a value has once any promise around it has resolved
+    fn settled_type(value_type: &Type) -> Type {
+        match value_type {
+            Type::Promise(Some(inner)) => inner.as_ref().clone(),
+            Type::Promise(None) => Type::Any,
+            other => other.clone(),
+        }
+    }
+
+    /// The keys named by a literal `"key"` or `["a", "b"]` operand
+    fn literal_keys(node: &AstNode) -> Option<Vec<String>> {
+        match node {
+            AstNode::String(key, _) => Some(vec![key.clone()]),
+            AstNode::Array { elements, .. } => elements.iter()
+                .map(|element| match element {
+                    AstNode::String(key, _) => Some(key.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn nullable(value_type: Type) -> Type {
+        match value_type {
+            Type::Null | Type::Nullable(_) | Type::Any => value_type,
+            other => Type::Nullable(Box::new(other)),
+        }
+    }
+
+    /// The type of a value that may come from either of two types
+    fn unify_types(a: &Type, b: &Type) -> Type {
+        match (a, b) {
+            _ if a == b => a.clone(),
+            (Type::Null, other) | (other, Type::Null) => Self::nullable(other.clone()),
+            (Type::Nullable(inner), other) | (other, Type::Nullable(inner)) if **inner == *other => Type::Nullable(inner.clone()),
+            _ => Type::Any,
+        }
+    }
+
+    /// The receiver of `.member` with any null stripped off; a plain `.` on a
+    /// value that may be null is reported as a warning
+    fn non_null_receiver(&mut self, object_type: &Type, member: &str, null_safe: bool, span: Span) -> Type {
+        match Self::settled_type(object_type) {
+            found @ (Type::Null | Type::Nullable(_)) => {
+                if !null_safe {
+                    self.warnings.push(AnalyzerWarning::NullableMemberAccess {
+                        member: member.to_string(),
+                        found: found.to_string(),
+                        span,
+                    });
+                }
+                match found {
+                    Type::Nullable(inner) => *inner,
+                    _ => Type::Any,
+                }
+            }
+            other => other,
+        }
+    }
+
+    fn infer_binary_result_type(&self, left: &Type, right: &Type, operator: &BinaryOp, span: Span) -> Result<Type, AnalyzerError> {
         match operator {
             BinaryOp::Add => {
                 match (left, right) {
                     (Type::Number, Type::Number) => Ok(Type::Number),
+                    // Concatenate arrays, or append a single item
+                    (Type::Array(items), Type::Array(other)) if items == other => Ok(left.clone()),
+                    (Type::Array(items), other) if **items == *other => Ok(left.clone()),
+                    (Type::Array(_), _) => Ok(Type::Array(Box::new(Type::Any))),
                     (Type::String, _) | (_, Type::String) => Ok(Type::String),
+                    // Merge objects, the right side wins
+                    (Type::Object(fields), Type::Object(other)) => {
+                        let mut merged = fields.clone();
+                        merged.extend(other.iter().map(|(key, value)| (key.clone(), value.clone())));
+                        Ok(Type::Object(merged))
+                    }
                     _ => Ok(Type::Any),
                 }
             }
-            BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
+            // Remove from, or intersect, the left side
+            BinaryOp::Subtract | BinaryOp::Multiply => {
+                match (left, right) {
+                    (Type::Number, Type::Number) | (Type::String, Type::String) => Ok(left.clone()),
+                    (Type::Array(_), _) => Ok(left.clone()),
+                    // Any value can be an object key
+                    (Type::Object(_), _) => Ok(left.clone()),
+                    (Type::Any, _) | (Type::Number | Type::String, Type::Any) => Ok(Type::Any),
+                    _ => Err(AnalyzerError::TypeError {
+                        expected: "two numbers, two strings, an array, or an object and keys".to_string(),
+                        found: format!("{} and {}", left, right),
+                        span,
+                    }),
+                }
+            }
+            BinaryOp::Divide => {
                 match (left, right) {
                     (Type::Number, Type::Number) => Ok(Type::Number),
-                    _ => Ok(Type::Any),
+                    (Type::Number | Type::Any, Type::Number | Type::Any) => Ok(Type::Any),
+                    _ => Err(AnalyzerError::TypeError {
+                        expected: "number".to_string(),
+                        found: format!("{} and {}", left, right),
+                        span,
+                    }),
+                }
+            }
+            // Equality is deep and never fails, values of different types are unequal
+            BinaryOp::Equal | BinaryOp::NotEqual => Ok(Type::Boolean),
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                match (left, right) {
+                    (Type::Number, Type::Number)
+                    | (Type::String, Type::String)
+                    | (Type::Any, Type::Number | Type::String | Type::Any)
+                    | (Type::Number | Type::String, Type::Any) => Ok(Type::Boolean),
+                    _ => Err(AnalyzerError::TypeError {
+                        expected: "two numbers or two strings".to_string(),
+                        found: format!("{} and {}", left, right),
+                        span,
+                    }),
+                }
+            }
+            // The fallback only matters when the left side may be null
+            BinaryOp::Coalesce => Ok(match left {
+                Type::Null => right.clone(),
+                Type::Nullable(inner) => Self::unify_types(inner, right),
+                other => other.clone(),
+            }),
+            BinaryOp::And => {
+                match (left, right) {
+                    (Type::Boolean | Type::Any, Type::Boolean | Type::Any) => Ok(Type::Boolean),
+                    _ => Err(AnalyzerError::TypeError {
+                        expected: "boolean".to_string(),
+                        found: format!("{} and {}", left, right),
+                        span,
+                    }),
+                }
+            }
+        }
+    }
+
+    fn infer_method_result_type(&self, receiver: &Type, method: &str, argument_count: usize, span: Span) -> Result<Type, AnalyzerError> {
+        match receiver {
+            Type::Regex => {
+                let (expected, result_type) = match method {
+                    "test" => (1, Type::Boolean),
+                    "match" => (1, Type::Nullable(Box::new(Type::Array(Box::new(Type::String))))),
+                    "replace" => (2, Type::String),
+                    _ => return Err(AnalyzerError::UnknownMethod {
+                        receiver: receiver.to_string(),
+                        method: method.to_string(),
+                        span,
+                    }),
+                };
+                if argument_count != expected {
+                    return Err(AnalyzerError::ArgumentCount {
+                        method: method.to_string(),
+                        expected,
+                        found: argument_count,
+                        span,
+                    });
                 }
+                Ok(result_type)
             }
-            BinaryOp::Equal => Ok(Type::Boolean),
+            Type::Any => Ok(Type::Any),
+            _ => Err(AnalyzerError::UnknownMethod {
+                receiver: receiver.to_string(),
+                method: method.to_string(),
+                span,
+            }),
         }
     }
 
     fn infer_types(&mut self, resolution_order: &[String]) -> Result<(), AnalyzerError> {
         for symbol_name in resolution_order {
             if let Some(symbol) = self.symbol_table.get_symbol(symbol_name).cloned() {
+                // A function body is checked once with unknown arguments, then again per call
+                if let Type::Function(parameter_count) = symbol.symbol_type {
+                    let (_, table) = self.infer_call(symbol_name, &vec![Type::Any; parameter_count], symbol.definition_span)?;
+                    if let Some(function) = self.functions.iter_mut().find(|function| function.name == *symbol_name) {
+                        function.symbol_table = table;
+                    }
+                    if let Some(symbol_mut) = self.symbol_table.get_symbol_mut(symbol_name) {
+                        symbol_mut.is_resolved = true;
+                    }
+                    continue;
+                }
+                
+                // A binding holds part of the subject once it has settled
+                if let Some((subject, steps)) = self.match_bindings.get(symbol_name) {
+                    let subject_type = self.lookup_symbol(subject).map(|subject| Self::settled_type(&subject.symbol_type)).unwrap_or(Type::Any);
+                    let binding_type = Self::binding_type(&subject_type, steps);
+                    if let Some(symbol_mut) = self.symbol_table.get_symbol_mut(symbol_name) {
+                        symbol_mut.symbol_type = binding_type;
+                        symbol_mut.is_resolved = true;
+                    }
+                    continue;
+                }
+                
+                // Dependencies come first in the order, so their types are known
+                if let Some(ast_node) = &symbol.ast_node {
+                    let symbol_type = self.infer_type_from_ast(ast_node)?;
+                    if matches!(symbol_type, Type::Function(_)) {
+                        return Err(AnalyzerError::TypeError {
+                            expected: "a value or a call".to_string(),
+                            found: symbol_type.to_string(),
+                            span: symbol.definition_span,
+                        });
+                    }
+                    if let Some(symbol_mut) = self.symbol_table.get_symbol_mut(symbol_name) {
+                        symbol_mut.symbol_type = symbol_type;
+                    }
+                }
+                
                 // Check if any dependencies are promises
                 let has_promise_dependency = symbol.dependencies.iter()
                     .any(|dep| {
-                        if let Some(dep_symbol) = self.symbol_table.get_symbol(dep) {
+                        if let Some(dep_symbol) = self.lookup_symbol(dep) {
                             matches!(dep_symbol.symbol_type, Type::Promise(_))
                         } else {
                             false
@@ -631,36 +1898,43 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
-    fn validate_program(&self) -> Result<(), AnalyzerError> {
-        // Check for undefined symbols
-        for symbol in self.symbol_table.symbols().values() {
-            for dependency in &symbol.dependencies {
-                if !self.symbol_table.symbols().contains_key(dependency) {
-                    return Err(AnalyzerError::UndefinedSymbol {
-                        name: dependency.clone(),
-                        line: symbol.definition_line,
-                    });
+    /// Symbols reachable without passing through a conditional branch are
+    /// evaluated up front; everything else waits until a branch needs it.
+    /// Parameters and `match` bindings have no value to wait for.
+    fn mark_deferred_symbols(&mut self) {
+        let mut queue: VecDeque<String> = self.symbol_table.symbols().values()
+            .filter(|symbol| symbol.dependents.is_empty())
+            .map(|symbol| symbol.name.clone())
+            .collect();
+        let mut eager: HashSet<String> = queue.iter().cloned().collect();
+        
+        while let Some(name) = queue.pop_front() {
+            if let Some(symbol) = self.symbol_table.get_symbol(&name) {
+                for dependency in symbol.dependencies.difference(&symbol.deferred_dependencies) {
+                    if eager.insert(dependency.clone()) {
+                        queue.push_back(dependency.clone());
+                    }
                 }
             }
         }
         
-        // Validate endpoints
-        for endpoint in &self.endpoints {
-            // Check for duplicate endpoint paths with same method
-            let conflicts: Vec<_> = self.endpoints.iter()
-                .filter(|other| {
-                    other.name != endpoint.name &&
-                    other.method == endpoint.method &&
-                    other.path == endpoint.path
-                })
-                .collect();
+        for symbol in self.symbol_table.symbols.values_mut() {
+            symbol.is_deferred = !eager.contains(&symbol.name) && symbol.ast_node.is_some();
+        }
+    }
+
+    fn validate_program(&self) -> Result<(), AnalyzerError> {
+        // Only the first endpoint for a method and path would ever answer
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            let repeated = self.endpoints[..index].iter()
+                .any(|other| other.method == endpoint.method && other.path == endpoint.path);
             
-            if !conflicts.is_empty() {
+            if repeated {
                 return Err(AnalyzerError::DuplicateEndpoint {
                     name: endpoint.name.clone(),
-                    method: format!("{:?}", endpoint.method),
+                    method: endpoint.method.to_string(),
                     path: endpoint.path.clone(),
-                    line: endpoint.line,
+                    span: endpoint.span,
                 });
             }
         }
@@ -668,6 +1942,15 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
+    /// Where `node` first refers to `name`
+    fn reference_span(node: &AstNode, name: &str) -> Option<Span> {
+        match node {
+            AstNode::Identifier(identifier, span) if identifier == name => Some(*span),
+            AstNode::FunctionCall { name: called, span, .. } if called == name => Some(*span),
+            _ => node.children().into_iter().find_map(|child| Self::reference_span(child, name)),
+        }
+    }
+
     pub fn symbol_table(&self) -> &SymbolTable {
         &self.symbol_table
     }
@@ -679,6 +1962,10 @@ impl SemanticAnalyzer {
     pub fn tests(&self) -> &[TestInfo] {
         &self.tests
     }
+
+    pub fn warnings(&self) -> &[AnalyzerWarning] {
+        &self.warnings
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -687,6 +1974,7 @@ pub struct AnalyzedProgram {
     pub resolution_order: Vec<String>,
     pub endpoints: Vec<EndpointInfo>,
     pub tests: Vec<TestInfo>,
+    pub functions: Vec<FunctionInfo>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -694,14 +1982,50 @@ pub enum AnalyzerError {
     #[error("Circular dependency detected involving symbols: {0:?}")]
     CircularDependency(Vec<String>),
     
-    #[error("Undefined symbol '{name}' at line {line}")]
-    UndefinedSymbol { name: String, line: usize },
+    #[error("Undefined symbol '{name}' at line {}", .span.line)]
+    UndefinedSymbol { name: String, span: Span },
     
-    #[error("Type error at line {line}: expected {expected}, found {found}")]
-    TypeError { expected: String, found: String, line: usize },
+    #[error("Type error at line {}: expected {expected}, found {found}", .span.line)]
+    TypeError { expected: String, found: String, span: Span },
     
-    #[error("Duplicate endpoint '{name}' for {method} {path} at line {line}")]
-    DuplicateEndpoint { name: String, method: String, path: String, line: usize },
+    #[error("Duplicate endpoint '{name}' for {method} {path} at line {}", .span.line)]
+    DuplicateEndpoint { name: String, method: String, path: String, span: Span },
+    
+    #[error("Unknown method '{method}' on {receiver} at line {}", .span.line)]
+    UnknownMethod { receiver: String, method: String, span: Span },
+    
+    #[error("Method '{method}' expects {expected} argument(s), found {found} at line {}", .span.line)]
+    ArgumentCount { method: String, expected: usize, found: usize, span: Span },
+    
+    #[error("Function '{function}' expects {expected} argument(s), found {found} at line {}", .span.line)]
+    FunctionArgumentCount { function: String, expected: usize, found: usize, span: Span },
+    
+    #[error("Circular dependency across files {files:?} involving symbols: {symbols:?}")]
+    CircularImport { files: Vec<String>, symbols: Vec<String> },
+    
+    #[error("Import of '{path}' at line {} was not loaded, compile the file as part of a project", .span.line)]
+    UnresolvedImport { path: String, span: Span },
+    
+    #[error("Top-level object at line {} can only declare named fields", .span.line)]
+    UnnamedGlobal { span: Span },
+    
+    #[error("Unknown annotation '@{name}' at line {}", .span.line)]
+    UnknownAnnotation { name: String, span: Span },
+    
+    #[error("Invalid '@{name}' at line {}: {message}", .span.line)]
+    InvalidAnnotation { name: String, message: String, span: Span },
+    
+    #[error("Match at line {} does not cover every {found}", .span.line)]
+    NonExhaustiveMatch { found: String, span: Span },
+    
+    #[error("'{name}' at line {} is declared more than once", .span.line)]
+    DuplicateSymbol { name: String, span: Span },
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum AnalyzerWarning {
+    #[error("'.{member}' at line {} is used on {found}, which may be null; use '?.' or '??' instead", .span.line)]
+    NullableMemberAccess { member: String, found: String, span: Span },
 }
 
 #[cfg(test)]
@@ -875,6 +2199,403 @@ mod tests {
         assert!(result.symbol_table.get_symbol("comparison").is_some());
     }
 
+    #[test]
+    fn test_comparison_operator_types() {
+        let source = r#"
+            count: 3
+            name: "loaf"
+            small: count < 10
+            sorted: name <= "zebra"
+            same: [1, 2] = [1, 2]
+            both: small & !sorted
+            user: fetch_user(1)
+            adult: user.age >= 18
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        for name in ["small", "sorted", "same", "both"] {
+            assert_eq!(result.symbol_table.get_symbol(name).unwrap().symbol_type, Type::Boolean, "{}", name);
+        }
+        let adult = result.symbol_table.get_symbol("adult").unwrap();
+        assert_eq!(adult.symbol_type, Type::Promise(Some(Box::new(Type::Boolean))));
+    }
+
+    #[test]
+    fn test_comparison_operator_type_errors() {
+        assert!(matches!(analyze_source(r#"x: 1 < "2""#), Err(AnalyzerError::TypeError { .. })));
+        assert!(matches!(analyze_source("x: [1] > [0]"), Err(AnalyzerError::TypeError { .. })));
+        assert!(matches!(analyze_source("x: true & 1"), Err(AnalyzerError::TypeError { .. })));
+        assert!(matches!(analyze_source(r#"x: !"yes""#), Err(AnalyzerError::TypeError { .. })));
+        
+        // Equality accepts any pair of values
+        assert!(analyze_source(r#"x: 1 = "1""#).is_ok());
+    }
+
+    #[test]
+    fn test_conditional_branches_are_deferred() {
+        let source = r#"
+            user: 1
+            is_gold: user = 1
+            gold: gold_price(user)
+            standard: 10
+            price: is_gold ? gold : standard + 1
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        // Each branch is its own symbol that only the conditional needs
+        let price = result.symbol_table.get_symbol("price").unwrap();
+        assert_eq!(price.deferred_dependencies.len(), 2);
+        assert!(price.dependencies.contains("is_gold"));
+        assert!(!price.deferred_dependencies.contains("is_gold"));
+        for branch in &price.deferred_dependencies {
+            assert!(result.symbol_table.get_symbol(branch).unwrap().is_deferred);
+        }
+        
+        // Only the branches reach `gold` and `standard`, while `user` is needed up front
+        assert!(result.symbol_table.get_symbol("gold").unwrap().is_deferred);
+        assert!(result.symbol_table.get_symbol("standard").unwrap().is_deferred);
+        assert!(!result.symbol_table.get_symbol("user").unwrap().is_deferred);
+        assert!(!price.is_deferred);
+        
+        // One deferred branch makes the whole conditional deferred
+        assert!(matches!(price.symbol_type, Type::Promise(_)));
+    }
+
+    #[test]
+    fn test_conditional_types() {
+        let result = analyze_source(r#"label: 1 < 2 ? "small" : "large""#).unwrap();
+        assert_eq!(result.symbol_table.get_symbol("label").unwrap().symbol_type, Type::String);
+        
+        let result = analyze_source(r#"mixed: true ? "yes" : 0"#).unwrap();
+        assert_eq!(result.symbol_table.get_symbol("mixed").unwrap().symbol_type, Type::Any);
+        
+        assert!(matches!(analyze_source("bad: 1 ? 2 : 3"), Err(AnalyzerError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_match_arms_are_deferred() {
+        let source = r#"
+            response: { status: 404, error: "missing" }
+            gold: gold_price()
+            message: match response {
+                { status: 200..299 }: gold,
+                { status: 400..499, error }: error,
+                _: "failed",
+            }
+        "#;
+        let result = analyze_source(source).unwrap();
+        
+        // Only the subject is needed before an arm is chosen
+        let message = result.symbol_table.get_symbol("message").unwrap();
+        assert_eq!(message.deferred_dependencies.len(), 3);
+        assert_eq!(message.dependencies.len(), 4);
+        assert!(result.symbol_table.get_symbol("gold").unwrap().is_deferred);
+        assert!(matches!(message.symbol_type, Type::Promise(_)));
+        
+        // A binding has no value of its own, it holds part of the subject
+        let error = result.symbol_table.symbols().values()
+            .find(|symbol| symbol.name.ends_with(".error") && symbol.ast_node.is_none())
+            .unwrap();
+        assert_eq!(error.symbol_type, Type::String);
+        assert!(!error.is_deferred);
+    }
+
+    #[test]
+    fn test_match_exhaustiveness() {
+        let exhaustive = |source: &str| match analyze_source(source) {
+            Ok(_) => true,
+            Err(AnalyzerError::NonExhaustiveMatch { .. }) => false,
+            Err(error) => panic!("Unexpected error {}", error),
+        };
+        
+        assert!(exhaustive("x: match 1 < 2 { true: 1, false: 0 }"));
+        assert!(exhaustive("n: 5\nx: match n { ..0: \"low\", 0: \"zero\", 0..10: \"some\", 10..: \"many\" }"));
+        assert!(exhaustive("xs: [1, 2]\nx: match xs { []: 0, [a]: a, [a, b, ..]: a + b }"));
+        assert!(exhaustive("p: (1, \"a\")\nx: match p { [n, s]: s }"));
+        assert!(exhaustive("u: true ? { name: \"a\" } : null\nx: match u { { name }: name, null: \"nobody\" }"));
+        assert!(exhaustive("x: match \"a\" { /a/: 1, _: 2 }"));
+        
+        assert!(!exhaustive("x: match 1 < 2 { true: 1 }"));
+        assert!(!exhaustive("n: 5\nx: match n { ..0: \"low\", 1..: \"high\" }"));
+        assert!(!exhaustive("xs: [1, 2]\nx: match xs { [a, ..]: a }"));
+        assert!(!exhaustive("u: true ? { name: \"a\" } : null\nx: match u { { name }: name }"));
+        assert!(!exhaustive("x: match \"a\" { /a/: 1, \"b\": 2 }"));
+        
+        let result = analyze_source("n: 5\nx: match n { 0: \"zero\", _: 1 }").unwrap();
+        assert_eq!(result.symbol_table.get_symbol("x").unwrap().symbol_type, Type::Any);
+        let result = analyze_source("n: 5\nx: match n { 0: null, _: 1 }").unwrap();
+        assert_eq!(result.symbol_table.get_symbol("x").unwrap().symbol_type, Type::Nullable(Box::new(Type::Number)));
+    }
+
+    #[test]
+    fn test_nullable_member_access_warns() {
+        let source = r#"
+            found: 1 < 2
+            user: found ? { name: "Ada" } : null
+            name: user.name
+            safe: user?.name
+            display: user?.name ?? "anonymous"
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        let result = analyzer.analyze(&ast).unwrap();
+        
+        let object_type = Type::Object(IndexMap::from([("name".to_string(), Type::String)]));
+        assert_eq!(result.symbol_table.get_symbol("user").unwrap().symbol_type, Type::Nullable(Box::new(object_type)));
+        assert_eq!(result.symbol_table.get_symbol("name").unwrap().symbol_type, Type::String);
+        assert_eq!(result.symbol_table.get_symbol("safe").unwrap().symbol_type, Type::Nullable(Box::new(Type::String)));
+        assert_eq!(result.symbol_table.get_symbol("display").unwrap().symbol_type, Type::String);
+        
+        // Only the plain `.` is reported
+        assert!(matches!(analyzer.warnings(), [AnalyzerWarning::NullableMemberAccess { member, found, span }]
+            if member == "name" && found == "nullable<object>" && span.line == 4));
+    }
+
+    #[test]
+    fn test_index_types() {
+        let result = analyze_source(r#"
+            names: ["ada", "grace"]
+            i: 1
+            last: names[-1]
+            picked: names[i]
+            headers: { "content type": "json", size: 2 }
+            kind: headers["content type"]
+            field: headers[names[0]]
+        "#).unwrap();
+        
+        assert_eq!(result.symbol_table.get_symbol("last").unwrap().symbol_type, Type::String);
+        assert_eq!(result.symbol_table.get_symbol("kind").unwrap().symbol_type, Type::String);
+        assert_eq!(result.symbol_table.get_symbol("field").unwrap().symbol_type, Type::Any);
+        
+        let picked = result.symbol_table.get_symbol("picked").unwrap();
+        assert_eq!(picked.symbol_type, Type::String);
+        assert!(picked.dependencies.contains("names"));
+        assert!(picked.dependencies.contains("i"));
+        
+        assert!(matches!(analyze_source(r#"a: [1][true]"#), Err(AnalyzerError::TypeError { .. })));
+        assert!(matches!(analyze_source(r#"a: 5[0]"#), Err(AnalyzerError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_set_operator_types() {
+        let result = analyze_source(r#"
+            user: { id: 1, name: "ada", password: "secret" }
+            public: user - "password"
+            summary: user * ["id", "name"]
+            merged: user + { admin: true }
+            tags: ["a", "b"] + "c"
+            initials: "ada lovelace" * "al"
+        "#).unwrap();
+        
+        let field_names = |name: &str| match &result.symbol_table.get_symbol(name).unwrap().symbol_type {
+            Type::Object(fields) => {
+                let mut names: Vec<_> = fields.keys().cloned().collect();
+                names.sort();
+                names
+            }
+            other => panic!("Expected object type, got {}", other),
+        };
+        assert_eq!(field_names("public"), ["id", "name"]);
+        assert_eq!(field_names("summary"), ["id", "name"]);
+        assert_eq!(field_names("merged"), ["admin", "id", "name", "password"]);
+        assert_eq!(result.symbol_table.get_symbol("tags").unwrap().symbol_type, Type::Array(Box::new(Type::String)));
+        assert_eq!(result.symbol_table.get_symbol("initials").unwrap().symbol_type, Type::String);
+        
+        assert!(matches!(analyze_source(r#"x: "abc" - 1"#), Err(AnalyzerError::TypeError { .. })));
+        assert!(matches!(analyze_source(r#"x: true * [1]"#), Err(AnalyzerError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_complex_key_scopes_and_errors() {
+        let result = analyze_source(r#"
+            year: 2024
+            totals: { region: "west", [region, year]: amount, amount: 10 }
+        "#).unwrap();
+        
+        // Keys and values see sibling fields and enclosing names
+        let totals = result.symbol_table.get_symbol("totals").unwrap();
+        for dependency in ["totals.region", "year", "totals.amount"] {
+            assert!(totals.dependencies.contains(dependency), "missing {}", dependency);
+        }
+        assert!(result.resolution_order.iter().position(|name| name == "totals.amount")
+            < result.resolution_order.iter().position(|name| name == "totals"));
+        
+        assert!(matches!(analyze_source(r#"{ 1: "one" }"#), Err(AnalyzerError::UnnamedGlobal { span }) if span.line == 1));
+        assert!(matches!(analyze_source(r#"x: { /a/: 1 }"#), Err(AnalyzerError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_destructuring_symbols() {
+        let result = analyze_source(r#"
+            a: 7
+            (q, r): divmod(a, 2)
+            (name, age): ("ada", 36)
+            total: q + r
+        "#).unwrap();
+        
+        // Each name depends on the shared source, which is evaluated once
+        for name in ["q", "r"] {
+            let symbol = result.symbol_table.get_symbol(name).unwrap();
+            assert_eq!(symbol.dependencies, HashSet::from(["(q, r)".to_string()]));
+            assert!(matches!(symbol.symbol_type, Type::Promise(_)));
+        }
+        assert!(result.symbol_table.get_symbol("(q, r)").unwrap().dependencies.contains("a"));
+        
+        assert_eq!(result.symbol_table.get_symbol("(name, age)").unwrap().symbol_type, Type::Tuple(vec![Type::String, Type::Number]));
+        assert_eq!(result.symbol_table.get_symbol("name").unwrap().symbol_type, Type::String);
+        assert_eq!(result.symbol_table.get_symbol("age").unwrap().symbol_type, Type::Number);
+        
+        assert!(matches!(analyze_source(r#"(x, y, z): (1, 2)"#), Err(AnalyzerError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_function_types_per_call_site() {
+        let result = analyze_source(r#"
+            first(items): items[0]
+            word: first(["a", "b"])
+            count: first([1, 2])
+            user: fetchUser(1)
+            name: first([user.name])
+            taxed(amount): amount * rate
+            rate: 2
+            total: taxed(count)
+        "#).unwrap();
+        
+        let symbol = |name: &str| result.symbol_table.get_symbol(name).unwrap();
+        assert_eq!(symbol("word").symbol_type, Type::String);
+        assert_eq!(symbol("count").symbol_type, Type::Number);
+        assert!(matches!(symbol("name").symbol_type, Type::Promise(_)));
+        assert_eq!(symbol("total").symbol_type, Type::Number);
+        
+        // A call depends on the function, which depends on the globals its body reads
+        assert!(symbol("total").dependencies.contains("taxed"));
+        assert_eq!(symbol("taxed").dependencies, HashSet::from(["rate".to_string()]));
+        let order = &result.resolution_order;
+        let position = |name: &str| order.iter().position(|symbol| symbol == name).unwrap();
+        assert!(position("rate") < position("taxed") && position("taxed") < position("total"));
+        
+        // Parameters and the body are symbols of the function, not globals
+        let taxed = result.functions.iter().find(|function| function.name == "taxed").unwrap();
+        assert_eq!(taxed.parameters, ["taxed.amount"]);
+        assert!(taxed.symbol_table.get_symbol("taxed()").is_some());
+        assert!(result.symbol_table.get_symbol("taxed.amount").is_none());
+    }
+
+    #[test]
+    fn test_function_errors() {
+        assert!(matches!(
+            analyze_source("twice(x): x + x\nbad: twice(1, 2)"),
+            Err(AnalyzerError::FunctionArgumentCount { expected: 1, found: 2, .. })
+        ));
+        assert!(matches!(
+            analyze_source("f(x): x + missing\ny: f(1)"),
+            Err(AnalyzerError::UndefinedSymbol { name, .. }) if name == "missing"
+        ));
+        assert!(matches!(
+            // Only this call passes a value the body cannot use
+            analyze_source("flip(x): !x\nyes: flip(false)\nno: flip(\"a\")"),
+            Err(AnalyzerError::TypeError { .. })
+        ));
+        // Parameters are not visible outside the function
+        assert!(matches!(
+            analyze_source("f(x): x\ny: x"),
+            Err(AnalyzerError::UndefinedSymbol { .. })
+        ));
+        // A cached value would keep the arguments of the first call
+        assert!(matches!(
+            analyze_source("f(x): { @cache(60)\ndoubled: x * 2 }"),
+            Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "cache"
+        ));
+    }
+
+    fn analyze_files(files: &[(&str, &str)]) -> Result<AnalyzedProgram, AnalyzerError> {
+        let directory = tempfile::TempDir::new().unwrap();
+        for (name, source) in files {
+            std::fs::write(directory.path().join(name), source).unwrap();
+        }
+        let project = Project::load(&directory.path().join(files[0].0)).unwrap();
+        SemanticAnalyzer::new().analyze_project(&project)
+    }
+
+    #[test]
+    fn test_imported_names_resolve_across_files() {
+        let result = analyze_files(&[
+            ("main.loaf", r#"
+                helpers: @import "helpers.loaf"
+                user: { first: "Ada", last: "Lovelace" }
+                name: helpers.fullName(user)
+                taxed: 100 * helpers.rate
+                shadowed: { helpers: { rate: 3 }, value: helpers.rate }
+            "#),
+            ("helpers.loaf", r#"
+                fullName(u): u.first + " " + u.last
+                rate: 2
+                doubled: rate * 2
+            "#),
+        ]).unwrap();
+        
+        let symbol = |name: &str| result.symbol_table.get_symbol(name).unwrap();
+        assert!(symbol("name").dependencies.contains("helpers.fullName"));
+        assert_eq!(symbol("name").symbol_type, Type::String);
+        assert!(symbol("taxed").dependencies.contains("helpers.rate"));
+        assert_eq!(symbol("helpers.doubled").dependencies, HashSet::from(["helpers.rate".to_string()]));
+        
+        // A declared name hides the namespace
+        assert!(symbol("shadowed.value").dependencies.contains("shadowed.helpers"));
+        
+        assert!(matches!(
+            analyze_files(&[("main.loaf", "h: @import \"h.loaf\"\nx: h.missing"), ("h.loaf", "y: 1")]),
+            Err(AnalyzerError::UndefinedSymbol { name, .. }) if name == "h.missing"
+        ));
+    }
+
+    #[test]
+    fn test_cycles_across_files_name_the_files() {
+        let error = analyze_files(&[
+            ("main.loaf", "other: @import \"other.loaf\"\na: other.b + 1\nafter: a"),
+            ("other.loaf", "main: @import \"main.loaf\"\nb: main.a + 1"),
+        ]).unwrap_err();
+        
+        let AnalyzerError::CircularImport { files, symbols } = error else { panic!("Expected a cycle across files") };
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("main.loaf") && files[1].ends_with("other.loaf"));
+        // `after` waits on the cycle but is not part of it
+        assert_eq!(symbols, ["a", "other.b"]);
+        
+        // Importing each other is fine without a cycle between symbols
+        assert!(analyze_files(&[
+            ("main.loaf", "other: @import \"other.loaf\"\na: other.b + 1\nc: 1"),
+            ("other.loaf", "main: @import \"main.loaf\"\nb: main.c + 1"),
+        ]).is_ok());
+        
+        assert!(matches!(
+            analyze_source("h: @import \"h.loaf\"\nx: h.y"),
+            Err(AnalyzerError::UnresolvedImport { span, .. }) if span.line == 1
+        ));
+    }
+
+    #[test]
+    fn test_coalesce_types_and_dependencies() {
+        let result = analyze_source(r#"
+            missing: null
+            port: missing ?? 8080
+            host: "localhost" ?? fallback
+            fallback: "example.com"
+        "#).unwrap();
+        
+        assert_eq!(result.symbol_table.get_symbol("port").unwrap().symbol_type, Type::Number);
+        assert_eq!(result.symbol_table.get_symbol("host").unwrap().symbol_type, Type::String);
+        
+        // The fallback is only needed when the left side is null
+        let host = result.symbol_table.get_symbol("host").unwrap();
+        assert!(!host.dependencies.iter().any(|dependency| dependency == "fallback"));
+        assert_eq!(host.deferred_dependencies.len(), 1);
+        assert!(result.symbol_table.get_symbol("fallback").unwrap().is_deferred);
+    }
+
     #[test]
     fn test_promise_type_creation() {
         let source = r#"{
@@ -897,8 +2618,8 @@ mod tests {
         let mut symbol_table = SymbolTable::new();
         
         // Test adding symbols
-        let symbol1 = Symbol::new("test1".to_string(), Type::String, 1);
-        let symbol2 = Symbol::new("test2".to_string(), Type::Number, 2);
+        let symbol1 = Symbol::new("test1".to_string(), Type::String, Span::default());
+        let symbol2 = Symbol::new("test2".to_string(), Type::Number, Span::default());
         
         symbol_table.add_symbol(symbol1);
         symbol_table.add_symbol(symbol2);
@@ -918,6 +2639,20 @@ mod tests {
         assert!(test1_symbol.dependents.contains("test2"));
     }
 
+    #[test]
+    fn test_symbol_table_reports_undefined_dependencies() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.add_symbol(Symbol::new("total".to_string(), Type::Number, Span::default()));
+        symbol_table.add_dependency("total", "missing");
+        
+        assert!(matches!(
+            symbol_table.resolve_dependencies(),
+            Err(AnalyzerError::UndefinedSymbol { name, .. }) if name == "missing"
+        ));
+        // A function body's table leaves outside names to the enclosing table
+        assert_eq!(symbol_table.resolve_local_dependencies().unwrap(), vec!["total".to_string()]);
+    }
+
     #[test]
     fn test_dependency_resolution_simple() {
         let source = r#"{
@@ -935,6 +2670,118 @@ mod tests {
         assert!(result.resolution_order.contains(&"c".to_string()));
     }
 
+    #[test]
+    fn test_object_scopes_allow_sibling_forward_references() {
+        let source = r#"
+            x: 10
+            order: {
+                total: subtotal - discount,
+                subtotal: x * 3,
+                discount: 5,
+            }
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let total = result.symbol_table.get_symbol("order.total").unwrap();
+        assert_eq!(total.symbol_type, Type::Number);
+        assert!(total.dependencies.contains("order.subtotal"));
+        assert!(total.dependencies.contains("order.discount"));
+        
+        // `x` is found in the enclosing (global) scope
+        let subtotal = result.symbol_table.get_symbol("order.subtotal").unwrap();
+        assert!(subtotal.dependencies.contains("x"));
+        
+        // Siblings are computed before the field that uses them
+        let position = |name: &str| result.resolution_order.iter().position(|n| n == name).unwrap();
+        assert!(position("order.subtotal") < position("order.total"));
+        assert!(position("order.total") < position("order"));
+        
+        // Nested fields no longer leak into the global scope
+        assert!(result.symbol_table.get_symbol("total").is_none());
+    }
+
+    #[test]
+    fn test_object_scopes_shadow_enclosing_names() {
+        let source = r#"
+            name: "global"
+            outer: {
+                name: "outer",
+                inner: { greeting: $"hi {name}", name: "inner" },
+                label: $"{name} label",
+            }
+            copy: name
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let greeting = result.symbol_table.get_symbol("outer.inner.greeting").unwrap();
+        assert!(greeting.dependencies.contains("outer.inner.name"));
+        let label = result.symbol_table.get_symbol("outer.label").unwrap();
+        assert!(label.dependencies.contains("outer.name"));
+        let copy = result.symbol_table.get_symbol("copy").unwrap();
+        assert!(copy.dependencies.contains("name"));
+    }
+
+    #[test]
+    fn test_object_field_named_after_enclosing_symbol() {
+        let source = r#"
+            version: "1.0"
+            status: { version: version, app: { version: version } }
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let field = result.symbol_table.get_symbol("status.version").unwrap();
+        assert!(field.dependencies.contains("version"));
+        let nested = result.symbol_table.get_symbol("status.app.version").unwrap();
+        assert!(nested.dependencies.contains("status.version"));
+    }
+
+    #[test]
+    fn test_promise_propagates_through_object_scopes() {
+        let source = r#"
+            response: {
+                user: fetch_user(1),
+                greeting: $"hello {user.name}",
+                static: "fixed",
+            }
+        "#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let greeting = result.symbol_table.get_symbol("response.greeting").unwrap();
+        assert!(matches!(greeting.symbol_type, Type::Promise(_)));
+        let fixed = result.symbol_table.get_symbol("response.static").unwrap();
+        assert_eq!(fixed.symbol_type, Type::String);
+        
+        // The object waits on its deferred fields
+        let response = result.symbol_table.get_symbol("response").unwrap();
+        assert!(matches!(response.symbol_type, Type::Promise(_)));
+    }
+
+    #[test]
+    fn test_object_scope_errors() {
+        let result = analyze_source("a: { b: c + 1 }");
+        assert!(matches!(result, Err(AnalyzerError::UndefinedSymbol { ref name, .. }) if name == "c"));
+        
+        let result = analyze_source("a: { b: c, c: b }");
+        assert!(matches!(result, Err(AnalyzerError::CircularDependency(_))));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_offending_expression() {
+        // The reference on line 3, not the declaration starting on line 1
+        let source = "order: {\n  id: 1,\n  total: price * 2\n}";
+        let Err(AnalyzerError::UndefinedSymbol { name, span }) = analyze_source(source) else { panic!("Expected an undefined symbol") };
+        assert_eq!(name, "price");
+        assert_eq!((span.line, &source[span.start..span.end]), (3, "price"));
+
+        let source = "a: 1\nb: a.shout()";
+        let Err(AnalyzerError::UnknownMethod { span, .. }) = analyze_source(source) else { panic!("Expected an unknown method") };
+        assert_eq!(&source[span.start..span.end], "a.shout()");
+    }
+
     #[test]
     fn test_nested_object_analysis() {
         let source = r#"{
@@ -957,13 +2804,13 @@ mod tests {
         let config_symbol = result.symbol_table.get_symbol("config").unwrap();
         assert!(matches!(config_symbol.symbol_type, Type::Object(_)));
         
-        // Check that nested object fields are created as symbols
-        assert!(result.symbol_table.get_symbol("database").is_some());
-        assert!(result.symbol_table.get_symbol("cache").is_some());
-        assert!(result.symbol_table.get_symbol("host").is_some());
-        assert!(result.symbol_table.get_symbol("port").is_some());
-        assert!(result.symbol_table.get_symbol("enabled").is_some());
-        assert!(result.symbol_table.get_symbol("ttl").is_some());
+        // Check that nested object fields are scoped under their parents
+        assert!(result.symbol_table.get_symbol("config.database").is_some());
+        assert!(result.symbol_table.get_symbol("config.cache").is_some());
+        assert!(result.symbol_table.get_symbol("config.database.host").is_some());
+        assert!(result.symbol_table.get_symbol("config.database.port").is_some());
+        assert!(result.symbol_table.get_symbol("config.cache.enabled").is_some());
+        assert!(result.symbol_table.get_symbol("config.cache.ttl").is_some());
     }
 
     #[test]
@@ -1026,17 +2873,17 @@ mod tests {
         let promise_any = Type::Promise(None);
         assert_eq!(format!("{}", promise_any), "promise<any>");
         
-        let object_type = Type::Object(HashMap::new());
+        let object_type = Type::Object(IndexMap::new());
         assert_eq!(format!("{}", object_type), "object");
     }
 
     #[test]
     fn test_symbol_creation() {
-        let symbol = Symbol::new("test_symbol".to_string(), Type::String, 42);
+        let symbol = Symbol::new("test_symbol".to_string(), Type::String, Span::new(3, 8, 42));
         
         assert_eq!(symbol.name, "test_symbol");
         assert_eq!(symbol.symbol_type, Type::String);
-        assert_eq!(symbol.definition_line, 42);
+        assert_eq!(symbol.definition_span.line, 42);
         assert!(!symbol.is_resolved);
         assert!(symbol.dependencies.is_empty());
         assert!(symbol.dependents.is_empty());
@@ -1047,7 +2894,7 @@ mod tests {
     fn test_analyzer_initialization() {
         let analyzer = SemanticAnalyzer::new();
         
-        assert_eq!(analyzer.current_scope, "global");
+        assert!(analyzer.scopes.is_empty());
         assert_eq!(analyzer.symbol_table.symbols().len(), 0);
         assert_eq!(analyzer.endpoints.len(), 0);
     }
@@ -1079,18 +2926,18 @@ mod tests {
         
         // Should have symbols for all the nested structure
         assert!(result.symbol_table.get_symbol("api").is_some());
-        assert!(result.symbol_table.get_symbol("version").is_some());
-        assert!(result.symbol_table.get_symbol("endpoints").is_some());
-        assert!(result.symbol_table.get_symbol("config").is_some());
-        assert!(result.symbol_table.get_symbol("timeout").is_some());
-        assert!(result.symbol_table.get_symbol("retries").is_some());
-        assert!(result.symbol_table.get_symbol("debug").is_some());
+        assert!(result.symbol_table.get_symbol("api.version").is_some());
+        assert!(result.symbol_table.get_symbol("api.endpoints").is_some());
+        assert!(result.symbol_table.get_symbol("api.config").is_some());
+        assert!(result.symbol_table.get_symbol("api.config.timeout").is_some());
+        assert!(result.symbol_table.get_symbol("api.config.retries").is_some());
+        assert!(result.symbol_table.get_symbol("api.config.debug").is_some());
         
         // Nested objects and arrays should have correct types
         let api_symbol = result.symbol_table.get_symbol("api").unwrap();
         assert!(matches!(api_symbol.symbol_type, Type::Object(_)));
         
-        let endpoints_symbol = result.symbol_table.get_symbol("endpoints").unwrap();
+        let endpoints_symbol = result.symbol_table.get_symbol("api.endpoints").unwrap();
         assert!(matches!(endpoints_symbol.symbol_type, Type::Array(_)));
     }
 
@@ -1102,14 +2949,14 @@ mod tests {
         
         let undefined_error = AnalyzerError::UndefinedSymbol { 
             name: "test".to_string(), 
-            line: 1 
+            span: Span::default(),
         };
         assert!(matches!(undefined_error, AnalyzerError::UndefinedSymbol { .. }));
         
         let type_error = AnalyzerError::TypeError { 
             expected: "number".to_string(), 
             found: "string".to_string(), 
-            line: 1 
+            span: Span::default(),
         };
         assert!(matches!(type_error, AnalyzerError::TypeError { .. }));
         
@@ -1117,7 +2964,7 @@ mod tests {
             name: "test".to_string(), 
             method: "GET".to_string(), 
             path: "/test".to_string(), 
-            line: 1 
+            span: Span::default(),
         };
         assert!(matches!(duplicate_error, AnalyzerError::DuplicateEndpoint { .. }));
     }
@@ -1132,8 +2979,8 @@ mod tests {
         let result = analyze_source(source).unwrap();
         
         // Test AnalyzedProgram structure
-        assert!(result.symbol_table.symbols().len() > 0);
-        assert!(result.resolution_order.len() > 0);
+        assert!(!result.symbol_table.symbols().is_empty());
+        assert!(!result.resolution_order.is_empty());
         assert_eq!(result.endpoints.len(), 0); // No endpoints in this simple test
         
         // Test that resolution order contains our symbols
@@ -1165,9 +3012,9 @@ mod tests {
         assert!(result.symbol_table.get_symbol("user2").is_some());
         assert!(result.symbol_table.get_symbol("user3").is_some());
         
-        // Should have multiple name and age symbols (from nested objects)
-        assert!(result.symbol_table.get_symbol("name").is_some());
-        assert!(result.symbol_table.get_symbol("age").is_some());
+        // Each nested object has its own name and age symbols
+        assert!(result.symbol_table.get_symbol("user2.name").is_some());
+        assert!(result.symbol_table.get_symbol("user3.age").is_some());
         
         // All symbols should be resolved
         for symbol in result.symbol_table.symbols().values() {
@@ -1233,12 +3080,12 @@ mod tests {
         
         // Verify all levels are analyzed
         assert!(result.symbol_table.get_symbol("level1").is_some());
-        assert!(result.symbol_table.get_symbol("level2").is_some());
-        assert!(result.symbol_table.get_symbol("level3").is_some());
-        assert!(result.symbol_table.get_symbol("level4").is_some());
-        assert!(result.symbol_table.get_symbol("deep_value").is_some());
+        assert!(result.symbol_table.get_symbol("level1.level2").is_some());
+        assert!(result.symbol_table.get_symbol("level1.level2.level3").is_some());
+        assert!(result.symbol_table.get_symbol("level1.level2.level3.level4").is_some());
+        assert!(result.symbol_table.get_symbol("level1.level2.level3.level4.deep_value").is_some());
         
-        let deep_symbol = result.symbol_table.get_symbol("deep_value").unwrap();
+        let deep_symbol = result.symbol_table.get_symbol("level1.level2.level3.level4.deep_value").unwrap();
         assert_eq!(deep_symbol.symbol_type, Type::String);
     }
 
@@ -1272,8 +3119,8 @@ mod tests {
         }
         
         // Should have symbols for nested object fields
-        assert!(result.symbol_table.get_symbol("name").is_some());
-        assert!(result.symbol_table.get_symbol("age").is_some());
+        assert!(result.symbol_table.get_symbol("users[0].name").is_some());
+        assert!(result.symbol_table.get_symbol("users[2].age").is_some());
     }
 
     #[test]
@@ -1294,21 +3141,21 @@ mod tests {
         
         // Should handle the complex nested structure
         assert!(result.symbol_table.get_symbol("metadata").is_some());
-        assert!(result.symbol_table.get_symbol("name").is_some());
-        assert!(result.symbol_table.get_symbol("version").is_some());
-        assert!(result.symbol_table.get_symbol("tags").is_some());
-        assert!(result.symbol_table.get_symbol("config").is_some());
-        assert!(result.symbol_table.get_symbol("enabled").is_some());
-        assert!(result.symbol_table.get_symbol("limits").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.name").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.version").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.tags").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.config").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.config.enabled").is_some());
+        assert!(result.symbol_table.get_symbol("metadata.config.limits").is_some());
         
         // Verify types are correct
-        let name_symbol = result.symbol_table.get_symbol("name").unwrap();
+        let name_symbol = result.symbol_table.get_symbol("metadata.name").unwrap();
         assert_eq!(name_symbol.symbol_type, Type::String);
         
-        let version_symbol = result.symbol_table.get_symbol("version").unwrap();
+        let version_symbol = result.symbol_table.get_symbol("metadata.version").unwrap();
         assert_eq!(version_symbol.symbol_type, Type::Number);
         
-        let enabled_symbol = result.symbol_table.get_symbol("enabled").unwrap();
+        let enabled_symbol = result.symbol_table.get_symbol("metadata.config.enabled").unwrap();
         assert_eq!(enabled_symbol.symbol_type, Type::Boolean);
     }
 
@@ -1325,7 +3172,7 @@ mod tests {
         // Verify that symbols track their definition lines
         for symbol in result.symbol_table.symbols().values() {
             // Lines should be > 0 (line numbers are 1-based)
-            assert!(symbol.definition_line > 0, 
+            assert!(symbol.definition_span.line > 0, 
                    "Symbol {} should have a valid line number", symbol.name);
         }
     }
@@ -1492,6 +3339,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_dotted_keys_cannot_shadow_nested_fields() {
+        let result = analyze_source(r#"config: { "a.b": 1, a: { b: 2 } }"#);
+        assert!(matches!(result, Err(AnalyzerError::DuplicateSymbol { name, .. }) if name == "config.a.b"));
+        
+        let result = analyze_source("a: { b: 2 }\n`a.b`: 1");
+        assert!(matches!(result, Err(AnalyzerError::DuplicateSymbol { name, .. }) if name == "a.b"));
+        
+        // Without the nested field the dotted key is an ordinary field
+        assert!(analyze_source(r#"config: { "a.b": 1, a: { c: 2 } }"#).is_ok());
+    }
+
     #[test]
     fn test_object_field_analysis_vs_top_level() {
         let source = r#"{
@@ -1546,6 +3405,190 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_template_string_dependencies() {
+        let source = r#"{
+            first: "Ada",
+            last: "Lovelace",
+            full: $"{first} {last}",
+            plain: $"no substitutions"
+        }"#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let full_symbol = result.symbol_table.get_symbol("full").unwrap();
+        assert_eq!(full_symbol.symbol_type, Type::String);
+        assert!(full_symbol.dependencies.contains("first"));
+        assert!(full_symbol.dependencies.contains("last"));
+        
+        let plain_symbol = result.symbol_table.get_symbol("plain").unwrap();
+        assert_eq!(plain_symbol.symbol_type, Type::String);
+        assert!(plain_symbol.dependencies.is_empty());
+        
+        let full_index = result.resolution_order.iter().position(|name| name == "full").unwrap();
+        let first_index = result.resolution_order.iter().position(|name| name == "first").unwrap();
+        assert!(first_index < full_index);
+    }
+
+    #[test]
+    fn test_template_string_becomes_promise() {
+        let source = r#"{
+            user: fetchUser(1),
+            message: $"Welcome back, {user.name}!"
+        }"#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        let message_symbol = result.symbol_table.get_symbol("message").unwrap();
+        assert!(matches!(message_symbol.symbol_type, Type::Promise(_)));
+        assert!(message_symbol.dependencies.contains("user"));
+    }
+
+    #[test]
+    fn test_http_call_depends_on_url_values() {
+        let result = analyze_source(r#"
+            cust: { zip: "98101" }
+            tax: //tax/rates?zip={cust.zip}
+        "#).unwrap();
+        
+        let tax = result.symbol_table.get_symbol("tax").unwrap();
+        assert!(tax.dependencies.contains("cust"));
+        assert!(matches!(tax.symbol_type, Type::Promise(_)));
+    }
+
+    #[test]
+    fn test_template_string_undefined_reference() {
+        let source = r#"{ message: $"Hello {nobody}" }"#;
+        
+        let result = analyze_source(source);
+        assert!(matches!(result, Err(AnalyzerError::UndefinedSymbol { name, .. }) if name == "nobody"));
+    }
+
+    #[test]
+    fn test_regex_method_types() {
+        let source = r#"{
+            second: "42",
+            pattern: /[0-5][0-9]/,
+            valid: /[0-5][0-9]/.test(second),
+            parts: /(\d)(\d)/.match(second),
+            masked: pattern.replace(second, "**")
+        }"#;
+        
+        let result = analyze_source(source).unwrap();
+        
+        assert_eq!(result.symbol_table.get_symbol("pattern").unwrap().symbol_type, Type::Regex);
+        assert_eq!(result.symbol_table.get_symbol("valid").unwrap().symbol_type, Type::Boolean);
+        assert_eq!(
+            result.symbol_table.get_symbol("parts").unwrap().symbol_type,
+            Type::Nullable(Box::new(Type::Array(Box::new(Type::String))))
+        );
+        
+        let masked_symbol = result.symbol_table.get_symbol("masked").unwrap();
+        assert!(masked_symbol.dependencies.contains("pattern"));
+        assert!(masked_symbol.dependencies.contains("second"));
+    }
+
+    #[test]
+    fn test_regex_method_errors() {
+        let result = analyze_source(r#"{ bad: /a/.exec("a") }"#);
+        assert!(matches!(result, Err(AnalyzerError::UnknownMethod { method, .. }) if method == "exec"));
+        
+        let result = analyze_source(r#"{ bad: /a/.replace("a") }"#);
+        assert!(matches!(result, Err(AnalyzerError::ArgumentCount { expected: 2, found: 1, .. })));
+        
+        let result = analyze_source(r#"{ bad: "text".test("a") }"#);
+        assert!(matches!(result, Err(AnalyzerError::UnknownMethod { receiver, .. }) if receiver == "string"));
+    }
+
+    #[test]
+    fn test_annotations_on_declarations() {
+        let result = analyze_source(r#"
+            @endpoint(GET, "/status")
+            status: { ok: true, version: version }
+            version: 2
+            prices: {
+                @test("tax is added") { equals: 11 }
+                @cache(60)
+                total: 10 + 1
+            }
+        "#).unwrap();
+        
+        let endpoint = &result.endpoints[0];
+        assert_eq!((endpoint.name.as_str(), endpoint.method.to_string(), endpoint.path.as_str()), ("status", "GET".to_string(), "/status"));
+        assert!(matches!(&endpoint.handler, AstNode::Identifier(name, _) if name == "status"));
+        
+        // A test without `expect` checks the declaration it is written on
+        let test = &result.tests[0];
+        assert_eq!(test.name, "tax is added");
+        assert!(matches!(&test.expect_expression, AstNode::Identifier(name, _) if name == "prices.total"));
+        assert_eq!(result.symbol_table.get_symbol("prices.total").unwrap().cache_seconds, Some(60));
+    }
+
+    #[test]
+    fn test_annotations_on_functions_and_destructures() {
+        let result = analyze_source(r#"
+            @endpoint(POST, "/echo")
+            @test("doubles") { expect: double(2), equals: 4 }
+            double(n): n * 2
+            @cache(30)
+            (low, high): (1, 9)
+        "#).unwrap();
+        
+        // A function endpoint is called with the request
+        let endpoint = &result.endpoints[0];
+        assert_eq!(endpoint.name, "double");
+        assert!(matches!(&endpoint.handler, AstNode::FunctionCall { name, arguments, .. }
+            if name == "double" && matches!(arguments.as_slice(), [AstNode::Identifier(argument, _)] if argument == "request")));
+        assert!(matches!(&result.tests[0].expect_expression, AstNode::FunctionCall { name, .. } if name == "double"));
+        
+        // The tuple holds the annotations, its names read from it
+        assert_eq!(result.symbol_table.get_symbol("(low, high)").unwrap().cache_seconds, Some(30));
+        assert_eq!(result.symbol_table.get_symbol("high").unwrap().cache_seconds, None);
+    }
+
+    #[test]
+    fn test_http_annotation_requests_the_url() {
+        let result = analyze_source(r#"
+            @http(POST) { body: { id: id } }
+            created: "https://api.example.com/items"
+            id: 7
+        "#).unwrap();
+        
+        let created = result.symbol_table.get_symbol("created").unwrap();
+        assert!(matches!(created.ast_node, Some(AstNode::HttpCall { method: HttpMethod::Post, .. })));
+        assert!(matches!(created.symbol_type, Type::Promise(_)));
+    }
+
+    #[test]
+    fn test_annotation_errors() {
+        let result = analyze_source("@route(\"/x\")\nx: 1");
+        assert!(matches!(result, Err(AnalyzerError::UnknownAnnotation { name, .. }) if name == "route"));
+        
+        let result = analyze_source("@endpoint(FETCH, \"/x\")\nx: 1");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "endpoint"));
+        
+        let result = analyze_source("@cache(-5)\nx: 1");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "cache"));
+        
+        let result = analyze_source("@test(\"empty\")\nx: 1");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "test"));
+        
+        let result = analyze_source("@cache(60)\nf(a): a");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "cache"));
+        
+        let result = analyze_source("@endpoint(GET, \"/x\")\nf(a, b): a");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "endpoint"));
+        
+        let result = analyze_source("@test(\"calls\") { equals: 1 }\nf(a): a");
+        assert!(matches!(result, Err(AnalyzerError::InvalidAnnotation { name, .. }) if name == "test"));
+        
+        let result = analyze_source("@route(\"/x\")\n(a, b): (1, 2)");
+        assert!(matches!(result, Err(AnalyzerError::UnknownAnnotation { name, .. }) if name == "route"));
+        
+        let result = analyze_source("@endpoint(GET, \"/x\")\nx: 1\n@endpoint(GET, \"/x\")\ny: 2");
+        assert!(matches!(result, Err(AnalyzerError::DuplicateEndpoint { name, method, .. }) if name == "y" && method == "GET"));
+    }
+
     #[test]
     fn test_memory_usage_with_deep_nesting() {
         // Create a simpler deeply nested structure that's easier to debug
@@ -1561,13 +3604,13 @@ mod tests {
             }
         }"#;
         
-        let result = analyze_source(&source).unwrap();
+        let result = analyze_source(source).unwrap();
         
         // Should handle deep nesting without issues
         assert!(result.symbol_table.get_symbol("root").is_some());
-        assert!(result.symbol_table.get_symbol("level_0").is_some());
-        assert!(result.symbol_table.get_symbol("level_1").is_some());
-        assert!(result.symbol_table.get_symbol("level_2").is_some());
-        assert!(result.symbol_table.get_symbol("deep_value").is_some());
+        assert!(result.symbol_table.get_symbol("root.level_0").is_some());
+        assert!(result.symbol_table.get_symbol("root.level_0.level_1").is_some());
+        assert!(result.symbol_table.get_symbol("root.level_0.level_1.level_2").is_some());
+        assert!(result.symbol_table.get_symbol("root.level_0.level_1.level_2.deep_value").is_some());
     }
 }
@@ -1,13 +1,14 @@
-use crate::analyzer::SemanticAnalyzer;
+use crate::analyzer::{AnalyzedProgram, SemanticAnalyzer};
 use crate::codegen::CodeGenerator;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::diagnostics::{Diagnostic, SourceMap};
+use crate::formatter::{format_source, FormatError};
+use crate::project::Project;
 use crate::runtime::{HttpRequest, LoafServer};
 use crate::test_runner::TestRunner;
 use anyhow::{anyhow, Result};
 use clap::{Parser as ClapParser, Subcommand};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 
 /// Command-line interface for the loaf language compiler
@@ -88,6 +89,17 @@ pub enum Commands {
         #[arg(long)]
         verbose: bool,
     },
+    
+    /// Format loaf source files in place
+    Fmt {
+        /// Input loaf source files
+        #[arg(short, long, required = true)]
+        input: Vec<PathBuf>,
+        
+        /// Only report files that are not formatted, failing if there are any
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 pub struct CliHandler;
@@ -115,18 +127,17 @@ impl CliHandler {
             Commands::Test { input, verbose } => {
                 self.handle_test(input, verbose).await
             }
+            Commands::Fmt { input, check } => {
+                self.handle_fmt(input, check).await
+            }
         }
     }
 
     async fn handle_compile(&self, input: PathBuf, output: Option<PathBuf>, pretty: bool) -> Result<()> {
         println!("Compiling {}...", input.display());
 
-        // Read source file
-        let source = fs::read_to_string(&input)
-            .map_err(|e| anyhow!("Failed to read input file: {}", e))?;
-
-        // Compile the source
-        let bytecode = self.compile_source(&source)?;
+        // Compile the file and everything it imports
+        let bytecode = self.compile_project(&input)?;
 
         // Determine output file
         let output_path = output.unwrap_or_else(|| {
@@ -156,11 +167,7 @@ impl CliHandler {
             println!("Running {}...", input.display());
         }
 
-        // Read and compile source
-        let source = fs::read_to_string(&input)
-            .map_err(|e| anyhow!("Failed to read input file: {}", e))?;
-
-        let bytecode = self.compile_source(&source)?;
+        let bytecode = self.compile_project(&input)?;
 
         if verbose {
             println!("Compilation completed:");
@@ -188,7 +195,7 @@ impl CliHandler {
                     crate::codegen::Value::Number(n) => println!("{}", n),
                     crate::codegen::Value::Boolean(b) => println!("{}", b),
                     crate::codegen::Value::Null => println!("null"),
-                    other => println!("{}", serde_json::to_string_pretty(&other)?),
+                    other => println!("{}", serde_json::to_string_pretty(&other.to_json())?),
                 }
             }
             None => {
@@ -204,11 +211,7 @@ impl CliHandler {
     async fn handle_server(&self, input: PathBuf, port: u16, verbose: bool) -> Result<()> {
         println!("Starting loaf HTTP server on port {}...", port);
 
-        // Read and compile source
-        let source = fs::read_to_string(&input)
-            .map_err(|e| anyhow!("Failed to read input file: {}", e))?;
-
-        let bytecode = self.compile_source(&source)?;
+        let bytecode = self.compile_project(&input)?;
 
         if verbose {
             println!("Compilation completed:");
@@ -260,21 +263,15 @@ impl CliHandler {
     async fn handle_info(&self, input: PathBuf, show_symbols: bool, show_deps: bool) -> Result<()> {
         println!("Analyzing {}...", input.display());
 
-        // Read source file
-        let source = fs::read_to_string(&input)
-            .map_err(|e| anyhow!("Failed to read input file: {}", e))?;
-
-        // Parse and analyze
-        let mut lexer = Lexer::new(&source);
-        let tokens = lexer.tokenize()?;
-        println!("Tokens: {}", tokens.len());
-
-        let mut parser = Parser::new(tokens);
-        let ast = parser.parse()?;
-        println!("AST nodes: {}", self.count_ast_nodes(&ast));
+        // Parse and analyze, reporting on whatever parsed if there are syntax errors
+        let project = self.load_project(&input)?;
+        if project.modules.len() > 1 {
+            println!("Files: {}", project.modules.len());
+        }
+        println!("Tokens: {}", project.modules.iter().map(|module| module.token_count).sum::<usize>());
+        println!("AST nodes: {}", project.modules.iter().map(|module| self.count_ast_nodes(&module.ast)).sum::<usize>());
 
-        let mut analyzer = SemanticAnalyzer::new();
-        let analyzed = analyzer.analyze(&ast)?;
+        let analyzed = self.analyze(&project)?;
         println!("Symbols: {}", analyzed.symbol_table.symbols().len());
         println!("Assignments: {}", analyzed.resolution_order.len());
 
@@ -299,7 +296,7 @@ impl CliHandler {
 
         // Check for endpoints
         let endpoints: Vec<_> = analyzed.endpoints.iter()
-            .map(|endpoint| format!("{:?} {} ({})", endpoint.method, endpoint.path, endpoint.name))
+            .map(|endpoint| format!("{} {} ({})", endpoint.method, endpoint.path, endpoint.name))
             .collect();
 
         if !endpoints.is_empty() {
@@ -335,22 +332,11 @@ impl CliHandler {
             println!("Running tests from {}...", input.display());
         }
 
-        // Read source file
-        let source = fs::read_to_string(&input)
-            .map_err(|e| anyhow!("Failed to read input file: {}", e))?;
-
         // Parse and analyze to extract test information
-        let mut lexer = Lexer::new(&source);
-        let tokens = lexer.tokenize()
-            .map_err(|e| anyhow!("Lexical analysis failed: {}", e))?;
-
-        let mut parser = Parser::new(tokens);
-        let ast = parser.parse()
-            .map_err(|e| anyhow!("Parsing failed: {}", e))?;
+        let project = self.load_project(&input)?;
+        self.check_syntax(&project)?;
 
-        let mut analyzer = SemanticAnalyzer::new();
-        let analyzed = analyzer.analyze(&ast)
-            .map_err(|e| anyhow!("Semantic analysis failed: {}", e))?;
+        let analyzed = self.analyze(&project)?;
 
         // Check if there are any tests
         if analyzed.tests.is_empty() {
@@ -393,21 +379,83 @@ impl CliHandler {
         Ok(())
     }
 
-    fn compile_source(&self, source: &str) -> Result<crate::codegen::BytecodeProgram> {
-        // Tokenize
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.tokenize()
-            .map_err(|e| anyhow!("Lexical analysis failed: {}", e))?;
+    async fn handle_fmt(&self, inputs: Vec<PathBuf>, check: bool) -> Result<()> {
+        let mut unformatted = 0;
+
+        for input in &inputs {
+            let source = fs::read_to_string(input)
+                .map_err(|e| anyhow!("Failed to read {}: {}", input.display(), e))?;
+            let formatted = match format_source(&source) {
+                Ok(formatted) => formatted,
+                Err(FormatError::Syntax(errors)) => {
+                    let mut sources = SourceMap::new();
+                    sources.add(input, &source);
+                    for error in &errors {
+                        eprintln!("{}\n", sources.render(&sources.syntax_diagnostic(0, error)));
+                    }
+                    return Err(anyhow!("{} syntax error(s) found", errors.len()));
+                }
+                Err(e) => return Err(anyhow!("Failed to format {}: {}", input.display(), e)),
+            };
 
-        // Parse
-        let mut parser = Parser::new(tokens);
-        let ast = parser.parse()
-            .map_err(|e| anyhow!("Parsing failed: {}", e))?;
+            if formatted == source {
+                continue;
+            }
+            if check {
+                println!("{} is not formatted", input.display());
+                unformatted += 1;
+            } else {
+                fs::write(input, formatted)?;
+                println!("Formatted {}", input.display());
+            }
+        }
 
-        // Analyze
+        if unformatted > 0 {
+            return Err(anyhow!("{} file(s) are not formatted", unformatted));
+        }
+        Ok(())
+    }
+
+    /// Parse the input file and every file it imports, printing every
+    /// syntax error and warning found along the way
+    fn load_project(&self, input: &Path) -> Result<Project> {
+        let project = Project::load(input)?;
+        for diagnostic in project.diagnostics() {
+            eprintln!("{}\n", project.sources.render(&diagnostic));
+        }
+        Ok(project)
+    }
+
+    /// Analyze a loaded project, printing the error or any warnings with the
+    /// source they point at
+    fn analyze(&self, project: &Project) -> Result<AnalyzedProgram> {
         let mut analyzer = SemanticAnalyzer::new();
-        let analyzed = analyzer.analyze(&ast)
-            .map_err(|e| anyhow!("Semantic analysis failed: {}", e))?;
+        let analyzed = analyzer.analyze_project(project).map_err(|error| {
+            eprintln!("{}\n", project.sources.render(&Diagnostic::from(&error)));
+            anyhow!("Semantic analysis failed")
+        })?;
+        for warning in analyzer.warnings() {
+            eprintln!("{}\n", project.sources.render(&Diagnostic::from(warning)));
+        }
+        Ok(analyzed)
+    }
+
+    /// Fail when any file has syntax errors, whose partial ASTs can be
+    /// inspected but not compiled
+    fn check_syntax(&self, project: &Project) -> Result<()> {
+        match project.errors().count() {
+            0 => Ok(()),
+            count => Err(anyhow!("{} syntax error(s) found", count)),
+        }
+    }
+
+    /// Compile a file and its imports into a single program
+    fn compile_project(&self, input: &Path) -> Result<crate::codegen::BytecodeProgram> {
+        let project = self.load_project(input)?;
+        self.check_syntax(&project)?;
+
+        // Analyze
+        let analyzed = self.analyze(&project)?;
 
         // Generate code
         let mut codegen = CodeGenerator::new();
@@ -422,16 +470,21 @@ impl CliHandler {
         
         match node {
             crate::parser::AstNode::Object { fields, .. } => {
-                for value in fields.values() {
+                for (key, value) in fields {
+                    if let crate::parser::ObjectKey::Computed(key) = key {
+                        count += self.count_ast_nodes(key);
+                    }
                     count += self.count_ast_nodes(value);
                 }
             }
-            crate::parser::AstNode::Array { elements, .. } => {
+            crate::parser::AstNode::Array { elements, .. } | crate::parser::AstNode::Tuple { elements, .. } => {
                 for element in elements {
                     count += self.count_ast_nodes(element);
                 }
             }
-            crate::parser::AstNode::Assignment { value, .. } => {
+            crate::parser::AstNode::Assignment { value, .. }
+            | crate::parser::AstNode::Destructure { value, .. }
+            | crate::parser::AstNode::Function { body: value, .. } => {
                 count += self.count_ast_nodes(value);
             }
             crate::parser::AstNode::Binary { left, right, .. } => {
@@ -441,6 +494,34 @@ impl CliHandler {
             crate::parser::AstNode::Unary { operand, .. } => {
                 count += self.count_ast_nodes(operand);
             }
+            crate::parser::AstNode::Template { parts, .. } => {
+                for part in parts {
+                    if let crate::parser::TemplatePart::Expression(expression) = part {
+                        count += self.count_ast_nodes(expression);
+                    }
+                }
+            }
+            crate::parser::AstNode::Conditional { condition, then_branch, else_branch, .. } => {
+                count += self.count_ast_nodes(condition);
+                count += self.count_ast_nodes(then_branch);
+                count += self.count_ast_nodes(else_branch);
+            }
+            crate::parser::AstNode::Match { subject, arms, .. } => {
+                count += self.count_ast_nodes(subject);
+                for arm in arms {
+                    count += self.count_ast_nodes(&arm.value);
+                }
+            }
+            crate::parser::AstNode::Index { object, index, .. } => {
+                count += self.count_ast_nodes(object);
+                count += self.count_ast_nodes(index);
+            }
+            crate::parser::AstNode::MethodCall { object, arguments, .. } => {
+                count += self.count_ast_nodes(object);
+                for argument in arguments {
+                    count += self.count_ast_nodes(argument);
+                }
+            }
             crate::parser::AstNode::HttpCall { body: Some(body), .. } => {
                 count += self.count_ast_nodes(body);
             }
@@ -472,6 +553,20 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_syntax_errors_stop_compilation() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "a: {{ b: ), c: 1 }}\nd: ~\ne: 2").unwrap();
+        
+        let cli_handler = CliHandler::new();
+        let result = cli_handler.handle_compile(temp_file.path().to_path_buf(), None, false).await;
+        assert_eq!(result.unwrap_err().to_string(), "2 syntax error(s) found");
+        
+        // Info still reports on the statements that parsed
+        let result = cli_handler.handle_info(temp_file.path().to_path_buf(), false, false).await;
+        assert!(result.is_ok());
+    }
+
     #[tokio::test]
     async fn test_run_command() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -489,7 +584,7 @@ mod tests {
     #[tokio::test]
     async fn test_info_command() {
         let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, r#"{{ "endpoint": "@endpoint:GET:/test" }}"#).unwrap();
+        writeln!(temp_file, "@endpoint(GET, \"/test\")\nendpoint: {{ ok: true }}").unwrap();
         
         let cli_handler = CliHandler::new();
         let result = cli_handler.handle_info(
@@ -504,7 +599,7 @@ mod tests {
     #[tokio::test]
     async fn test_server_command() {
         let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, r#"{{ "api": "@endpoint:GET:/hello" }}"#).unwrap();
+        writeln!(temp_file, "@endpoint(GET, \"/hello\")\napi: {{ message: \"hi\" }}").unwrap();
         
         let cli_handler = CliHandler::new();
         let result = cli_handler.handle_server(
@@ -516,6 +611,22 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_fmt_command() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "x:{{a:1,\nb:2}}").unwrap();
+        let path = temp_file.path().to_path_buf();
+        
+        let cli_handler = CliHandler::new();
+        let result = cli_handler.handle_fmt(vec![path.clone()], true).await;
+        assert_eq!(result.unwrap_err().to_string(), "1 file(s) are not formatted");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x:{a:1,\nb:2}");
+        
+        cli_handler.handle_fmt(vec![path.clone()], false).await.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x: {\n  a: 1,\n  b: 2,\n}\n");
+        assert!(cli_handler.handle_fmt(vec![path], true).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_test_command() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -1,8 +1,12 @@
 use crate::analyzer::AnalyzedProgram;
-use crate::parser::AstNode;
+use crate::lexer::Span;
+use crate::parser::{AstNode, ObjectKey, TemplatePart};
 use anyhow::{anyhow, Result};
+use bigdecimal::BigDecimal;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
 use uuid::Uuid;
 
 /// Bytecode instructions for the loaf runtime
@@ -18,14 +22,37 @@ pub enum Instruction {
     Subtract,
     Multiply,
     Divide,
+    Negate,
+    
+    // Comparison and boolean operations
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Not,
+    
+    // String operations
+    Concat(usize), // pop n values and join their string forms
     
     // Object/Array operations
     CreateObject,
     SetProperty(String),
+    SetEntry, // pop the value, then the key, and set it on the object below
     GetProperty(String),
+    GetPropertyOrNull(String), // `?.`: null instead of an error on non-objects
     CreateArray,
     AppendArray,
-    GetIndex(usize),
+    CreateTuple(usize), // pop n values into a tuple
+    GetIndex, // pop the index, then the array or object
+    
+    // Built-in method call on the receiver below the arguments
+    CallMethod {
+        method: String,
+        argument_count: usize,
+    },
     
     // Promise operations
     CreatePromise(String), // promise_id
@@ -38,16 +65,29 @@ pub enum Instruction {
         path: String,
         handler_id: String,
     },
-    HttpCall {
+    HttpCall { // pop the url
         method: String,
-        url: String,
         body: Option<String>,
     },
+    UrlEncode, // pop a value and push its percent-encoded string form
     
     // Control flow
     Jump(usize),
     JumpIfFalse(usize),
+    JumpIfSet(String, usize), // skip evaluating a deferred variable that already has a value
+    LoadCached(String, usize), // set the variable from the cache and jump, while the cached value is fresh
+    StoreCached { // keep the variable's value for later runs
+        name: String,
+        seconds: u64,
+    },
+    // Pop the arguments into a new frame and run a function, which returns its result on the stack
+    Call {
+        function: String,
+        argument_count: usize,
+    },
     Return,
+    // Pop a value and push whether it matches; a match sets the pattern's bindings
+    MatchPattern(ValuePattern),
     
     // Stack operations
     Duplicate,
@@ -55,24 +95,211 @@ pub enum Instruction {
     Swap,
 }
 
+/// A `match` arm's pattern with its literals as values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ValuePattern {
+    Any,
+    // Matches anything and stores it in the variable
+    Bind(String),
+    Value(Value),
+    // Numbers between the bounds, both included
+    Range {
+        start: Option<BigDecimal>,
+        end: Option<BigDecimal>,
+    },
+    // Strings the regex finds a match in
+    Regex {
+        pattern: String,
+        flags: String,
+    },
+    // Arrays or tuples with these elements, and any more if there is a rest
+    Array {
+        elements: Vec<ValuePattern>,
+        rest: Option<Box<ValuePattern>>,
+    },
+    // Objects with at least these fields
+    Object(IndexMap<String, ValuePattern>),
+}
+
 /// Runtime values that can be stored in bytecode
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
     Null,
     Boolean(bool),
-    Number(f64),
+    Number(#[serde(with = "bigdecimal::serde::json_num")] BigDecimal),
     String(String),
-    Object(HashMap<String, Value>),
+    // Fields in declaration order, which is also the order they serialize in
+    Object(IndexMap<String, Value>),
+    // An object with at least one key that is not a string
+    Map(#[serde(with = "map_entries")] IndexMap<KeyValue, Value>),
     Array(Vec<Value>),
+    Tuple(Vec<Value>),
+    Regex {
+        pattern: String,
+        flags: String,
+    },
     Promise(String), // promise_id
 }
 
+impl Value {
+    /// Convert to plain JSON, keeping numbers exact (`0.10` stays `0.10`)
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Boolean(b) => serde_json::Value::Bool(*b),
+            // With arbitrary precision any JSON number text is kept exactly,
+            // however large or small its exponent
+            Value::Number(n) => serde_json::Value::Number(serde_json::Number::from_str(&json_number_text(n))
+                .expect("json_number_text only writes JSON numbers")),
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Object(map) => serde_json::Value::Object(
+                map.iter().map(|(k, v)| (k.clone(), v.to_json())).collect(),
+            ),
+            // JSON keys are strings, so other keys are written as `[key, value]` pairs
+            Value::Map(map) => {
+                serde_json::Value::Array(map.iter()
+                    .map(|(key, value)| serde_json::Value::Array(vec![key.to_value().to_json(), value.to_json()]))
+                    .collect())
+            }
+            Value::Array(arr) | Value::Tuple(arr) => serde_json::Value::Array(arr.iter().map(Value::to_json).collect()),
+            Value::Regex { pattern, flags } => serde_json::Value::String(format!("/{}/{}", pattern, flags)),
+            // Unresolved promises have no value yet
+            Value::Promise(_) => serde_json::Value::Null,
+        }
+    }
+
+    /// The value as an object key, or `None` for values that cannot be keys
+    pub fn to_key(&self) -> Option<KeyValue> {
+        match self {
+            Value::Null => Some(KeyValue::Null),
+            Value::Boolean(b) => Some(KeyValue::Boolean(*b)),
+            Value::Number(n) => Some(KeyValue::Number(n.normalized())),
+            Value::String(s) => Some(KeyValue::String(s.clone())),
+            Value::Array(items) => items.iter().map(Value::to_key).collect::<Option<_>>().map(KeyValue::Array),
+            Value::Tuple(items) => items.iter().map(Value::to_key).collect::<Option<_>>().map(KeyValue::Tuple),
+            Value::Object(map) => KeyValue::object(map.iter().map(|(key, value)| Some((KeyValue::String(key.clone()), value.to_key()?)))),
+            Value::Map(map) => KeyValue::object(map.iter().map(|(key, value)| Some((key.clone(), value.to_key()?)))),
+            Value::Regex { .. } | Value::Promise(_) => None,
+        }
+    }
+
+    /// The value of a named field of an object or map
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(map) => map.get(name),
+            Value::Map(map) => map.get(&KeyValue::String(name.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The value stored under `key` in an object or map. A number also finds
+    /// the string key it is written as, since JSON objects only have string keys.
+    pub fn entry(&self, key: &KeyValue) -> Option<&Value> {
+        let exact = match (self, key) {
+            (Value::Object(map), KeyValue::String(name)) => map.get(name),
+            (Value::Map(map), key) => map.get(key),
+            _ => None,
+        };
+        match key {
+            KeyValue::Number(n) if exact.is_none() => self.field(&n.to_string()),
+            _ => exact,
+        }
+    }
+
+    /// The entries of an object or map, keyed by value
+    pub fn to_entries(&self) -> Option<IndexMap<KeyValue, Value>> {
+        match self {
+            Value::Object(map) => Some(map.iter().map(|(key, value)| (KeyValue::String(key.clone()), value.clone())).collect()),
+            Value::Map(map) => Some(map.clone()),
+            _ => None,
+        }
+    }
+
+    /// An object when every key is a string, otherwise a map
+    pub fn from_entries(entries: IndexMap<KeyValue, Value>) -> Value {
+        if entries.keys().all(|key| matches!(key, KeyValue::String(_))) {
+            Value::Object(entries.into_iter()
+                .filter_map(|(key, value)| match key {
+                    KeyValue::String(key) => Some((key, value)),
+                    _ => None,
+                })
+                .collect())
+        } else {
+            Value::Map(entries)
+        }
+    }
+}
+
+/// A decimal written as a JSON number. Exponents are always written as
+/// `E+n` or `E-n`, so a decimal always gets the same text.
+fn json_number_text(n: &BigDecimal) -> String {
+    let text = n.to_string();
+    match text.split_once(['e', 'E']) {
+        Some((digits, exponent)) if exponent.starts_with(['+', '-']) => format!("{}E{}", digits, exponent),
+        Some((digits, exponent)) => format!("{}E+{}", digits, exponent),
+        None => text,
+    }
+}
+
+/// A value used as an object key. Numbers are normalized and object entries
+/// sorted, so equal values make equal keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum KeyValue {
+    Null,
+    Boolean(bool),
+    Number(#[serde(with = "bigdecimal::serde::json_num")] BigDecimal),
+    String(String),
+    Array(Vec<KeyValue>),
+    Tuple(Vec<KeyValue>),
+    Object(Vec<(KeyValue, KeyValue)>),
+}
+
+impl KeyValue {
+    fn object(entries: impl Iterator<Item = Option<(KeyValue, KeyValue)>>) -> Option<KeyValue> {
+        let mut entries = entries.collect::<Option<Vec<_>>>()?;
+        entries.sort();
+        Some(KeyValue::Object(entries))
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            KeyValue::Null => Value::Null,
+            KeyValue::Boolean(b) => Value::Boolean(*b),
+            KeyValue::Number(n) => Value::Number(n.clone()),
+            KeyValue::String(s) => Value::String(s.clone()),
+            KeyValue::Array(items) => Value::Array(items.iter().map(KeyValue::to_value).collect()),
+            KeyValue::Tuple(items) => Value::Tuple(items.iter().map(KeyValue::to_value).collect()),
+            KeyValue::Object(entries) => {
+                let map: IndexMap<KeyValue, Value> = entries.iter().map(|(key, value)| (key.clone(), value.to_value())).collect();
+                Value::from_entries(map)
+            }
+        }
+    }
+}
+
+/// Maps serialize as a list of `[key, value]` pairs since keys are not strings
+mod map_entries {
+    use super::{KeyValue, Value};
+    use indexmap::IndexMap;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(map: &IndexMap<KeyValue, Value>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IndexMap<KeyValue, Value>, D::Error> {
+        Ok(Vec::<(KeyValue, Value)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
 /// Compiled bytecode program
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BytecodeProgram {
     pub instructions: Vec<Instruction>,
     pub constants: Vec<Value>,
-    pub endpoints: HashMap<String, EndpointInfo>,
+    pub endpoints: IndexMap<String, EndpointInfo>,
+    #[serde(default)]
+    pub functions: IndexMap<String, FunctionInfo>,
     pub entry_point: usize,
 }
 
@@ -85,13 +312,20 @@ pub struct EndpointInfo {
     pub handler_end: usize,
 }
 
+/// A function's code, called with its arguments bound to `parameters`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionInfo {
+    pub parameters: Vec<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
 /// Code generator for converting analyzed AST to bytecode
 pub struct CodeGenerator {
     instructions: Vec<Instruction>,
     constants: Vec<Value>,
-    endpoints: HashMap<String, EndpointInfo>,
-    label_counter: usize,
-    variable_locations: HashMap<String, usize>,
+    endpoints: IndexMap<String, EndpointInfo>,
+    functions: IndexMap<String, FunctionInfo>,
 }
 
 impl CodeGenerator {
@@ -99,9 +333,8 @@ impl CodeGenerator {
         Self {
             instructions: Vec::new(),
             constants: Vec::new(),
-            endpoints: HashMap::new(),
-            label_counter: 0,
-            variable_locations: HashMap::new(),
+            endpoints: IndexMap::new(),
+            functions: IndexMap::new(),
         }
     }
 
@@ -112,126 +345,165 @@ impl CodeGenerator {
         // Process assignments in dependency order
         for symbol_name in &program.resolution_order {
             if let Some(symbol) = program.symbol_table.symbols().get(symbol_name) {
-                if let Some(ast_node) = &symbol.ast_node {
-                    let assignment = AstNode::Assignment {
-                        name: symbol.name.clone(),
-                        value: Box::new(ast_node.clone()),
-                        line: symbol.definition_line,
-                    };
-                    self.generate_assignment(&assignment, program.symbol_table.symbols())?;
+                // Deferred symbols are evaluated by the branch that needs them
+                if !symbol.is_deferred {
+                    self.generate_symbol(symbol, program.symbol_table.symbols())?;
                 }
             }
         }
 
-        // Generate endpoint registrations  
-        for endpoint in &program.endpoints {
-            let handler_id = Uuid::new_v4().to_string();
-            let handler_start = self.instructions.len();
-            
-            // Generate endpoint handler code
-            self.generate_expression(&endpoint.handler, program.symbol_table.symbols())?;
-            self.emit(Instruction::Return);
-            let handler_end = self.instructions.len();
-
-            // Register the endpoint
-            self.emit(Instruction::RegisterEndpoint {
-                method: format!("{:?}", endpoint.method),
-                path: endpoint.path.clone(),
-                handler_id: handler_id.clone(),
-            });
-
-            self.endpoints.insert(handler_id, EndpointInfo {
-                method: format!("{:?}", endpoint.method),
-                path: endpoint.path.clone(),
-                handler_start,
-                handler_end,
-            });
+        // Function bodies only run when called, so the top level jumps over them
+        if !program.functions.is_empty() {
+            let skip_functions = self.instructions.len();
+            self.emit(Instruction::Jump(0));
+            for function in &program.functions {
+                self.generate_function(function, program.symbol_table.symbols())?;
+            }
+            self.instructions[skip_functions] = Instruction::Jump(self.instructions.len());
+        }
+
+        // Each request runs its handler in a heap of its own, so a handler
+        // computes everything its value needs and the top level skips it
+        if !program.endpoints.is_empty() {
+            let skip_handlers = self.instructions.len();
+            self.emit(Instruction::Jump(0));
+            let mut handlers = Vec::new();
+            for endpoint in &program.endpoints {
+                let handler_start = self.instructions.len();
+                for symbol_name in Self::handler_symbols(&endpoint.name, program) {
+                    self.generate_symbol(&program.symbol_table.symbols()[symbol_name], program.symbol_table.symbols())?;
+                }
+                self.generate_expression(&endpoint.handler, program.symbol_table.symbols())?;
+                self.emit(Instruction::Return);
+                handlers.push((handler_start, self.instructions.len()));
+            }
+            self.instructions[skip_handlers] = Instruction::Jump(self.instructions.len());
+
+            for (endpoint, (handler_start, handler_end)) in program.endpoints.iter().zip(handlers) {
+                let handler_id = Uuid::new_v4().to_string();
+                self.emit(Instruction::RegisterEndpoint {
+                    method: endpoint.method.to_string(),
+                    path: endpoint.path.clone(),
+                    handler_id: handler_id.clone(),
+                });
+
+                self.endpoints.insert(handler_id, EndpointInfo {
+                    method: endpoint.method.to_string(),
+                    path: endpoint.path.clone(),
+                    handler_start,
+                    handler_end,
+                });
+            }
         }
 
         Ok(BytecodeProgram {
             instructions: self.instructions.clone(),
             constants: self.constants.clone(),
             endpoints: self.endpoints.clone(),
+            functions: self.functions.clone(),
             entry_point,
         })
     }
 
-    fn generate_assignment(&mut self, assignment: &AstNode, symbols: &HashMap<String, crate::analyzer::Symbol>) -> Result<()> {
-        if let AstNode::Assignment { name, value, .. } = assignment {
-            // Generate code for the value expression
-            self.generate_expression(value, symbols)?;
-            
-            // Store the result in the variable
-            self.emit(Instruction::StoreVariable(name.clone()));
+    /// A function body evaluates its own symbols in its frame, then returns its result
+    fn generate_function(&mut self, function: &crate::analyzer::FunctionInfo, globals: &IndexMap<String, crate::analyzer::Symbol>) -> Result<()> {
+        let mut symbols = globals.clone();
+        symbols.extend(function.symbol_table.symbols().iter().map(|(name, symbol)| (name.clone(), symbol.clone())));
+        
+        let start = self.instructions.len();
+        for symbol_name in &function.resolution_order {
+            let Some(symbol) = function.symbol_table.get_symbol(symbol_name) else { continue };
+            // Parameters have no value of their own, the call binds them
+            if !symbol.is_deferred {
+                self.generate_symbol(symbol, &symbols)?;
+            }
         }
+        self.generate_expression(&AstNode::Identifier(function.result.clone(), Span::default()), &symbols)?;
+        self.emit(Instruction::Return);
+        
+        self.functions.insert(function.name.clone(), FunctionInfo {
+            parameters: function.parameters.clone(),
+            start,
+            end: self.instructions.len(),
+        });
         Ok(())
     }
 
-    fn generate_expression(&mut self, expr: &AstNode, symbols: &HashMap<String, crate::analyzer::Symbol>) -> Result<()> {
+    /// The eager symbols an endpoint's value needs, in resolution order.
+    /// Deferred ones are evaluated by the branches that use them.
+    fn handler_symbols<'a>(name: &str, program: &'a AnalyzedProgram) -> Vec<&'a String> {
+        let symbols = program.symbol_table.symbols();
+        let mut needed = HashSet::from([name]);
+        let mut pending = vec![name];
+        while let Some(current) = pending.pop() {
+            let Some(symbol) = symbols.get(current) else { continue };
+            for dependency in &symbol.dependencies {
+                if needed.insert(dependency.as_str()) {
+                    pending.push(dependency);
+                }
+            }
+        }
+        
+        program.resolution_order.iter()
+            .filter(|symbol_name| needed.contains(symbol_name.as_str()))
+            .filter(|symbol_name| symbols.get(*symbol_name).is_some_and(|symbol| !symbol.is_deferred && symbol.ast_node.is_some()))
+            .collect()
+    }
+
+    /// Compute a symbol into its variable, reusing a cached value when the
+    /// symbol has a `@cache` lifetime
+    fn generate_symbol(&mut self, symbol: &crate::analyzer::Symbol, symbols: &IndexMap<String, crate::analyzer::Symbol>) -> Result<()> {
+        let Some(ast_node) = &symbol.ast_node else { return Ok(()) };
+        
+        let guard = self.instructions.len();
+        if symbol.cache_seconds.is_some() {
+            self.emit(Instruction::LoadCached(symbol.name.clone(), 0));
+        }
+        self.generate_expression(ast_node, symbols)?;
+        self.emit(Instruction::StoreVariable(symbol.name.clone()));
+        if let Some(seconds) = symbol.cache_seconds {
+            self.emit(Instruction::StoreCached { name: symbol.name.clone(), seconds });
+            self.instructions[guard] = Instruction::LoadCached(symbol.name.clone(), self.instructions.len());
+        }
+        
+        Ok(())
+    }
+
+    fn generate_expression(&mut self, expr: &AstNode, symbols: &IndexMap<String, crate::analyzer::Symbol>) -> Result<()> {
         match expr {
-            AstNode::String(value) => {
+            AstNode::String(value, _) => {
                 // Check for special annotations
                 if value.starts_with("@promise:") {
                     let promise_id = value.strip_prefix("@promise:").unwrap_or(value);
                     self.emit(Instruction::CreatePromise(promise_id.to_string()));
-                } else if value.starts_with("@endpoint:") {
-                    // Parse endpoint annotation: @endpoint:METHOD:PATH
-                    let parts: Vec<&str> = value.strip_prefix("@endpoint:").unwrap_or("").split(':').collect();
-                    if parts.len() >= 2 {
-                        let method = parts[0].to_string();
-                        let path = parts[1].to_string();
-                        let endpoint_key = format!("{}:{}", method, path);
-                        
-                        self.emit(Instruction::RegisterEndpoint {
-                            method: method.clone(),
-                            path: path.clone(),
-                            handler_id: "default".to_string(),
-                        });
-                        
-                        // Add to endpoints map
-                        self.endpoints.insert(endpoint_key, crate::codegen::EndpointInfo {
-                            method,
-                            path,
-                            handler_start: self.instructions.len(),
-                            handler_end: self.instructions.len(),
-                        });
-                    } else {
-                        self.emit(Instruction::LoadConstant(Value::String(value.clone())));
-                    }
-                } else if value.starts_with("@http:") {
-                    // Parse HTTP call annotation: @http:METHOD:URL
-                    let parts: Vec<&str> = value.strip_prefix("@http:").unwrap_or("").split(':').collect();
-                    if parts.len() >= 2 {
-                        let method = parts[0].to_string();
-                        let url = parts[1..].join(":");  // Rejoin in case URL contains colons
-                        self.emit(Instruction::HttpCall {
-                            method,
-                            url,
-                            body: None,
-                        });
-                    } else {
-                        self.emit(Instruction::LoadConstant(Value::String(value.clone())));
-                    }
                 } else {
                     self.emit(Instruction::LoadConstant(Value::String(value.clone())));
                 }
             }
 
-            AstNode::Number(value) => {
-                self.emit(Instruction::LoadConstant(Value::Number(*value)));
+            AstNode::Number(value, _) => {
+                self.emit(Instruction::LoadConstant(Value::Number(value.clone())));
             }
 
-            AstNode::Boolean(value) => {
+            AstNode::Boolean(value, _) => {
                 self.emit(Instruction::LoadConstant(Value::Boolean(*value)));
             }
 
-            AstNode::Null => {
+            AstNode::Null(_) => {
                 self.emit(Instruction::LoadConstant(Value::Null));
             }
 
-            AstNode::Identifier(name) => {
+            AstNode::Regex { pattern, flags, .. } => {
+                let value = self.register_regex(pattern, flags);
+                self.emit(Instruction::LoadConstant(value));
+            }
+
+            AstNode::Identifier(name, _) => {
                 // Check if this variable is a promise
                 if let Some(symbol) = symbols.get(name) {
+                    if symbol.is_deferred {
+                        self.generate_deferred(symbol, symbols)?;
+                    }
                     match &symbol.symbol_type {
                         crate::analyzer::Type::Promise(_) => {
                             self.emit(Instruction::AwaitPromise(name.clone()));
@@ -245,6 +517,45 @@ impl CodeGenerator {
                 }
             }
 
+            AstNode::Template { parts, .. } => {
+                // Push every part, then join them all with a single instruction
+                let mut count = 0;
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(text) => {
+                            self.emit(Instruction::LoadConstant(Value::String(text.clone())));
+                        }
+                        TemplatePart::Expression(expression) => {
+                            self.generate_expression(expression, symbols)?;
+                        }
+                    }
+                    count += 1;
+                }
+                
+                match parts.as_slice() {
+                    [] => self.emit(Instruction::LoadConstant(Value::String(String::new()))),
+                    [TemplatePart::Text(_)] => {}
+                    _ => self.emit(Instruction::Concat(count)),
+                }
+            }
+
+            AstNode::Binary { left, operator: crate::parser::BinaryOp::Coalesce, right, .. } => {
+                // Keep the left value unless it is null; the fallback only runs when needed
+                self.generate_expression(left, symbols)?;
+                self.emit(Instruction::Duplicate);
+                self.emit(Instruction::LoadConstant(Value::Null));
+                self.emit(Instruction::NotEqual);
+                let jump_to_fallback = self.instructions.len();
+                self.emit(Instruction::JumpIfFalse(0));
+                let jump_to_end = self.instructions.len();
+                self.emit(Instruction::Jump(0));
+                
+                self.instructions[jump_to_fallback] = Instruction::JumpIfFalse(self.instructions.len());
+                self.emit(Instruction::Pop);
+                self.generate_expression(right, symbols)?;
+                self.instructions[jump_to_end] = Instruction::Jump(self.instructions.len());
+            }
+
             AstNode::Binary { left, operator, right, .. } => {
                 self.generate_expression(left, symbols)?;
                 self.generate_expression(right, symbols)?;
@@ -254,18 +565,91 @@ impl CodeGenerator {
                     crate::parser::BinaryOp::Subtract => self.emit(Instruction::Subtract),
                     crate::parser::BinaryOp::Multiply => self.emit(Instruction::Multiply),
                     crate::parser::BinaryOp::Divide => self.emit(Instruction::Divide),
-                    _ => return Err(anyhow!("Unsupported binary operator: {:?}", operator)),
+                    crate::parser::BinaryOp::Equal => self.emit(Instruction::Equal),
+                    crate::parser::BinaryOp::NotEqual => self.emit(Instruction::NotEqual),
+                    crate::parser::BinaryOp::Less => self.emit(Instruction::Less),
+                    crate::parser::BinaryOp::LessEqual => self.emit(Instruction::LessEqual),
+                    crate::parser::BinaryOp::Greater => self.emit(Instruction::Greater),
+                    crate::parser::BinaryOp::GreaterEqual => self.emit(Instruction::GreaterEqual),
+                    crate::parser::BinaryOp::And => self.emit(Instruction::And),
+                    crate::parser::BinaryOp::Coalesce => unreachable!("coalesce is generated lazily above"),
+                }
+            }
+
+            AstNode::Unary { operator, operand, .. } => {
+                self.generate_expression(operand, symbols)?;
+                
+                match operator {
+                    crate::parser::UnaryOp::Negate => self.emit(Instruction::Negate),
+                    crate::parser::UnaryOp::Not => self.emit(Instruction::Not),
+                }
+            }
+
+            AstNode::Match { subject, arms, .. } => {
+                // Each arm tests a copy of the subject and drops it once chosen
+                self.generate_expression(subject, symbols)?;
+                let mut jumps_to_end = Vec::new();
+                for arm in arms {
+                    let pattern = self.match_pattern(&arm.pattern)?;
+                    self.emit(Instruction::Duplicate);
+                    self.emit(Instruction::MatchPattern(pattern));
+                    let jump_to_next = self.instructions.len();
+                    self.emit(Instruction::JumpIfFalse(0));
+                    
+                    self.emit(Instruction::Pop);
+                    self.generate_expression(&arm.value, symbols)?;
+                    jumps_to_end.push(self.instructions.len());
+                    self.emit(Instruction::Jump(0));
+                    self.instructions[jump_to_next] = Instruction::JumpIfFalse(self.instructions.len());
+                }
+                
+                // The analyzer makes sure some arm matches, unless a value has the wrong type
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::LoadConstant(Value::Null));
+                for jump in jumps_to_end {
+                    self.instructions[jump] = Instruction::Jump(self.instructions.len());
                 }
             }
 
+            AstNode::Conditional { condition, then_branch, else_branch, .. } => {
+                self.generate_expression(condition, symbols)?;
+                let jump_to_else = self.instructions.len();
+                self.emit(Instruction::JumpIfFalse(0));
+                
+                self.generate_expression(then_branch, symbols)?;
+                let jump_to_end = self.instructions.len();
+                self.emit(Instruction::Jump(0));
+                
+                self.instructions[jump_to_else] = Instruction::JumpIfFalse(self.instructions.len());
+                self.generate_expression(else_branch, symbols)?;
+                self.instructions[jump_to_end] = Instruction::Jump(self.instructions.len());
+            }
+
             AstNode::Object { fields, .. } => {
+                // Fields are set in declaration order, which the object keeps
                 self.emit(Instruction::CreateObject);
                 for (key, value) in fields {
-                    self.generate_expression(value, symbols)?;
-                    self.emit(Instruction::SetProperty(key.clone()));
+                    match key {
+                        ObjectKey::Name(name) => {
+                            self.generate_expression(value, symbols)?;
+                            self.emit(Instruction::SetProperty(name.clone()));
+                        }
+                        ObjectKey::Computed(key) => {
+                            self.generate_expression(key, symbols)?;
+                            self.generate_expression(value, symbols)?;
+                            self.emit(Instruction::SetEntry);
+                        }
+                    }
                 }
             }
 
+            AstNode::Tuple { elements, .. } => {
+                for element in elements {
+                    self.generate_expression(element, symbols)?;
+                }
+                self.emit(Instruction::CreateTuple(elements.len()));
+            }
+
             AstNode::Array { elements, .. } => {
                 self.emit(Instruction::CreateArray);
                 for element in elements {
@@ -283,30 +667,62 @@ impl CodeGenerator {
             }
 
             AstNode::HttpCall { method, url, body, .. } => {
-                let body_str = if let Some(_body_node) = body {
-                    // For now, we'll serialize the body as JSON
-                    // In a full implementation, this would be more sophisticated
-                    Some("{}".to_string())
-                } else {
-                    None
-                };
-
-                // For now, convert URL to string - in full implementation would evaluate it
-                let url_str = format!("{:?}", url); // Simple conversion for demo
+                // For now, we'll serialize the body as JSON
+                // In a full implementation, this would be more sophisticated
+                let body_str = body.as_ref().map(|_body_node| "{}".to_string());
+
+                // Build the url at runtime, encoding each embedded value
+                match url.as_ref() {
+                    AstNode::Template { parts, .. } => {
+                        for part in parts {
+                            match part {
+                                TemplatePart::Text(text) => {
+                                    self.emit(Instruction::LoadConstant(Value::String(text.clone())));
+                                }
+                                TemplatePart::Expression(expression) => {
+                                    self.generate_expression(expression, symbols)?;
+                                    self.emit(Instruction::UrlEncode);
+                                }
+                            }
+                        }
+                        self.emit(Instruction::Concat(parts.len()));
+                    }
+                    other => self.generate_expression(other, symbols)?,
+                }
 
                 self.emit(Instruction::HttpCall {
-                    method: format!("{:?}", method),
-                    url: url_str,
+                    method: method.to_string(),
                     body: body_str,
                 });
             }
 
-            AstNode::MemberAccess { object, property, .. } => {
+            AstNode::MemberAccess { object, property, null_safe, .. } => {
                 // Generate code for the object
                 self.generate_expression(object, symbols)?;
                 
                 // Emit instruction to get the property
-                self.emit(Instruction::GetProperty(property.clone()));
+                if *null_safe {
+                    self.emit(Instruction::GetPropertyOrNull(property.clone()));
+                } else {
+                    self.emit(Instruction::GetProperty(property.clone()));
+                }
+            }
+
+            AstNode::Index { object, index, .. } => {
+                self.generate_expression(object, symbols)?;
+                self.generate_expression(index, symbols)?;
+                self.emit(Instruction::GetIndex);
+            }
+
+            AstNode::MethodCall { object, method, arguments, .. } => {
+                self.generate_expression(object, symbols)?;
+                for arg in arguments {
+                    self.generate_expression(arg, symbols)?;
+                }
+                self.emit(Instruction::CallMethod {
+                    method: method.clone(),
+                    argument_count: arguments.len(),
+                });
             }
 
             AstNode::FunctionCall { name, arguments, .. } => {
@@ -315,7 +731,15 @@ impl CodeGenerator {
                     self.generate_expression(arg, symbols)?;
                 }
                 
-                // Generate a promise for the function call
+                if symbols.get(name).is_some_and(|symbol| matches!(symbol.symbol_type, crate::analyzer::Type::Function(_))) {
+                    self.emit(Instruction::Call {
+                        function: name.clone(),
+                        argument_count: arguments.len(),
+                    });
+                    return Ok(());
+                }
+                
+                // Generate a promise for an external function call
                 let promise_id = format!("{}:{}", name, Uuid::new_v4());
                 self.emit(Instruction::CreatePromise(promise_id));
             }
@@ -327,43 +751,61 @@ impl CodeGenerator {
         Ok(())
     }
 
-    fn convert_literal_to_value(&self, literal: &serde_json::Value) -> Result<Value> {
-        match literal {
-            serde_json::Value::Null => Ok(Value::Null),
-            serde_json::Value::Bool(b) => Ok(Value::Boolean(*b)),
-            serde_json::Value::Number(n) => {
-                if let Some(f) = n.as_f64() {
-                    Ok(Value::Number(f))
-                } else {
-                    Err(anyhow!("Invalid number format"))
-                }
-            }
-            serde_json::Value::String(s) => Ok(Value::String(s.clone())),
-            serde_json::Value::Array(arr) => {
-                let mut values = Vec::new();
-                for item in arr {
-                    values.push(self.convert_literal_to_value(item)?);
-                }
-                Ok(Value::Array(values))
-            }
-            serde_json::Value::Object(obj) => {
-                let mut map = HashMap::new();
-                for (key, value) in obj {
-                    map.insert(key.clone(), self.convert_literal_to_value(value)?);
-                }
-                Ok(Value::Object(map))
-            }
+    /// Evaluate a deferred symbol in place, the first time any branch needs it
+    fn generate_deferred(&mut self, symbol: &crate::analyzer::Symbol, symbols: &IndexMap<String, crate::analyzer::Symbol>) -> Result<()> {
+        if symbol.ast_node.is_none() {
+            return Err(anyhow!("Deferred symbol '{}' has no value", symbol.name));
         }
+        
+        let guard = self.instructions.len();
+        self.emit(Instruction::JumpIfSet(symbol.name.clone(), 0));
+        self.generate_symbol(symbol, symbols)?;
+        self.instructions[guard] = Instruction::JumpIfSet(symbol.name.clone(), self.instructions.len());
+        
+        Ok(())
     }
 
-    fn emit(&mut self, instruction: Instruction) {
-        self.instructions.push(instruction);
+    /// Register each distinct regex once so the VM can compile it up front
+    fn register_regex(&mut self, pattern: &str, flags: &str) -> Value {
+        let value = Value::Regex { pattern: pattern.to_string(), flags: flags.to_string() };
+        if !self.constants.iter().any(|c| matches!(c, Value::Regex { pattern: p, flags: f } if p == pattern && f == flags)) {
+            self.constants.push(value.clone());
+        }
+        value
+    }
+
+    fn match_pattern(&mut self, pattern: &crate::parser::Pattern) -> Result<ValuePattern> {
+        use crate::parser::Pattern as Source;
+        Ok(match pattern {
+            Source::Wildcard => ValuePattern::Any,
+            Source::Binding(name, _) => ValuePattern::Bind(name.clone()),
+            Source::Literal(literal) => ValuePattern::Value(match literal {
+                AstNode::String(value, _) => Value::String(value.clone()),
+                AstNode::Number(value, _) => Value::Number(value.clone()),
+                AstNode::Boolean(value, _) => Value::Boolean(*value),
+                AstNode::Null(_) => Value::Null,
+                _ => return Err(anyhow!("Unsupported literal in a match pattern")),
+            }),
+            Source::Range { start, end } => ValuePattern::Range { start: start.clone(), end: end.clone() },
+            Source::Regex { pattern, flags } => {
+                self.register_regex(pattern, flags);
+                ValuePattern::Regex { pattern: pattern.clone(), flags: flags.clone() }
+            }
+            Source::Array { elements, rest } => ValuePattern::Array {
+                elements: elements.iter().map(|element| self.match_pattern(element)).collect::<Result<_>>()?,
+                rest: match rest {
+                    Some(rest) => Some(Box::new(self.match_pattern(rest)?)),
+                    None => None,
+                },
+            },
+            Source::Object(fields) => ValuePattern::Object(fields.iter()
+                .map(|(field, value)| Ok((field.clone(), self.match_pattern(value)?)))
+                .collect::<Result<_>>()?),
+        })
     }
 
-    fn next_label(&mut self) -> usize {
-        let label = self.label_counter;
-        self.label_counter += 1;
-        label
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
     }
 }
 
@@ -423,7 +865,7 @@ mod tests {
     #[test]
     fn test_endpoint_codegen() {
         let source = r#"{
-            "handler": "@endpoint:GET:/api/test",
+            @endpoint(GET, "/api/test")
             "response": { "message": "Hello World" }
         }"#;
         
@@ -444,7 +886,100 @@ mod tests {
             matches!(inst, Instruction::RegisterEndpoint { .. })
         });
         assert!(has_endpoint_instruction);
-        assert!(!bytecode.endpoints.is_empty());
+        let endpoint = bytecode.endpoints.values().next().unwrap();
+        assert_eq!((endpoint.method.as_str(), endpoint.path.as_str()), ("GET", "/api/test"));
+    }
+
+    #[test]
+    fn test_template_codegen() {
+        let source = r#"{ name: "Ada", greeting: $"Hello {name}, {name}!", fixed: $"plain" }"#;
+        
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+        
+        let mut analyzer = SemanticAnalyzer::new();
+        let analyzed = analyzer.analyze(&ast).unwrap();
+        
+        let mut codegen = CodeGenerator::new();
+        let bytecode = codegen.generate(&analyzed).unwrap();
+        
+        // One concatenation for the whole template instead of a chain of adds
+        let concats: Vec<_> = bytecode.instructions.iter()
+            .filter(|inst| matches!(inst, Instruction::Concat(_)))
+            .collect();
+        assert_eq!(concats.len(), 1);
+        assert!(matches!(concats[0], Instruction::Concat(5)));
+        assert!(!bytecode.instructions.iter().any(|inst| matches!(inst, Instruction::Add)));
+    }
+
+    #[test]
+    fn test_key_values_are_normalized() {
+        let key = |value: Value| value.to_key().unwrap();
+        let number = |n: &str| Value::Number(BigDecimal::from_str(n).unwrap());
+        
+        assert_eq!(key(Value::Array(vec![number("2.0")])), key(Value::Array(vec![number("2")])));
+        assert!(Value::Regex { pattern: "a".to_string(), flags: String::new() }.to_key().is_none());
+        
+        // Maps survive a round trip through the bytecode format
+        let map = Value::Map(IndexMap::from([(key(number("1")), Value::String("one".to_string()))]));
+        let json = serde_json::to_string(&map).unwrap();
+        let Value::Map(restored) = serde_json::from_str::<Value>(&json).unwrap() else { panic!("Expected map") };
+        assert!(matches!(restored.get(&key(number("1"))), Some(Value::String(s)) if s == "one"));
+    }
+
+    #[test]
+    fn test_decimals_convert_to_exact_json_numbers() {
+        let json = |n: &str| serde_json::to_string(&Value::Number(BigDecimal::from_str(n).unwrap()).to_json()).unwrap();
+        
+        assert_eq!(json("0.10"), "0.10");
+        assert_eq!(json("123456789012345678901234567890.123456789"), "123456789012345678901234567890.123456789");
+        // Beyond what a float can hold, but still a number
+        assert_eq!(json("1e400"), "1E+400");
+        assert_eq!(json("1e-400"), "1E-400");
+        assert_eq!(json("-25e400"), "-25E+400");
+    }
+
+    #[test]
+    fn test_every_decimal_has_json_number_text() {
+        for mantissa in ["0", "1", "-7", "123.45", "-0.001", "99999999999999999999.5"] {
+            for exponent in (-2000..=2000).step_by(7) {
+                let n = BigDecimal::from_str(&format!("{}e{}", mantissa, exponent)).unwrap();
+                let text = json_number_text(&n);
+                assert!(serde_json::from_str::<serde_json::Number>(&text).is_ok(), "{} is not a JSON number", text);
+                assert_eq!(BigDecimal::from_str(&text).unwrap(), n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_regex_codegen() {
+        let source = r#"{ text: "a1b2", first: /\d/.test(text), again: /\d/.match(text), other: /x/i.test(text) }"#;
+        
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+        
+        let mut analyzer = SemanticAnalyzer::new();
+        let analyzed = analyzer.analyze(&ast).unwrap();
+        
+        let mut codegen = CodeGenerator::new();
+        let bytecode = codegen.generate(&analyzed).unwrap();
+        
+        // Each distinct pattern is listed once in the constant pool
+        let regex_constants = bytecode.constants.iter()
+            .filter(|c| matches!(c, Value::Regex { .. }))
+            .count();
+        assert_eq!(regex_constants, 2);
+        
+        let has_method_call = bytecode.instructions.iter().any(|inst| {
+            matches!(inst, Instruction::CallMethod { method, argument_count: 1 } if method == "match")
+        });
+        assert!(has_method_call);
     }
 
     #[test]
@@ -0,0 +1,363 @@
+use crate::analyzer::{AnalyzerError, AnalyzerWarning};
+use crate::lexer::{LexerError, Span};
+use crate::parser::{ParseError, ParseWarning};
+use crate::project::SyntaxError;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning with the source it points at. Codes are `E01xx` for
+/// the lexer, `E02xx` for the parser and `E03xx` for the analyzer; warnings
+/// use `W` in place of `E`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, code, message: message.into(), span: None, help: None }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, code, message: message.into(), span: None, help: None }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl From<&LexerError> for Diagnostic {
+    /// Lexer errors only know their line and column, see `SourceMap::span_at`
+    fn from(error: &LexerError) -> Self {
+        match error {
+            LexerError::UnexpectedCharacter(ch, ..) => Diagnostic::error("E0101", format!("unexpected character '{}'", ch)),
+            LexerError::UnterminatedString(..) => Diagnostic::error("E0102", "unterminated string")
+                .with_help("close the string with the quote it was opened with"),
+            LexerError::InvalidEscapeSequence(ch, ..) => Diagnostic::error("E0103", format!("invalid escape sequence '\\{}'", ch))
+                .with_help("write '\\\\' for a backslash"),
+            LexerError::InvalidUnicodeEscape(digits, ..) => Diagnostic::error("E0104", format!("invalid unicode escape '\\u{}'", digits))
+                .with_help("'\\u' takes exactly 4 hex digits"),
+            LexerError::UnpairedSurrogate(digits, ..) => Diagnostic::error("E0105", format!("unpaired surrogate '\\u{}'", digits))
+                .with_help("a high surrogate must be followed by a low surrogate escape"),
+            LexerError::InvalidLongUnicodeEscape(digits, ..) => Diagnostic::error("E0106", format!("invalid unicode escape '\\U{}'", digits))
+                .with_help("'\\U' takes exactly 5 hex digits"),
+            LexerError::InvalidCodePointEscape(digits, ..) => Diagnostic::error("E0107", format!("invalid code point escape '\\U{{{}}}'", digits))
+                .with_help("'\\U{...}' takes 1 to 8 decimal digits naming a valid character"),
+            LexerError::InvalidByteEscape(digits, ..) => Diagnostic::error("E0108", format!("invalid byte escape '\\x{}'", digits))
+                .with_help("'\\x' takes exactly 2 hex digits"),
+            LexerError::InvalidByteSequence(..) => Diagnostic::error("E0109", "byte escapes are not valid UTF-8"),
+            LexerError::UnterminatedIdentifier(..) => Diagnostic::error("E0110", "unterminated backtick identifier")
+                .with_help("close the identifier with '`'"),
+            LexerError::EmptyIdentifier(..) => Diagnostic::error("E0118", "empty backtick identifier")
+                .with_help("write '````' for an identifier that is a single backtick"),
+            LexerError::UnterminatedTemplateExpression(..) => Diagnostic::error("E0111", "unterminated template expression")
+                .with_help("close the expression with '}'"),
+            LexerError::UnterminatedRegex(..) => Diagnostic::error("E0112", "unterminated regex literal")
+                .with_help("close the regex with '/'"),
+            LexerError::InvalidRegexFlag(flag, ..) => Diagnostic::error("E0113", format!("invalid regex flag '{}'", flag)),
+            LexerError::InvalidRegex(message, ..) => Diagnostic::error("E0114", format!("invalid regex: {}", message)),
+            LexerError::UnterminatedBlockComment(..) => Diagnostic::error("E0115", "unterminated block comment")
+                .with_help("close the comment with '*/'"),
+            LexerError::InvalidNumber(number, ..) => Diagnostic::error("E0116", format!("invalid number '{}'", number)),
+            LexerError::UnknownAnnotation(name, ..) => Diagnostic::error("E0117", format!("unknown annotation '@{}'", name)),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        match error {
+            ParseError::UnexpectedToken(token, span) => Diagnostic::error("E0201", format!("unexpected token {}", token))
+                .with_span(*span),
+            ParseError::ExpectedIdentifier(span) => Diagnostic::error("E0202", "expected an identifier")
+                .with_span(*span),
+            ParseError::ExpectedString(span) => Diagnostic::error("E0203", "expected a string")
+                .with_span(*span),
+            ParseError::ExpectedStringOrIdentifier(token, span) => Diagnostic::error("E0205", format!("expected a field name, found {}", token))
+                .with_span(*span),
+            ParseError::ExpectedToken(_, span, message) => Diagnostic::error("E0206", message.clone())
+                .with_span(*span),
+            ParseError::DuplicateParameter(parameter, span) => Diagnostic::error("E0209", format!("duplicate parameter '{}'", parameter))
+                .with_span(*span)
+                .with_help("give each parameter a different name"),
+            ParseError::MisplacedAnnotation(name, span) => Diagnostic::error("E0210", format!("'@{}' does not annotate a declaration", name))
+                .with_span(*span)
+                .with_help("write annotations on the lines before a declaration, function or object field"),
+        }
+    }
+}
+
+impl From<&ParseWarning> for Diagnostic {
+    fn from(warning: &ParseWarning) -> Self {
+        match warning {
+            ParseWarning::DeprecatedAssignment(name, span) => Diagnostic::warning("W0201", format!("'{} = ...' is deprecated", name))
+                .with_span(*span)
+                .with_help(format!("write '{}: ...' instead", name)),
+        }
+    }
+}
+
+impl From<&AnalyzerError> for Diagnostic {
+    fn from(error: &AnalyzerError) -> Self {
+        match error {
+            AnalyzerError::CircularDependency(symbols) => Diagnostic::error("E0301", format!("circular dependency involving {}", symbols.join(", "))),
+            AnalyzerError::UndefinedSymbol { name, span } => Diagnostic::error("E0302", format!("undefined symbol '{}'", name))
+                .with_span(*span)
+                .with_help(format!("declare '{}' or import the file that does", name)),
+            AnalyzerError::TypeError { expected, found, span } => Diagnostic::error("E0303", format!("expected {}, found {}", expected, found))
+                .with_span(*span),
+            AnalyzerError::DuplicateEndpoint { name, method, path, span } => Diagnostic::error("E0304", format!("endpoint '{}' repeats {} {}", name, method, path))
+                .with_span(*span),
+            AnalyzerError::UnknownMethod { receiver, method, span } => Diagnostic::error("E0305", format!("unknown method '{}' on {}", method, receiver))
+                .with_span(*span),
+            AnalyzerError::ArgumentCount { method, expected, found, span } => Diagnostic::error("E0306", format!("method '{}' expects {} argument(s), found {}", method, expected, found))
+                .with_span(*span),
+            AnalyzerError::FunctionArgumentCount { function, expected, found, span } => Diagnostic::error("E0307", format!("function '{}' expects {} argument(s), found {}", function, expected, found))
+                .with_span(*span),
+            AnalyzerError::CircularImport { files, symbols } => Diagnostic::error("E0308", format!("circular dependency across {} involving {}", files.join(", "), symbols.join(", "))),
+            AnalyzerError::UnresolvedImport { path, span } => Diagnostic::error("E0309", format!("import of '{}' was not loaded", path))
+                .with_span(*span)
+                .with_help("compile the file as part of a project"),
+            AnalyzerError::UnnamedGlobal { span } => Diagnostic::error("E0310", "a top-level object can only declare named fields")
+                .with_span(*span),
+            AnalyzerError::UnknownAnnotation { name, span } => Diagnostic::error("E0311", format!("unknown annotation '@{}'", name))
+                .with_span(*span)
+                .with_help("use one of @endpoint, @test, @http or @cache"),
+            AnalyzerError::InvalidAnnotation { name, message, span } => Diagnostic::error("E0312", format!("invalid '@{}': {}", name, message))
+                .with_span(*span),
+            AnalyzerError::NonExhaustiveMatch { found, span } => Diagnostic::error("E0313", format!("match does not cover every {}", found))
+                .with_span(*span)
+                .with_help("add a `_` arm for the values no other arm matches"),
+            AnalyzerError::DuplicateSymbol { name, span } => Diagnostic::error("E0314", format!("'{}' is declared more than once", name))
+                .with_span(*span)
+                .with_help("give each declaration its own name; a field named \"a.b\" is the same symbol as field `b` of `a`"),
+        }
+    }
+}
+
+impl From<&AnalyzerWarning> for Diagnostic {
+    fn from(warning: &AnalyzerWarning) -> Self {
+        match warning {
+            AnalyzerWarning::NullableMemberAccess { member, found, span } => Diagnostic::warning("W0301", format!("'.{}' is used on {}, which may be null", member, found))
+                .with_span(*span)
+                .with_help(format!("use '?.{}' or a default with '??'", member)),
+        }
+    }
+}
+
+/// One file of a `SourceMap`
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+    // Offset of the file's first byte among all files
+    pub start: usize,
+}
+
+/// The source text of every file of a project. Spans are byte offsets into
+/// the files laid end to end, so a span alone tells which file it is in.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file and return the offset its spans start at. Files are a byte
+    /// apart, so the end of one file is not the start of the next.
+    pub fn add(&mut self, path: &Path, source: &str) -> usize {
+        let start = self.files.last().map_or(0, |file| file.start + file.source.len() + 1);
+        self.files.push(SourceFile { path: path.to_path_buf(), source: source.to_string(), start });
+        start
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// The file an offset falls in
+    pub fn file_at(&self, offset: usize) -> Option<&SourceFile> {
+        self.files.iter().rev()
+            .find(|file| file.start <= offset)
+            .filter(|file| offset <= file.start + file.source.len())
+    }
+
+    /// The span of the character at a line and column of the `file`th file,
+    /// both counted from 1
+    pub fn span_at(&self, file: usize, line: usize, column: usize) -> Option<Span> {
+        let file = self.files.get(file)?;
+        let line_start: usize = file.source.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum();
+        let text = file.source[line_start..].split('\n').next().unwrap_or("");
+        let offset = text.char_indices().nth(column.saturating_sub(1)).map_or(text.len(), |(index, _)| index);
+        let width = text[offset..].chars().next().map_or(0, char::len_utf8);
+        let start = file.start + line_start + offset;
+        Some(Span::new(start, start + width, line))
+    }
+
+    /// A syntax error of the `file`th file as a diagnostic
+    pub fn syntax_diagnostic(&self, file: usize, error: &SyntaxError) -> Diagnostic {
+        match error {
+            SyntaxError::Lex(error) => {
+                let (line, column) = error.position();
+                let diagnostic = Diagnostic::from(error);
+                match self.span_at(file, line, column) {
+                    Some(span) => diagnostic.with_span(span),
+                    None => diagnostic,
+                }
+            }
+            SyntaxError::Parse(error) => Diagnostic::from(error),
+        }
+    }
+
+    /// Render a diagnostic with the line it points at and a caret underline:
+    ///
+    /// ```text
+    /// error[E0302]: undefined symbol 'price'
+    ///  --> shop.loaf:3:8
+    ///   |
+    /// 3 | total: price * 2
+    ///   |        ^^^^^
+    ///   = help: declare 'price' or import the file that does
+    /// ```
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut output = diagnostic.to_string();
+        let mut gutter = String::new();
+
+        let located = diagnostic.span.and_then(|span| self.file_at(span.start).map(|file| (file, span)));
+        if let Some((file, span)) = located {
+            let source = file.source.as_str();
+            let start = span.start - file.start;
+            let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+            let line_end = source[start..].find('\n').map_or(source.len(), |index| start + index);
+            let line_number = source[..line_start].matches('\n').count() + 1;
+            let text = source[line_start..line_end].trim_end_matches('\r');
+
+            // A span running past the line is underlined to the end of it
+            let end = span.end.saturating_sub(file.start).clamp(start, line_start + text.len());
+            let width = source[start..end].chars().count().max(1);
+            // Tabs are kept so the carets line up with the text above
+            let indent: String = source[line_start..start].chars()
+                .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect();
+            let column = source[line_start..start].chars().count() + 1;
+
+            gutter = " ".repeat(line_number.to_string().len());
+            let _ = write!(output, "\n{}--> {}:{}:{}", gutter, file.path.display(), line_number, column);
+            let _ = write!(output, "\n{} |", gutter);
+            let _ = write!(output, "\n{} | {}", line_number, text);
+            let _ = write!(output, "\n{} | {}{}", gutter, indent, "^".repeat(width));
+        }
+
+        if let Some(help) = &diagnostic.help {
+            let _ = write!(output, "\n{} = help: {}", gutter, help);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::SemanticAnalyzer;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn analyze(offset: usize, source: &str) -> AnalyzerError {
+        let tokens = Lexer::with_offset(source, offset).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        SemanticAnalyzer::new().analyze(&ast).unwrap_err()
+    }
+
+    #[test]
+    fn test_render_points_at_the_undefined_reference() {
+        let source = "price: 10\n\ntotal: pirce * 2\n";
+        let mut sources = SourceMap::new();
+        let offset = sources.add(Path::new("shop.loaf"), source);
+        let error = analyze(offset, source);
+
+        let rendered = sources.render(&Diagnostic::from(&error));
+        assert_eq!(rendered, [
+            "error[E0302]: undefined symbol 'pirce'",
+            " --> shop.loaf:3:8",
+            "  |",
+            "3 | total: pirce * 2",
+            "  |        ^^^^^",
+            "  = help: declare 'pirce' or import the file that does",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_spans_tell_files_apart() {
+        let mut sources = SourceMap::new();
+        sources.add(Path::new("main.loaf"), "a: 1\n");
+        let source = "b: 2\nc: \"x\".shout()\n";
+        let offset = sources.add(Path::new("lib.loaf"), source);
+        assert_eq!(offset, 6);
+        let error = analyze(offset, source);
+
+        let rendered = sources.render(&Diagnostic::from(&error));
+        assert!(rendered.starts_with("error[E0305]: unknown method 'shout' on string\n --> lib.loaf:2:4\n"), "{}", rendered);
+        assert!(rendered.contains("2 | c: \"x\".shout()\n  |    ^^^^^^^^^^^"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_lexer_errors_are_located_by_line_and_column() {
+        let mut sources = SourceMap::new();
+        sources.add(Path::new("main.loaf"), "a: 1\n");
+        let source = "b: 2\nc: 3 $\n";
+        let offset = sources.add(Path::new("lib.loaf"), source);
+        let mut lexer = Lexer::with_offset(source, offset);
+        lexer.tokenize_recovering();
+        let error = SyntaxError::Lex(lexer.errors()[0].clone());
+
+        let rendered = sources.render(&sources.syntax_diagnostic(1, &error));
+        assert_eq!(rendered, [
+            "error[E0101]: unexpected character '$'",
+            " --> lib.loaf:2:6",
+            "  |",
+            "2 | c: 3 $",
+            "  |      ^",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_render_without_a_span() {
+        let diagnostic = Diagnostic::error("E0301", "circular dependency involving a, b");
+        assert_eq!(SourceMap::new().render(&diagnostic), "error[E0301]: circular dependency involving a, b");
+    }
+}
@@ -0,0 +1,659 @@
+use crate::lexer::{Lexer, Span, TokenType};
+use crate::parser::{AstNode, Parser};
+use crate::project::SyntaxError;
+use std::fmt;
+
+// Spaces per indentation level
+const INDENT: usize = 2;
+
+/// A token or comment with the whitespace before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    // `None` for a comment
+    pub token_type: Option<TokenType>,
+    // Whitespace between the previous token or comment and this one
+    pub leading: String,
+    pub text: String,
+    pub span: Span,
+}
+
+impl SyntaxToken {
+    fn is_comment(&self) -> bool {
+        self.token_type.is_none()
+    }
+
+    fn is_line_comment(&self) -> bool {
+        self.is_comment() && !self.text.starts_with("##")
+    }
+
+    fn starts_line(&self) -> bool {
+        self.leading.contains('\n')
+    }
+
+    fn after_blank_line(&self) -> bool {
+        self.leading.matches('\n').count() > 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxNode {
+    Token(SyntaxToken),
+    // A bracket, everything up to the bracket that closes it, and that bracket
+    Group {
+        open: SyntaxToken,
+        children: Vec<SyntaxNode>,
+        close: SyntaxToken,
+    },
+}
+
+impl SyntaxNode {
+    fn first(&self) -> &SyntaxToken {
+        match self {
+            SyntaxNode::Token(token) => token,
+            SyntaxNode::Group { open, .. } => open,
+        }
+    }
+
+    fn last(&self) -> &SyntaxToken {
+        match self {
+            SyntaxNode::Token(token) => token,
+            SyntaxNode::Group { close, .. } => close,
+        }
+    }
+
+    fn is_comment(&self) -> bool {
+        matches!(self, SyntaxNode::Token(token) if token.is_comment())
+    }
+
+    fn is_comma(&self) -> bool {
+        matches!(self, SyntaxNode::Token(SyntaxToken { token_type: Some(TokenType::Comma), .. }))
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxNode::Token(token) => write!(f, "{}{}", token.leading, token.text),
+            SyntaxNode::Group { open, children, close } => {
+                write!(f, "{}{}", open.leading, open.text)?;
+                for child in children {
+                    write!(f, "{}", child)?;
+                }
+                write!(f, "{}{}", close.leading, close.text)
+            }
+        }
+    }
+}
+
+/// A lossless syntax tree of one file: brackets are grouped with what they
+/// enclose, and every token and comment keeps the whitespace before it, so
+/// printing the tree gives back the source exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree {
+    // Top-level statements, each with the comments on the lines above it
+    pub statements: Vec<Vec<SyntaxNode>>,
+    // Comments after the last statement
+    pub trailing: Vec<SyntaxNode>,
+    // Whitespace at the end of the file
+    pub end: String,
+}
+
+impl SyntaxTree {
+    /// Build the tree of a file, failing with every syntax error it has.
+    /// Statements are split where the parser starts them.
+    pub fn parse(source: &str) -> Result<Self, FormatError> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize_recovering();
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse_recovering();
+        let errors: Vec<SyntaxError> = lexer.errors().iter().cloned().map(SyntaxError::Lex)
+            .chain(parser.errors().iter().cloned().map(SyntaxError::Parse))
+            .collect();
+        if !errors.is_empty() {
+            return Err(FormatError::Syntax(errors));
+        }
+
+        // Tokens and comments in source order. A comment inside a template
+        // string's `{...}` is part of that token and is skipped.
+        let mut position = 0;
+        let mut elements = Vec::new();
+        let mut comments = lexer.comments().iter().peekable();
+        for token in &tokens {
+            while let Some(comment) = comments.next_if(|comment| comment.start < token.span.start) {
+                if comment.start >= position {
+                    elements.push(Self::element(source, &mut position, None, *comment));
+                }
+            }
+            if token.token_type != TokenType::Eof {
+                elements.push(Self::element(source, &mut position, Some(token.token_type.clone()), token.span));
+            }
+        }
+
+        // Group brackets; the parser has already checked that they match
+        let mut opened = Vec::new();
+        let mut levels = vec![Vec::new()];
+        for element in elements {
+            match &element.token_type {
+                Some(TokenType::LeftBrace | TokenType::LeftBracket | TokenType::LeftParen) => {
+                    opened.push(element);
+                    levels.push(Vec::new());
+                }
+                Some(TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen) if !opened.is_empty() => {
+                    let children = levels.pop().unwrap_or_default();
+                    let open = opened.pop().expect("an open bracket");
+                    levels.last_mut().expect("the top level").push(SyntaxNode::Group { open, children, close: element });
+                }
+                _ => levels.last_mut().expect("the top level").push(SyntaxNode::Token(element)),
+            }
+        }
+        let nodes = levels.swap_remove(0);
+
+        let starts: Vec<usize> = match &program {
+            AstNode::Program(statements) => statements.iter().map(|statement| statement.span().start).collect(),
+            _ => Vec::new(),
+        };
+        let mut tree = SyntaxTree { statements: Vec::new(), trailing: Vec::new(), end: source[position..].to_string() };
+        let mut current: Vec<SyntaxNode> = Vec::new();
+        for node in nodes {
+            let starts_statement = !node.is_comment() && starts.contains(&node.first().span.start);
+            if starts_statement && current.iter().any(|node| !node.is_comment()) {
+                tree.statements.push(std::mem::take(&mut current));
+            }
+            current.push(node);
+        }
+        if current.iter().any(|node| !node.is_comment()) {
+            tree.statements.push(current);
+        } else {
+            tree.trailing = current;
+        }
+
+        // Comments on the lines after a statement belong to the next one
+        for index in 0..tree.statements.len() {
+            let (_, own_line) = trailing_comments(&tree.statements[index]);
+            let moved = tree.statements[index].split_off(own_line);
+            match tree.statements.get_mut(index + 1) {
+                Some(next) => {
+                    next.splice(0..0, moved);
+                }
+                None => {
+                    tree.trailing.splice(0..0, moved);
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+    fn element(source: &str, position: &mut usize, token_type: Option<TokenType>, span: Span) -> SyntaxToken {
+        let leading = source[*position..span.start].to_string();
+        *position = span.end;
+        SyntaxToken { token_type, leading, text: source[span.start..span.end].to_string(), span }
+    }
+
+    /// The file in the canonical layout: one statement per line, two-space
+    /// indentation, and a blank line around annotated declarations.
+    /// A bracket stays on one line unless the source breaks a line directly
+    /// inside it; then it gets one item per line and a trailing comma.
+    pub fn format(&self) -> String {
+        let mut printer = Printer::default();
+        let mut previous_annotated = false;
+
+        for statement in &self.statements {
+            let annotated = statement.iter()
+                .find(|node| !node.is_comment())
+                .is_some_and(|node| matches!(node.first().token_type, Some(TokenType::Annotation(_))));
+            if !printer.output.is_empty() {
+                let blank = statement[0].first().after_blank_line() || annotated || previous_annotated;
+                printer.newline(0, blank);
+            }
+            let nodes: Vec<&SyntaxNode> = statement.iter().collect();
+            printer.lines(&nodes, 0);
+            previous_annotated = annotated;
+        }
+
+        for comment in &self.trailing {
+            if !printer.output.is_empty() {
+                printer.newline(0, comment.first().after_blank_line());
+            }
+            printer.token(comment.first());
+        }
+
+        if !printer.output.is_empty() {
+            printer.output.push('\n');
+        }
+        printer.output
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in self.statements.iter().flatten().chain(&self.trailing) {
+            write!(f, "{}", node)?;
+        }
+        write!(f, "{}", self.end)
+    }
+}
+
+/// Reprint a file in the canonical layout, see `SyntaxTree::format`.
+/// Formatting the result again changes nothing.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let formatted = SyntaxTree::parse(source)?.format();
+
+    // Only whitespace, comments and trailing commas may change
+    let before = layout_free_tokens(source);
+    let after = layout_free_tokens(&formatted);
+    let changed = (0..before.len().max(after.len()))
+        .find(|&index| before.get(index).map(|(text, _)| text) != after.get(index).map(|(text, _)| text));
+    if let Some(index) = changed {
+        let line = before.get(index).or(before.last()).map_or(1, |(_, line)| *line);
+        return Err(FormatError::ChangedTokens(line));
+    }
+
+    Ok(formatted)
+}
+
+/// The text and line of each token, leaving out commas before a closing bracket
+fn layout_free_tokens(source: &str) -> Vec<(&str, usize)> {
+    let tokens = Lexer::new(source).tokenize_recovering();
+    tokens.iter().enumerate()
+        .filter(|(index, token)| {
+            let closes = tokens.get(index + 1).is_some_and(|next| {
+                matches!(next.token_type, TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen)
+            });
+            !(token.token_type == TokenType::Comma && closes)
+        })
+        .map(|(_, token)| (&source[token.span.start..token.span.end], token.line))
+        .collect()
+}
+
+/// Where the comments at the end of `nodes` start, and where those of them
+/// on lines of their own start
+fn trailing_comments<T: std::borrow::Borrow<SyntaxNode>>(nodes: &[T]) -> (usize, usize) {
+    let comments = nodes.iter().rposition(|node| !node.borrow().is_comment()).map_or(0, |index| index + 1);
+    let own_line = nodes[comments..].iter()
+        .position(|node| node.borrow().first().starts_line())
+        .map_or(nodes.len(), |index| comments + index);
+    (comments, own_line)
+}
+
+/// Whether a token ends an operand, so a `-` after it subtracts and a `[`
+/// after it indexes
+fn ends_operand(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::String(_)
+            | TokenType::Number(_)
+            | TokenType::Boolean(_)
+            | TokenType::Null
+            | TokenType::Regex { .. }
+            | TokenType::TemplateString(_)
+            | TokenType::HttpUrl(_)
+            | TokenType::Identifier(_)
+            | TokenType::RightBrace
+            | TokenType::RightBracket
+            | TokenType::RightParen
+    )
+}
+
+/// What the space between two tokens on a line depends on
+#[derive(Default)]
+struct Spacing<'a> {
+    previous: Option<&'a TokenType>,
+    // Whether `previous` is a prefix `-` or `!`
+    unary: bool,
+    // `?`s whose conditional's `:` hasn't been seen yet
+    conditionals: usize,
+}
+
+impl<'a> Spacing<'a> {
+    fn space_before(&self, next: &TokenType) -> bool {
+        let Some(previous) = self.previous else { return false };
+        if self.unary {
+            return false;
+        }
+        match (previous, next) {
+            (_, TokenType::Comma) => false,
+            // A url runs up to the next space
+            (TokenType::HttpUrl(_), _) => true,
+            (TokenType::Dot | TokenType::QuestionDot, _) | (_, TokenType::Dot | TokenType::QuestionDot) => false,
+            (_, TokenType::Colon) => self.conditionals > 0,
+            // `a..b` and `..rest`
+            (TokenType::DotDot, _) => false,
+            (previous, TokenType::DotDot) => !ends_operand(previous),
+            (TokenType::Identifier(_) | TokenType::Annotation(_), TokenType::LeftParen) => false,
+            (previous, TokenType::LeftBracket) => !ends_operand(previous),
+            _ => true,
+        }
+    }
+
+    fn push(&mut self, token_type: &'a TokenType) {
+        self.unary = matches!(token_type, TokenType::Minus | TokenType::Not) && !self.previous.is_some_and(ends_operand);
+        match token_type {
+            TokenType::Question => self.conditionals += 1,
+            TokenType::Colon if self.conditionals > 0 => self.conditionals -= 1,
+            _ => {}
+        }
+        self.previous = Some(token_type);
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    output: String,
+    // Indentation of the line being printed
+    indent: usize,
+    // Whether the line being printed ends in a `#` comment
+    in_line_comment: bool,
+}
+
+impl Printer {
+    fn newline(&mut self, indent: usize, blank: bool) {
+        self.output.push('\n');
+        if blank {
+            self.output.push('\n');
+        }
+        self.output.push_str(&" ".repeat(indent));
+        self.indent = indent;
+        self.in_line_comment = false;
+    }
+
+    fn token(&mut self, token: &SyntaxToken) {
+        self.output.push_str(if token.is_comment() { token.text.trim_end() } else { &token.text });
+        self.in_line_comment = token.is_line_comment();
+    }
+
+    /// A comment at the end of the current line
+    fn trailing_comment(&mut self, comment: &SyntaxToken) {
+        if self.in_line_comment {
+            self.newline(self.indent, false);
+        } else {
+            self.output.push(' ');
+        }
+        self.token(comment);
+    }
+
+    /// Print a statement or list item: the comments above it on lines of
+    /// their own, then its tokens
+    fn lines(&mut self, nodes: &[&SyntaxNode], indent: usize) {
+        for (index, node) in nodes.iter().enumerate() {
+            if index > 0 {
+                self.newline(indent, node.first().after_blank_line());
+            }
+            if !node.is_comment() {
+                self.sequence(&nodes[index..], indent);
+                return;
+            }
+            self.token(node.first());
+        }
+    }
+
+    /// Print nodes on one line, breaking it only where the source does.
+    /// Continuation lines are indented one level deeper than `indent`, except
+    /// the lines after annotations, which line up with them.
+    fn sequence(&mut self, nodes: &[&SyntaxNode], indent: usize) {
+        let mut spacing = Spacing::default();
+        let mut after_comment = false;
+        // Whether the current line only has annotations so far
+        let mut annotating = true;
+
+        for (index, node) in nodes.iter().enumerate() {
+            let first = node.first();
+            let line_break = index > 0 && (first.starts_line() || self.in_line_comment);
+            if line_break {
+                self.newline(if annotating { indent } else { indent + INDENT }, false);
+            } else if index > 0 && (after_comment || node.is_comment() || first.token_type.as_ref().is_some_and(|next| spacing.space_before(next))) {
+                self.output.push(' ');
+            }
+
+            // An annotation's arguments and config may follow it on its line
+            let annotation = matches!(first.token_type, Some(TokenType::Annotation(_)));
+            let configures = !line_break && (node.is_comment() || matches!(node, SyntaxNode::Group { .. }));
+            annotating = annotating && (annotation || configures);
+
+            match node {
+                SyntaxNode::Token(token) if token.is_comment() => {
+                    self.token(token);
+                    after_comment = true;
+                    continue;
+                }
+                SyntaxNode::Token(token) => self.token(token),
+                SyntaxNode::Group { open, children, close } => {
+                    // What comes first on a line is never called or indexed
+                    let previous = if line_break { None } else { spacing.previous };
+                    self.group(open, children, close, previous);
+                }
+            }
+            after_comment = false;
+            if let Some(token_type) = &node.last().token_type {
+                spacing.push(token_type);
+            }
+        }
+    }
+
+    /// Print a bracket and its contents, on one line unless the source
+    /// breaks a line directly inside it
+    fn group(&mut self, open: &SyntaxToken, children: &[SyntaxNode], close: &SyntaxToken, previous: Option<&TokenType>) {
+        let call = matches!(previous, Some(TokenType::Identifier(_) | TokenType::Annotation(_)));
+        let commas = children.iter().filter(|child| child.is_comma()).count();
+        // Lists take a trailing comma; a parenthesized expression or an index can't
+        let list = match open.token_type {
+            Some(TokenType::LeftBrace) => true,
+            Some(TokenType::LeftBracket) => !previous.is_some_and(ends_operand),
+            _ => call || commas > 0,
+        };
+        let broken = close.starts_line() || children.iter().any(|child| child.is_comment() || child.first().starts_line());
+
+        self.token(open);
+        if broken {
+            self.broken_group(children, list);
+        } else {
+            // Only the comma of a one-element tuple `(a,)` is kept
+            let tuple = open.token_type == Some(TokenType::LeftParen) && !call && commas == 1;
+            let mut children: Vec<&SyntaxNode> = children.iter().collect();
+            if children.last().is_some_and(|child| child.is_comma()) && !tuple {
+                children.pop();
+            }
+            if !children.is_empty() {
+                let padded = open.token_type == Some(TokenType::LeftBrace);
+                if padded {
+                    self.output.push(' ');
+                }
+                self.sequence(&children, self.indent);
+                if padded {
+                    self.output.push(' ');
+                }
+            }
+        }
+        self.token(close);
+    }
+
+    /// Print the contents of a bracket one item per line, with a comment
+    /// after an item kept on its line
+    fn broken_group(&mut self, children: &[SyntaxNode], list: bool) {
+        let indent = self.indent;
+        let mut items: Vec<Vec<&SyntaxNode>> = vec![Vec::new()];
+        for child in children {
+            if child.is_comma() {
+                items.push(Vec::new());
+            } else {
+                items.last_mut().expect("an item").push(child);
+            }
+        }
+        let comma_after_last = items.last().is_some_and(Vec::is_empty) && items.len() > 1;
+        let last = items.iter().rposition(|item| item.iter().any(|node| !node.is_comment()));
+
+        for (index, item) in items.iter().enumerate() {
+            // Comments on the line of the previous item's comma stay there
+            let mut nodes = &item[..];
+            if index > 0 {
+                while let Some(comment) = nodes.first().filter(|node| node.is_comment() && !node.first().starts_line()) {
+                    self.trailing_comment(comment.first());
+                    nodes = &nodes[1..];
+                }
+            }
+
+            let (comments, own_line) = trailing_comments(nodes);
+            if comments > 0 {
+                let blank = index > 0 && nodes[0].first().after_blank_line();
+                self.newline(indent + INDENT, blank);
+                self.lines(&nodes[..comments], indent + INDENT);
+                if list || Some(index) != last || comma_after_last {
+                    self.output.push(',');
+                }
+                for comment in &nodes[comments..own_line] {
+                    self.trailing_comment(comment.first());
+                }
+            }
+            for comment in &nodes[own_line..] {
+                self.newline(indent + INDENT, comment.first().after_blank_line());
+                self.token(comment.first());
+            }
+        }
+
+        self.newline(indent, false);
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum FormatError {
+    #[error("{} syntax error(s) found", .0.len())]
+    Syntax(Vec<SyntaxError>),
+
+    #[error("Formatting would change the program at line {0}")]
+    ChangedTokens(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted, "formatting is not idempotent");
+        formatted
+    }
+
+    #[test]
+    fn test_syntax_tree_is_lossless() {
+        let source = "# header\n\nx :{a:1 ,# one\n  b : [ 1,2 ]## block\n ##}\n\n\n   f( a ,b ) : a+b   # sum\n# end\n";
+        let tree = SyntaxTree::parse(source).unwrap();
+        assert_eq!(tree.to_string(), source);
+        assert_eq!(tree.statements.len(), 2);
+        assert_eq!(tree.trailing.len(), 1);
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(format("x:1+2*-3\ny :a.b?.c[0]"), "x: 1 + 2 * -3\ny: a.b?.c[0]\n");
+        assert_eq!(format("v: ok?(a,b):!c\nw: f( x ,y )"), "v: ok ? (a, b) : !c\nw: f(x, y)\n");
+        assert_eq!(format("o: {a:1,b:[1,2,],}\ne: {}"), "o: { a: 1, b: [1, 2] }\ne: {}\n");
+        assert_eq!(format("t: (1,)\ng: (1 + 2,3,)"), "t: (1,)\ng: (1 + 2, 3)\n");
+    }
+
+    #[test]
+    fn test_format_breaks_lists_with_trailing_commas() {
+        let source = "user: {name: \"Ada\",\n        tags: [\"a\",\n \"b\"], total: sum(1,\n2)}\nwrapped: f({\n a: 1\n})";
+        assert_eq!(format(source), "\
+user: {
+  name: \"Ada\",
+  tags: [
+    \"a\",
+    \"b\",
+  ],
+  total: sum(
+    1,
+    2,
+  ),
+}
+wrapped: f({
+  a: 1,
+})
+");
+        // A parenthesized expression is not a list
+        assert_eq!(format("x: (\n  1 + 2\n) * 3"), "x: (\n  1 + 2\n) * 3\n");
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "# Settings\n\n\n\nconfig: {   # inline\n  # about a\n  a: 1 # one\n  ,b: 2,   # two\n\n  # last\n}\ntotal: a +   ## why ## b\n# footer   ";
+        assert_eq!(format(source), "\
+# Settings
+
+config: {
+  # inline
+  # about a
+  a: 1, # one
+  b: 2, # two
+
+  # last
+}
+total: a + ## why ## b
+# footer
+");
+    }
+
+    #[test]
+    fn test_format_annotation_layout() {
+        let source = "x: 1\n@test(\"x is one\")   {expect: x,\nequals: 1}\ny: x\n@endpoint( GET,\"/y\" )\n@cache(60)\nz: y\nw: {\n@test(\"a\") {equals: 1}\na: 1}";
+        assert_eq!(format(source), "\
+x: 1
+
+@test(\"x is one\") {
+  expect: x,
+  equals: 1,
+}
+y: x
+
+@endpoint(GET, \"/y\")
+@cache(60)
+z: y
+
+w: {
+  @test(\"a\") { equals: 1 }
+  a: 1,
+}
+");
+    }
+
+    #[test]
+    fn test_format_match_layout() {
+        let source = "label: match response {\n{status:200 .. 299,body}:body,\n[first , .. rest]:first,\n.. -1:\"negative\",\n_:null}\nshort: match n {0:\"zero\",_:\"some\"}";
+        assert_eq!(format(source), "\
+label: match response {
+  { status: 200..299, body }: body,
+  [first, ..rest]: first,
+  ..-1: \"negative\",
+  _: null,
+}
+short: match n { 0: \"zero\", _: \"some\" }
+");
+    }
+
+    #[test]
+    fn test_format_keeps_line_breaks_that_matter() {
+        // A `[` on a new line is an array, not an index, and stays there
+        let source = "x: a\n[1, 2]\nlong: first +\nsecond";
+        assert_eq!(format(source), "x: a\n[1, 2]\nlong: first +\n  second\n");
+        assert_eq!(format(""), "");
+    }
+
+    #[test]
+    fn test_format_examples_is_idempotent() {
+        for source in [
+            include_str!("../../examples/endpoint.loaf"),
+            include_str!("../../examples/promises.loaf"),
+            include_str!("../../comprehensive_test.loaf"),
+            include_str!("../../test_runner_demo.loaf"),
+        ] {
+            let formatted = format(source);
+            assert_eq!(SyntaxTree::parse(source).unwrap().to_string(), source);
+            assert!(formatted.ends_with("}\n"), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn test_format_rejects_syntax_errors() {
+        let error = format_source("x: (1\ny: ~").unwrap_err();
+        assert!(matches!(&error, FormatError::Syntax(errors) if errors.len() == 2), "{:?}", error);
+    }
+}
@@ -1,14 +1,22 @@
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TokenType {
     // Literals
     String(String),
-    Number(f64),
+    Number(BigDecimal),
     Boolean(bool),
     Null,
     
+    // Regular expression literal: /pattern/flags
+    Regex { pattern: String, flags: String },
+    
+    // Template string: literal text interleaved with embedded expression tokens
+    TemplateString(Vec<TemplateSegment>),
+    
     // Identifiers and Keywords
     Identifier(String),
     
@@ -22,38 +30,83 @@ pub enum TokenType {
     Comma,         // ,
     Colon,         // :
     Dot,           // .
+    DotDot,        // .. (ranges and rest patterns)
     
     // Operators
     Plus,          // +
     Minus,         // -
     Multiply,      // *
     Divide,        // /
+    DoubleSlash,   // // (HTTP operator)
+    HttpUrl(Vec<TemplateSegment>), // //service/path?x={x}
     Equal,         // =
+    NotEqual,      // !=
+    Less,          // <
+    LessEqual,     // <=
+    Greater,       // >
+    GreaterEqual,  // >=
+    Not,           // !
+    And,           // &
+    Question,      // ?
+    QuestionDot,   // ?.
+    DoubleQuestion, // ??
     
     // Special Keywords
     Promise,       // @promise
-    Endpoint,      // @endpoint
-    Method,        // @method
-    Http,          // @http
-    Test,          // @test
+    Import,        // @import
+    Annotation(String), // @name, such as @endpoint or @test
+    
+    // Text the lexer could not read, already reported as an error
+    Invalid,
     
     // End of file
     Eof,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A piece of a `$"..."` template string. Embedded expressions are lexed
+/// eagerly and end with an `Eof` token so they can be parsed on their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TemplateSegment {
+    Text(String),
+    Expression(Vec<Token>),
+}
+
+/// A range of source text as byte offsets, `end` exclusive, with the line it
+/// starts on. Offsets of a project's files don't overlap, see
+/// `Lexer::with_offset`, so a span also tells which file it is in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize) -> Self {
+        Self { start, end, line }
+    }
+
+    /// The span from the start of this one to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end.max(self.start), line: self.line }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
     pub column: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, line: usize, column: usize) -> Self {
+    pub fn new(token_type: TokenType, span: Span, column: usize) -> Self {
         Self {
             token_type,
-            line,
+            line: span.line,
             column,
+            span,
         }
     }
 }
@@ -65,6 +118,23 @@ impl fmt::Display for TokenType {
             TokenType::Number(n) => write!(f, "{}", n),
             TokenType::Boolean(b) => write!(f, "{}", b),
             TokenType::Null => write!(f, "null"),
+            TokenType::TemplateString(segments) => {
+                write!(f, "$\"")?;
+                for segment in segments {
+                    match segment {
+                        TemplateSegment::Text(text) => write!(f, "{}", text)?,
+                        TemplateSegment::Expression(tokens) => {
+                            let parts: Vec<String> = tokens.iter()
+                                .filter(|t| t.token_type != TokenType::Eof)
+                                .map(|t| t.token_type.to_string())
+                                .collect();
+                            write!(f, "{{{}}}", parts.join(" "))?;
+                        }
+                    }
+                }
+                write!(f, "\"")
+            }
+            TokenType::Regex { pattern, flags } => write!(f, "/{}/{}", pattern, flags),
             TokenType::Identifier(s) => write!(f, "{}", s),
             TokenType::LeftBrace => write!(f, "{{"),
             TokenType::RightBrace => write!(f, "}}"),
@@ -75,16 +145,42 @@ impl fmt::Display for TokenType {
             TokenType::Comma => write!(f, ","),
             TokenType::Colon => write!(f, ":"),
             TokenType::Dot => write!(f, "."),
+            TokenType::DotDot => write!(f, ".."),
             TokenType::Plus => write!(f, "+"),
             TokenType::Minus => write!(f, "-"),
             TokenType::Multiply => write!(f, "*"),
             TokenType::Divide => write!(f, "/"),
+            TokenType::DoubleSlash => write!(f, "//"),
+            TokenType::HttpUrl(segments) => {
+                for segment in segments {
+                    match segment {
+                        TemplateSegment::Text(text) => write!(f, "{}", text)?,
+                        TemplateSegment::Expression(tokens) => {
+                            let parts: Vec<String> = tokens.iter()
+                                .filter(|t| t.token_type != TokenType::Eof)
+                                .map(|t| t.token_type.to_string())
+                                .collect();
+                            write!(f, "{{{}}}", parts.join(" "))?;
+                        }
+                    }
+                }
+                Ok(())
+            }
             TokenType::Equal => write!(f, "="),
+            TokenType::NotEqual => write!(f, "!="),
+            TokenType::Less => write!(f, "<"),
+            TokenType::LessEqual => write!(f, "<="),
+            TokenType::Greater => write!(f, ">"),
+            TokenType::GreaterEqual => write!(f, ">="),
+            TokenType::Not => write!(f, "!"),
+            TokenType::And => write!(f, "&"),
+            TokenType::Question => write!(f, "?"),
+            TokenType::QuestionDot => write!(f, "?."),
+            TokenType::DoubleQuestion => write!(f, "??"),
             TokenType::Promise => write!(f, "@promise"),
-            TokenType::Endpoint => write!(f, "@endpoint"),
-            TokenType::Method => write!(f, "@method"),
-            TokenType::Http => write!(f, "@http"),
-            TokenType::Test => write!(f, "@test"),
+            TokenType::Import => write!(f, "@import"),
+            TokenType::Annotation(name) => write!(f, "@{}", name),
+            TokenType::Invalid => write!(f, "<invalid>"),
             TokenType::Eof => write!(f, "EOF"),
         }
     }
@@ -95,39 +191,96 @@ pub struct Lexer {
     position: usize,
     line: usize,
     column: usize,
+    // Byte offset of `position`, counted from the offset the input starts at
+    offset: usize,
+    // Whether a `/` here starts a regex literal rather than dividing
+    regex_allowed: bool,
+    errors: Vec<LexerError>,
+    comments: Vec<Span>,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        Self::with_offset(input, 0)
+    }
+
+    /// A lexer whose token spans start at byte `offset`, for input that is
+    /// one of several files
+    pub fn with_offset(input: &str, offset: usize) -> Self {
         Self {
             input: input.chars().collect(),
             position: 0,
             line: 1,
             column: 1,
+            offset,
+            regex_allowed: true,
+            errors: Vec::new(),
+            comments: Vec::new(),
         }
     }
 
+    /// Errors found by the last `tokenize_recovering`, in source order
+    pub fn errors(&self) -> &[LexerError] {
+        &self.errors
+    }
+
+    /// Comments skipped by the last `tokenize_recovering`, in source order.
+    /// Tokens don't keep them, so this is where a formatter finds them.
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
+    }
+
     pub fn tokenize(&mut self) -> Result<Vec<Token>, LexerError> {
+        let tokens = self.tokenize_recovering();
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Tokenize the whole input, recording every error instead of stopping
+    /// at the first. A bad token becomes an `Invalid` token and the rest of
+    /// its line is skipped, so one mistake doesn't garble what follows.
+    pub fn tokenize_recovering(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
+        self.errors.clear();
+        self.comments.clear();
         
         while !self.is_at_end() {
-            self.skip_whitespace();
+            if let Err(error) = self.skip_whitespace() {
+                self.errors.push(error);
+            }
             
             if self.is_at_end() {
                 break;
             }
             
-            let token = self.next_token()?;
-            tokens.push(token);
+            let (line, column, start) = (self.line, self.column, self.offset);
+            match self.next_token() {
+                Ok(token) => tokens.push(token),
+                Err(error) => {
+                    // A stray character is skipped on its own, anything else
+                    // may have stopped in the middle of a string or regex
+                    if !matches!(error, LexerError::UnexpectedCharacter(..)) {
+                        while !self.is_at_end() && self.peek() != '\n' {
+                            self.advance();
+                        }
+                    }
+                    self.regex_allowed = false;
+                    self.errors.push(error);
+                    tokens.push(Token::new(TokenType::Invalid, Span::new(start, self.offset, line), column));
+                }
+            }
         }
         
-        tokens.push(Token::new(TokenType::Eof, self.line, self.column));
-        Ok(tokens)
+        tokens.push(Token::new(TokenType::Eof, Span::new(self.offset, self.offset, self.line), self.column));
+        tokens
     }
 
     fn next_token(&mut self) -> Result<Token, LexerError> {
         let start_line = self.line;
         let start_column = self.column;
+        let start = self.offset;
         
         let ch = self.advance();
         
@@ -140,103 +293,410 @@ impl Lexer {
             ')' => TokenType::RightParen,
             ',' => TokenType::Comma,
             ':' => TokenType::Colon,
+            '.' if self.peek() == '.' && !self.is_at_end() => {
+                self.advance();
+                TokenType::DotDot
+            }
+            // A number may leave out the zero before its point, as in `.5`
+            '.' if self.peek().is_ascii_digit() => {
+                self.retreat();
+                self.number()?
+            }
             '.' => TokenType::Dot,
             '+' => TokenType::Plus,
             '-' => TokenType::Minus,
             '*' => TokenType::Multiply,
+            '/' if self.peek() == '/' && !self.is_at_end() => {
+                self.advance();
+                if is_identifier_start(self.peek()) && !self.is_at_end() {
+                    self.http_url()?
+                } else {
+                    TokenType::DoubleSlash
+                }
+            }
+            '/' if self.regex_allowed && !self.is_at_end() => self.regex(start_line, start_column)?,
             '/' => TokenType::Divide,
             '=' => TokenType::Equal,
-            '"' => self.string()?,
-            '@' => self.annotation()?,
+            '!' if self.peek() == '=' && !self.is_at_end() => {
+                self.advance();
+                TokenType::NotEqual
+            }
+            '!' => TokenType::Not,
+            '<' if self.peek() == '=' && !self.is_at_end() => {
+                self.advance();
+                TokenType::LessEqual
+            }
+            '<' => TokenType::Less,
+            '>' if self.peek() == '=' && !self.is_at_end() => {
+                self.advance();
+                TokenType::GreaterEqual
+            }
+            '>' => TokenType::Greater,
+            '&' => TokenType::And,
+            // `c ?.5 : 1` is a conditional on the number `.5`
+            '?' if self.peek() == '.' && !self.input.get(self.position + 1).is_some_and(char::is_ascii_digit) => {
+                self.advance();
+                TokenType::QuestionDot
+            }
+            '?' if self.peek() == '?' && !self.is_at_end() => {
+                self.advance();
+                TokenType::DoubleQuestion
+            }
+            '?' => TokenType::Question,
+            '"' | '\'' => self.string(ch, start_line, start_column)?,
+            '$' if matches!(self.peek(), '"' | '\'') && !self.is_at_end() => {
+                let quote = self.advance();
+                self.template_string(quote, start_line, start_column)?
+            }
+            '@' if matches!(self.peek(), '"' | '\'') && !self.is_at_end() => {
+                let quote = self.advance();
+                self.raw_string(quote, start_line, start_column)?
+            }
+            '`' => self.quoted_identifier(start_line, start_column)?,
+            '@' => self.annotation(start_line, start_column)?,
             _ if ch.is_ascii_digit() || ch == '.' => {
-                self.position -= 1;
-                self.column -= 1;
+                self.retreat();
                 self.number()?
             }
-            _ if ch.is_ascii_alphabetic() || ch == '_' => {
-                self.position -= 1;
-                self.column -= 1;
+            _ if is_identifier_start(ch) => {
+                self.retreat();
                 self.identifier_or_keyword()?
             }
-            _ => return Err(LexerError::UnexpectedCharacter(ch, self.line, self.column)),
+            _ => return Err(LexerError::UnexpectedCharacter(ch, start_line, start_column)),
         };
         
-        Ok(Token::new(token_type, start_line, start_column))
+        // A slash after a complete operand divides it; anywhere else it opens a regex
+        self.regex_allowed = !matches!(
+            token_type,
+            TokenType::String(_)
+                | TokenType::Number(_)
+                | TokenType::Boolean(_)
+                | TokenType::Null
+                | TokenType::Regex { .. }
+                | TokenType::TemplateString(_)
+                | TokenType::HttpUrl(_)
+                | TokenType::Identifier(_)
+                | TokenType::RightBrace
+                | TokenType::RightBracket
+                | TokenType::RightParen
+        );
+        
+        Ok(Token::new(token_type, Span::new(start, self.offset, start_line), start_column))
+    }
+
+    /// Read a `"..."` or `'...'` string with backslash escapes.
+    fn string(&mut self, quote: char, start_line: usize, start_column: usize) -> Result<TokenType, LexerError> {
+        let mut buffer = StringBuffer::default();
+        
+        loop {
+            if self.is_at_end() {
+                return Err(LexerError::UnterminatedString(start_line, start_column));
+            }
+            
+            let (line, column) = (self.line, self.column);
+            match self.advance() {
+                ch if ch == quote => break,
+                '\\' => self.escape_sequence(&mut buffer, quote, line, column)?,
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                    buffer.push('\n')?;
+                }
+                ch => buffer.push(ch)?,
+            }
+        }
+        
+        Ok(TokenType::String(buffer.finish()?))
     }
 
-    fn string(&mut self) -> Result<TokenType, LexerError> {
+    /// Read an `@"..."` string. Backslashes are literal and a doubled quote
+    /// stands for one quote character.
+    fn raw_string(&mut self, quote: char, start_line: usize, start_column: usize) -> Result<TokenType, LexerError> {
         let mut value = String::new();
         
-        while !self.is_at_end() && self.peek() != '"' {
-            let ch = self.advance();
-            if ch == '\\' {
-                if self.is_at_end() {
-                    return Err(LexerError::UnterminatedString(self.line, self.column));
+        loop {
+            if self.is_at_end() {
+                return Err(LexerError::UnterminatedString(start_line, start_column));
+            }
+            
+            match self.advance() {
+                ch if ch == quote && self.peek() == quote && !self.is_at_end() => {
+                    self.advance();
+                    value.push(quote);
                 }
-                let escaped = self.advance();
-                match escaped {
-                    '"' => value.push('"'),
-                    '\\' => value.push('\\'),
-                    '/' => value.push('/'),
-                    'b' => value.push('\x08'),
-                    'f' => value.push('\x0C'),
-                    'n' => value.push('\n'),
-                    'r' => value.push('\r'),
-                    't' => value.push('\t'),
-                    'u' => {
-                        // Unicode escape sequence
-                        let mut unicode = String::new();
-                        for _ in 0..4 {
-                            if self.is_at_end() {
-                                return Err(LexerError::UnterminatedString(self.line, self.column));
-                            }
-                            unicode.push(self.advance());
-                        }
-                        if let Ok(code_point) = u32::from_str_radix(&unicode, 16) {
-                            if let Some(ch) = char::from_u32(code_point) {
-                                value.push(ch);
-                            } else {
-                                return Err(LexerError::InvalidUnicodeEscape(self.line, self.column));
-                            }
-                        } else {
-                            return Err(LexerError::InvalidUnicodeEscape(self.line, self.column));
-                        }
-                    }
-                    _ => return Err(LexerError::InvalidEscapeSequence(escaped, self.line, self.column)),
+                ch if ch == quote => break,
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                    value.push('\n');
                 }
-            } else {
-                value.push(ch);
+                ch => value.push(ch),
             }
         }
         
+        Ok(TokenType::String(value))
+    }
+
+    /// Read the rest of an escape sequence after its backslash, which was at
+    /// `line`/`column`. Escape digits never run past the string's `quote`.
+    fn escape_sequence(&mut self, buffer: &mut StringBuffer, quote: char, line: usize, column: usize) -> Result<(), LexerError> {
         if self.is_at_end() {
-            return Err(LexerError::UnterminatedString(self.line, self.column));
+            return Err(LexerError::UnterminatedString(line, column));
+        }
+        let escaped = self.advance();
+        let ch = match escaped {
+            '"' => '"',
+            '\'' => '\'',
+            '\\' => '\\',
+            '/' => '/',
+            '0' => '\0',
+            'b' => '\x08',
+            'f' => '\x0C',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\x0B',
+            'x' => {
+                // Byte escape; consecutive bytes are decoded together as UTF-8
+                let digits = self.take_chars(2, quote);
+                let byte = parse_hex(&digits, 2)
+                    .ok_or(LexerError::InvalidByteEscape(digits, line, column))?;
+                buffer.push_byte(byte as u8, line, column);
+                return Ok(());
+            }
+            'u' => {
+                let digits = self.take_chars(4, quote);
+                let unit = parse_hex(&digits, 4)
+                    .ok_or_else(|| LexerError::InvalidUnicodeEscape(digits.clone(), line, column))?;
+                if (0xD800..=0xDBFF).contains(&unit) {
+                    self.low_surrogate(unit, quote, line, column)?
+                } else {
+                    char::from_u32(unit).ok_or(LexerError::InvalidUnicodeEscape(digits, line, column))?
+                }
+            }
+            'U' if self.peek() == '{' && !self.is_at_end() => {
+                self.advance(); // consume '{'
+                let mut digits = String::new();
+                while !self.is_at_end() && !matches!(self.peek(), '}' | '\n') && self.peek() != quote && digits.len() < 9 {
+                    digits.push(self.advance());
+                }
+                if self.peek() != '}' || self.is_at_end() {
+                    return Err(LexerError::InvalidCodePointEscape(digits, line, column));
+                }
+                self.advance(); // consume '}'
+                let valid_digits = (1..=8).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit());
+                valid_digits
+                    .then(|| digits.parse::<u32>().ok())
+                    .flatten()
+                    .and_then(char::from_u32)
+                    .ok_or(LexerError::InvalidCodePointEscape(digits, line, column))?
+            }
+            'U' => {
+                let digits = self.take_chars(5, quote);
+                parse_hex(&digits, 5)
+                    .and_then(char::from_u32)
+                    .ok_or(LexerError::InvalidLongUnicodeEscape(digits, line, column))?
+            }
+            _ => return Err(LexerError::InvalidEscapeSequence(escaped, line, column)),
+        };
+        buffer.push(ch)
+    }
+
+    /// Combine a `\uD800`-`\uDBFF` high surrogate with the `\uDC00`-`\uDFFF`
+    /// escape that must follow it.
+    fn low_surrogate(&mut self, high: u32, quote: char, line: usize, column: usize) -> Result<char, LexerError> {
+        let high_digits = format!("{:04X}", high);
+        if self.peek() != '\\' || self.is_at_end() {
+            return Err(LexerError::UnpairedSurrogate(high_digits, line, column));
+        }
+        self.advance(); // consume '\\'
+        if self.peek() != 'u' || self.is_at_end() {
+            return Err(LexerError::UnpairedSurrogate(high_digits, line, column));
+        }
+        self.advance(); // consume 'u'
+        let digits = self.take_chars(4, quote);
+        match parse_hex(&digits, 4) {
+            Some(low) if (0xDC00..=0xDFFF).contains(&low) => {
+                let code_point = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                char::from_u32(code_point).ok_or(LexerError::UnpairedSurrogate(high_digits, line, column))
+            }
+            _ => Err(LexerError::UnpairedSurrogate(high_digits, line, column)),
+        }
+    }
+
+    /// Take up to `count` characters, stopping early at the end of input, a
+    /// newline or the closing `quote`.
+    fn take_chars(&mut self, count: usize, quote: char) -> String {
+        let mut taken = String::new();
+        while taken.chars().count() < count && !self.is_at_end() && self.peek() != '\n' && self.peek() != quote {
+            taken.push(self.advance());
         }
+        taken
+    }
+
+    /// Read a `/pattern/flags` regex literal after its opening slash. The
+    /// pattern is kept verbatim; a slash inside a character class or after a
+    /// backslash does not end it. The pattern is checked here so mistakes are
+    /// reported with a position.
+    fn regex(&mut self, start_line: usize, start_column: usize) -> Result<TokenType, LexerError> {
+        let mut pattern = String::new();
+        let mut in_class = false;
         
-        self.advance(); // Consume closing quote
-        Ok(TokenType::String(value))
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(LexerError::UnterminatedRegex(start_line, start_column));
+            }
+            
+            match self.advance() {
+                '/' if !in_class => break,
+                '\\' => {
+                    pattern.push('\\');
+                    if self.is_at_end() || self.peek() == '\n' {
+                        return Err(LexerError::UnterminatedRegex(start_line, start_column));
+                    }
+                    pattern.push(self.advance());
+                }
+                ch => {
+                    match ch {
+                        '[' => in_class = true,
+                        ']' => in_class = false,
+                        _ => {}
+                    }
+                    pattern.push(ch);
+                }
+            }
+        }
+        
+        let mut flags = String::new();
+        while !self.is_at_end() && self.peek().is_ascii_alphabetic() {
+            let flag = self.advance();
+            if !REGEX_FLAGS.contains(flag) || flags.contains(flag) {
+                return Err(LexerError::InvalidRegexFlag(flag, start_line, start_column));
+            }
+            flags.push(flag);
+        }
+        
+        regex::Regex::new(&regex_source(&pattern, &flags))
+            .map_err(|e| LexerError::InvalidRegex(e.to_string(), start_line, start_column))?;
+        
+        Ok(TokenType::Regex { pattern, flags })
+    }
+
+    /// Read a `$"..."` template string. `{expr}` embeds an expression and
+    /// `\{` / `\}` produce literal braces.
+    fn template_string(&mut self, quote: char, start_line: usize, start_column: usize) -> Result<TokenType, LexerError> {
+        let mut segments = Vec::new();
+        let mut buffer = StringBuffer::default();
+        
+        loop {
+            if self.is_at_end() {
+                return Err(LexerError::UnterminatedString(start_line, start_column));
+            }
+            
+            let (line, column) = (self.line, self.column);
+            match self.advance() {
+                ch if ch == quote => break,
+                '{' => {
+                    let text = std::mem::take(&mut buffer).finish()?;
+                    if !text.is_empty() {
+                        segments.push(TemplateSegment::Text(text));
+                    }
+                    let tokens = self.template_expression(line, column)?;
+                    segments.push(TemplateSegment::Expression(tokens));
+                }
+                '\\' if matches!(self.peek(), '{' | '}') && !self.is_at_end() => {
+                    let brace = self.advance();
+                    buffer.push(brace)?;
+                }
+                '\\' => self.escape_sequence(&mut buffer, quote, line, column)?,
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                    buffer.push('\n')?;
+                }
+                ch => buffer.push(ch)?,
+            }
+        }
+        
+        let text = buffer.finish()?;
+        if !text.is_empty() {
+            segments.push(TemplateSegment::Text(text));
+        }
+        Ok(TokenType::TemplateString(segments))
     }
 
-    fn annotation(&mut self) -> Result<TokenType, LexerError> {
-        let mut annotation = String::from("@");
+    /// Read the rest of a `//service/path?x={x}` call after the `//`. The URL
+    /// runs up to whitespace or a closing delimiter, with `{...}` embedding
+    /// expressions like a template string.
+    fn http_url(&mut self) -> Result<TokenType, LexerError> {
+        let mut segments = Vec::new();
+        let mut text = String::from("//");
         
-        // Read the full annotation including any special characters
         while !self.is_at_end() {
-            let ch = self.peek();
-            if ch.is_whitespace() || ch == ',' || ch == '}' || ch == ')' || ch == ']' {
-                break;
+            let (line, column) = (self.line, self.column);
+            match self.peek() {
+                ch if ch.is_whitespace() => break,
+                ',' | '(' | ')' | '[' | ']' | '}' => break,
+                '{' => {
+                    self.advance();
+                    if !text.is_empty() {
+                        segments.push(TemplateSegment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(TemplateSegment::Expression(self.template_expression(line, column)?));
+                }
+                _ => text.push(self.advance()),
             }
-            annotation.push(self.advance());
         }
         
-        // Match specific annotations to their token types
-        match annotation.as_str() {
-            "@promise" => Ok(TokenType::Promise),
-            "@endpoint" => Ok(TokenType::Endpoint),
-            "@method" => Ok(TokenType::Method),
-            "@http" => Ok(TokenType::Http),
-            "@test" => Ok(TokenType::Test),
-            _ => Ok(TokenType::Identifier(annotation)),
+        if !text.is_empty() {
+            segments.push(TemplateSegment::Text(text));
+        }
+        Ok(TokenType::HttpUrl(segments))
+    }
+
+    /// Lex the tokens of an embedded template expression up to its closing
+    /// brace, keeping track of nested object literals.
+    fn template_expression(&mut self, open_line: usize, open_column: usize) -> Result<Vec<Token>, LexerError> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        self.regex_allowed = true;
+        
+        loop {
+            self.skip_whitespace()?;
+            if self.is_at_end() {
+                return Err(LexerError::UnterminatedTemplateExpression(open_line, open_column));
+            }
+            
+            if self.peek() == '}' && depth == 0 {
+                tokens.push(Token::new(TokenType::Eof, Span::new(self.offset, self.offset, self.line), self.column));
+                self.advance();
+                return Ok(tokens);
+            }
+            
+            let token = self.next_token()?;
+            match token.token_type {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn annotation(&mut self, line: usize, column: usize) -> Result<TokenType, LexerError> {
+        // The name is an identifier, so `@name(args)` stops before the `(`
+        if self.is_at_end() || !is_identifier_start(self.peek()) {
+            return Err(LexerError::UnexpectedCharacter('@', line, column));
+        }
+        let mut name = String::new();
+        while !self.is_at_end() && is_identifier_continue(self.peek()) {
+            name.push(self.advance());
+        }
+        
+        match name.as_str() {
+            "promise" => Ok(TokenType::Promise),
+            "import" => Ok(TokenType::Import),
+            _ => Ok(TokenType::Annotation(name)),
         }
     }
 
@@ -253,8 +713,8 @@ impl Lexer {
             number_str.push(self.advance());
         }
         
-        // Decimal part
-        if !self.is_at_end() && self.peek() == '.' {
+        // Decimal part; `1..5` is a range, not `1.` followed by `.5`
+        if !self.is_at_end() && self.peek() == '.' && self.input.get(self.position + 1) != Some(&'.') {
             number_str.push(self.advance());
             while !self.is_at_end() && self.peek().is_ascii_digit() {
                 number_str.push(self.advance());
@@ -272,7 +732,8 @@ impl Lexer {
             }
         }
         
-        number_str.parse::<f64>()
+        // Parse straight into a decimal so the literal keeps its exact digits and scale
+        BigDecimal::from_str(&number_str)
             .map(TokenType::Number)
             .map_err(|_| LexerError::InvalidNumber(number_str, self.line, self.column))
     }
@@ -280,7 +741,7 @@ impl Lexer {
     fn identifier_or_keyword(&mut self) -> Result<TokenType, LexerError> {
         let mut identifier = String::new();
         
-        while !self.is_at_end() && (self.peek().is_ascii_alphanumeric() || self.peek() == '_') {
+        while !self.is_at_end() && is_identifier_continue(self.peek()) {
             identifier.push(self.advance());
         }
         
@@ -292,52 +753,118 @@ impl Lexer {
         }
     }
 
-    fn skip_whitespace(&mut self) {
+    /// Read a backtick-quoted identifier. Any characters are allowed and a
+    /// doubled backtick stands for a single one. The result is never a keyword.
+    fn quoted_identifier(&mut self, start_line: usize, start_column: usize) -> Result<TokenType, LexerError> {
+        let mut identifier = String::new();
+
+        loop {
+            if self.is_at_end() {
+                return Err(LexerError::UnterminatedIdentifier(start_line, start_column));
+            }
+
+            match self.advance() {
+                '`' if self.peek() == '`' && !self.is_at_end() => {
+                    self.advance();
+                    identifier.push('`');
+                }
+                '`' if identifier.is_empty() => {
+                    return Err(LexerError::EmptyIdentifier(start_line, start_column));
+                }
+                '`' => break,
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                    identifier.push('\n');
+                }
+                ch => identifier.push(ch),
+            }
+        }
+
+        Ok(TokenType::Identifier(identifier))
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), LexerError> {
         while !self.is_at_end() {
             match self.peek() {
-                ' ' | '\r' | '\t' => {
+                ' ' | '\r' | '\t' | '\x0C' | '\x0B' | '\0' => {
                     self.advance();
                 }
                 '\n' => {
+                    self.advance();
                     self.line += 1;
                     self.column = 1;
-                    self.advance();
                 }
-                '/' if self.peek_next() == Some('/') => {
-                    // Line comment
-                    while !self.is_at_end() && self.peek() != '\n' {
-                        self.advance();
+                '#' => self.skip_comment()?,
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip a `#` line comment, or a block comment opened by a run of two or
+    /// more hashes and closed by the next run of the same length.
+    fn skip_comment(&mut self) -> Result<(), LexerError> {
+        let start_line = self.line;
+        let start_column = self.column;
+        let start = self.offset;
+        let opening = self.hash_run();
+
+        if opening == 1 {
+            while !self.is_at_end() && self.peek() != '\n' {
+                self.advance();
+            }
+            self.comments.push(Span::new(start, self.offset, start_line));
+            return Ok(());
+        }
+
+        while !self.is_at_end() {
+            match self.peek() {
+                '#' => {
+                    if self.hash_run() == opening {
+                        self.comments.push(Span::new(start, self.offset, start_line));
+                        return Ok(());
                     }
                 }
-                '/' if self.peek_next() == Some('*') => {
-                    // Block comment
-                    self.advance(); // consume '/'
-                    self.advance(); // consume '*'
-                    while !self.is_at_end() {
-                        if self.peek() == '*' && self.peek_next() == Some('/') {
-                            self.advance(); // consume '*'
-                            self.advance(); // consume '/'
-                            break;
-                        }
-                        if self.peek() == '\n' {
-                            self.line += 1;
-                            self.column = 1;
-                        }
-                        self.advance();
-                    }
+                '\n' => {
+                    self.advance();
+                    self.line += 1;
+                    self.column = 1;
+                }
+                _ => {
+                    self.advance();
                 }
-                _ => break,
             }
         }
+
+        Err(LexerError::UnterminatedBlockComment(start_line, start_column))
+    }
+
+    /// Consume a run of consecutive `#` characters and return its length.
+    fn hash_run(&mut self) -> usize {
+        let mut count = 0;
+        while !self.is_at_end() && self.peek() == '#' {
+            self.advance();
+            count += 1;
+        }
+        count
     }
 
     fn advance(&mut self) -> char {
         let ch = self.input[self.position];
         self.position += 1;
         self.column += 1;
+        self.offset += ch.len_utf8();
         ch
     }
 
+    /// Step back over the character just read, on the same line
+    fn retreat(&mut self) {
+        self.position -= 1;
+        self.column -= 1;
+        self.offset -= self.input[self.position].len_utf8();
+    }
+
     fn peek(&self) -> char {
         if self.is_at_end() {
             '\0'
@@ -346,20 +873,104 @@ impl Lexer {
         }
     }
 
-    fn peek_next(&self) -> Option<char> {
-        if self.position + 1 >= self.input.len() {
-            None
-        } else {
-            Some(self.input[self.position + 1])
+    fn is_at_end(&self) -> bool {
+        self.position >= self.input.len()
+    }
+}
+
+/// String contents under construction. Runs of `\x` byte escapes are held
+/// back and decoded together so multi-byte UTF-8 sequences can be spelled out.
+#[derive(Default)]
+struct StringBuffer {
+    value: String,
+    bytes: Vec<u8>,
+    bytes_start: (usize, usize),
+}
+
+impl StringBuffer {
+    fn push(&mut self, ch: char) -> Result<(), LexerError> {
+        self.flush_bytes()?;
+        self.value.push(ch);
+        Ok(())
+    }
+
+    fn push_byte(&mut self, byte: u8, line: usize, column: usize) {
+        if self.bytes.is_empty() {
+            self.bytes_start = (line, column);
         }
+        self.bytes.push(byte);
     }
 
-    fn is_at_end(&self) -> bool {
-        self.position >= self.input.len()
+    fn flush_bytes(&mut self) -> Result<(), LexerError> {
+        if self.bytes.is_empty() {
+            return Ok(());
+        }
+        let bytes = std::mem::take(&mut self.bytes);
+        let (line, column) = self.bytes_start;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| LexerError::InvalidByteSequence(line, column))?;
+        self.value.push_str(&text);
+        Ok(())
     }
+
+    fn finish(mut self) -> Result<String, LexerError> {
+        self.flush_bytes()?;
+        Ok(self.value)
+    }
+}
+
+/// Flags accepted after a regex literal. `g` makes `.match` and `.replace`
+/// act on every match; the rest map to inline regex flags.
+pub const REGEX_FLAGS: &str = "gimsx";
+
+/// Build the source handed to the regex engine for a literal's pattern and flags.
+pub fn regex_source(pattern: &str, flags: &str) -> String {
+    let inline: String = flags.chars().filter(|f| *f != 'g').collect();
+    if inline.is_empty() {
+        pattern.to_string()
+    } else {
+        format!("(?{}){}", inline, pattern)
+    }
+}
+
+/// Parse exactly `len` hex digits.
+fn parse_hex(digits: &str, len: usize) -> Option<u32> {
+    if digits.len() == len && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        u32::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Identifiers may start with a letter, an underscore, a non-combining
+/// alphanumeric in the BMP, or any supplementary character outside the
+/// private use planes. Digits of any script are never a first character.
+fn is_identifier_start(ch: char) -> bool {
+    if ch == '_' {
+        return true;
+    }
+    if ch.is_numeric() {
+        return false;
+    }
+    if (ch as u32) > 0xFFFF {
+        return !matches!(ch as u32, 0xF0000..=0x10FFFF);
+    }
+    ch.is_alphanumeric() && !is_combining_mark(ch)
+}
+
+/// After the first character, identifiers may also contain digits and
+/// combining marks.
+fn is_identifier_continue(ch: char) -> bool {
+    is_identifier_start(ch) || ch.is_numeric() || is_combining_mark(ch)
+}
+
+/// Characters that can continue but not start a Unicode identifier, other
+/// than digits: nonspacing, spacing and enclosing marks.
+fn is_combining_mark(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch) && !unicode_ident::is_xid_start(ch) && !ch.is_numeric() && ch != '_'
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum LexerError {
     #[error("Unexpected character '{0}' at line {1}, column {2}")]
     UnexpectedCharacter(char, usize, usize),
@@ -370,8 +981,44 @@ pub enum LexerError {
     #[error("Invalid escape sequence '\\{0}' at line {1}, column {2}")]
     InvalidEscapeSequence(char, usize, usize),
     
-    #[error("Invalid unicode escape at line {0}, column {1}")]
-    InvalidUnicodeEscape(usize, usize),
+    #[error("Invalid unicode escape '\\u{0}' at line {1}, column {2}: expected 4 hex digits")]
+    InvalidUnicodeEscape(String, usize, usize),
+    
+    #[error("Unpaired surrogate '\\u{0}' at line {1}, column {2}: expected a following low surrogate escape")]
+    UnpairedSurrogate(String, usize, usize),
+    
+    #[error("Invalid unicode escape '\\U{0}' at line {1}, column {2}: expected 5 hex digits")]
+    InvalidLongUnicodeEscape(String, usize, usize),
+    
+    #[error("Invalid code point escape '\\U{{{0}}}' at line {1}, column {2}: expected 1 to 8 decimal digits naming a valid character")]
+    InvalidCodePointEscape(String, usize, usize),
+    
+    #[error("Invalid byte escape '\\x{0}' at line {1}, column {2}: expected 2 hex digits")]
+    InvalidByteEscape(String, usize, usize),
+    
+    #[error("Byte escapes starting at line {0}, column {1} are not valid UTF-8")]
+    InvalidByteSequence(usize, usize),
+    
+    #[error("Unterminated backtick identifier starting at line {0}, column {1}")]
+    UnterminatedIdentifier(usize, usize),
+    
+    #[error("Empty backtick identifier at line {0}, column {1}")]
+    EmptyIdentifier(usize, usize),
+    
+    #[error("Unterminated template expression starting at line {0}, column {1}")]
+    UnterminatedTemplateExpression(usize, usize),
+    
+    #[error("Unterminated regex literal starting at line {0}, column {1}")]
+    UnterminatedRegex(usize, usize),
+    
+    #[error("Invalid regex flag '{0}' in literal at line {1}, column {2}")]
+    InvalidRegexFlag(char, usize, usize),
+    
+    #[error("Invalid regex at line {1}, column {2}: {0}")]
+    InvalidRegex(String, usize, usize),
+    
+    #[error("Unterminated block comment starting at line {0}, column {1}")]
+    UnterminatedBlockComment(usize, usize),
     
     #[error("Invalid number '{0}' at line {1}, column {2}")]
     InvalidNumber(String, usize, usize),
@@ -380,6 +1027,32 @@ pub enum LexerError {
     UnknownAnnotation(String, usize, usize),
 }
 
+impl LexerError {
+    /// The line and column the error was found at
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            LexerError::UnexpectedCharacter(_, line, column)
+            | LexerError::InvalidEscapeSequence(_, line, column)
+            | LexerError::InvalidUnicodeEscape(_, line, column)
+            | LexerError::UnpairedSurrogate(_, line, column)
+            | LexerError::InvalidLongUnicodeEscape(_, line, column)
+            | LexerError::InvalidCodePointEscape(_, line, column)
+            | LexerError::InvalidByteEscape(_, line, column)
+            | LexerError::InvalidRegexFlag(_, line, column)
+            | LexerError::InvalidRegex(_, line, column)
+            | LexerError::InvalidNumber(_, line, column)
+            | LexerError::UnknownAnnotation(_, line, column)
+            | LexerError::UnterminatedString(line, column)
+            | LexerError::InvalidByteSequence(line, column)
+            | LexerError::UnterminatedIdentifier(line, column)
+            | LexerError::EmptyIdentifier(line, column)
+            | LexerError::UnterminatedTemplateExpression(line, column)
+            | LexerError::UnterminatedRegex(line, column)
+            | LexerError::UnterminatedBlockComment(line, column) => (*line, *column),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,9 +1073,346 @@ mod tests {
         let mut lexer = Lexer::new("@endpoint @promise @method");
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token_type, TokenType::Identifier("@endpoint".to_string()));
-        assert_eq!(tokens[1].token_type, TokenType::Identifier("@promise".to_string()));
-        assert_eq!(tokens[2].token_type, TokenType::Identifier("@method".to_string()));
+        assert_eq!(tokens[0].token_type, TokenType::Annotation("endpoint".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Promise);
+        assert_eq!(tokens[2].token_type, TokenType::Annotation("method".to_string()));
+    }
+
+    #[test]
+    fn test_import_keyword() {
+        let mut lexer = Lexer::new("@import @cache");
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token_type, TokenType::Import);
+        assert_eq!(tokens[1].token_type, TokenType::Annotation("cache".to_string()));
+    }
+
+    #[test]
+    fn test_decimal_numbers_keep_scale() {
+        let mut lexer = Lexer::new("0.10 123.00 1e3");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type.to_string(), "0.10");
+        assert_eq!(tokens[1].token_type.to_string(), "123.00");
+        assert_eq!(tokens[2].token_type, TokenType::Number(BigDecimal::from(1000)));
+    }
+
+    #[test]
+    fn test_hash_comments() {
+        let source = "# line comment\n{ a ## block\n comment ## : 1, ### keeps ## inside ### b: 2 } # trailing";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type.clone()).collect();
+
+        assert_eq!(types, vec![
+            TokenType::LeftBrace,
+            TokenType::Identifier("a".to_string()),
+            TokenType::Colon,
+            TokenType::Number(BigDecimal::from(1)),
+            TokenType::Comma,
+            TokenType::Identifier("b".to_string()),
+            TokenType::Colon,
+            TokenType::Number(BigDecimal::from(2)),
+            TokenType::RightBrace,
+            TokenType::Eof,
+        ]);
+        assert_eq!(tokens[3].line, 3);
+
+        let comments: Vec<_> = lexer.comments().iter().map(|span| &source[span.start..span.end]).collect();
+        assert_eq!(comments, ["# line comment", "## block\n comment ##", "### keeps ## inside ###", "# trailing"]);
+    }
+
+    #[test]
+    fn test_comments_do_not_join_tokens() {
+        let mut lexer = Lexer::new("white## comment ##space");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Identifier("white".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier("space".to_string()));
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut lexer = Lexer::new("{\n  a: 1 ## never closed\n  # still inside\n}");
+
+        match lexer.tokenize() {
+            Err(LexerError::UnterminatedBlockComment(line, column)) => {
+                assert_eq!((line, column), (2, 8));
+            }
+            other => panic!("Expected UnterminatedBlockComment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let mut lexer = Lexer::new("名前: 1, größe: 2, cafe\u{301}: 3, 𝒳: 4");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Identifier("名前".to_string()));
+        assert_eq!(tokens[4].token_type, TokenType::Identifier("größe".to_string()));
+        // 'e' followed by a combining acute accent
+        assert_eq!(tokens[8].token_type, TokenType::Identifier("cafe\u{301}".to_string()));
+        assert_eq!(tokens[12].token_type, TokenType::Identifier("𝒳".to_string()));
+    }
+
+    #[test]
+    fn test_identifier_cannot_start_with_combining_mark() {
+        let mut lexer = Lexer::new("\u{301}abc");
+
+        assert!(matches!(lexer.tokenize(), Err(LexerError::UnexpectedCharacter('\u{301}', _, _))));
+    }
+
+    #[test]
+    fn test_identifier_cannot_start_with_a_digit() {
+        let mut lexer = Lexer::new("\u{661}abc");
+        assert!(matches!(lexer.tokenize(), Err(LexerError::UnexpectedCharacter('\u{661}', 1, 1))));
+
+        // Digits of other scripts are still fine after the first character
+        let mut lexer = Lexer::new("x\u{661}: 1");
+        assert_eq!(lexer.tokenize().unwrap()[0].token_type, TokenType::Identifier("x\u{661}".to_string()));
+    }
+
+    #[test]
+    fn test_backtick_identifiers() {
+        let mut lexer = Lexer::new("`this is an identifier with a `` backtick character` `null` `true` `false` null");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Identifier("this is an identifier with a ` backtick character".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier("null".to_string()));
+        assert_eq!(tokens[2].token_type, TokenType::Identifier("true".to_string()));
+        assert_eq!(tokens[3].token_type, TokenType::Identifier("false".to_string()));
+        assert_eq!(tokens[4].token_type, TokenType::Null);
+    }
+
+    #[test]
+    fn test_unterminated_backtick_identifier() {
+        let mut lexer = Lexer::new("{ `open: 1 }");
+
+        match lexer.tokenize() {
+            Err(LexerError::UnterminatedIdentifier(line, column)) => assert_eq!((line, column), (1, 3)),
+            other => panic!("Expected UnterminatedIdentifier, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_backtick_identifier() {
+        let mut lexer = Lexer::new("{ ``: 1 }");
+
+        assert!(matches!(lexer.tokenize(), Err(LexerError::EmptyIdentifier(1, 3))));
+    }
+
+    fn lex_string(source: &str) -> Result<String, LexerError> {
+        let mut lexer = Lexer::new(source);
+        match lexer.tokenize()?.remove(0).token_type {
+            TokenType::String(value) => Ok(value),
+            other => panic!("Expected string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_single_quoted_strings() {
+        assert_eq!(lex_string(r#"'say "hi"'"#).unwrap(), "say \"hi\"");
+        assert_eq!(lex_string(r"'it\'s'").unwrap(), "it's");
+        assert_eq!(lex_string(r#""it's""#).unwrap(), "it's");
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(lex_string(r#"@"C:\temp\new""#).unwrap(), r"C:\temp\new");
+        assert_eq!(lex_string(r"@'\d+\.\d+'").unwrap(), r"\d+\.\d+");
+        assert_eq!(lex_string(r#"@"say ""hi""""#).unwrap(), r#"say "hi""#);
+        assert_eq!(lex_string(r#"@"\""#).unwrap(), r"\");
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        assert_eq!(lex_string(r#""\x41\x42""#).unwrap(), "AB");
+        assert_eq!(lex_string(r#""caf\xC3\xA9""#).unwrap(), "café");
+        assert_eq!(lex_string(r#""\u00e9""#).unwrap(), "é");
+        assert_eq!(lex_string(r#""\uD83D\uDE00""#).unwrap(), "😀");
+        assert_eq!(lex_string(r#""\U1F600""#).unwrap(), "😀");
+        assert_eq!(lex_string(r#""\U{128512}""#).unwrap(), "😀");
+        assert_eq!(lex_string(r#""\U{65}\t\0""#).unwrap(), "A\t\0");
+    }
+
+    #[test]
+    fn test_malformed_escapes() {
+        assert!(matches!(lex_string(r#""\xZ1""#), Err(LexerError::InvalidByteEscape(d, 1, 2)) if d == "Z1"));
+        assert!(matches!(lex_string(r#""a\xC3b""#), Err(LexerError::InvalidByteSequence(1, 3))));
+        assert!(matches!(lex_string(r#""\u12""#), Err(LexerError::InvalidUnicodeEscape(d, 1, 2)) if d == "12"));
+        assert!(matches!(lex_string(r#""\uD83Dx""#), Err(LexerError::UnpairedSurrogate(d, 1, 2)) if d == "D83D"));
+        assert!(matches!(lex_string(r#""\U1F60""#), Err(LexerError::InvalidLongUnicodeEscape(..))));
+        assert!(matches!(lex_string(r#""\U0D800""#), Err(LexerError::InvalidLongUnicodeEscape(d, ..)) if d == "0D800"));
+        assert!(matches!(lex_string(r#""\U{1114112}""#), Err(LexerError::InvalidCodePointEscape(d, ..)) if d == "1114112"));
+        assert!(matches!(lex_string(r#""\U{12a}""#), Err(LexerError::InvalidCodePointEscape(..))));
+        assert!(matches!(lex_string(r#""\U{}""#), Err(LexerError::InvalidCodePointEscape(..))));
+        assert!(matches!(lex_string(r#""\q""#), Err(LexerError::InvalidEscapeSequence('q', 1, 2))));
+        // Escape digits stop at the closing quote instead of swallowing it
+        assert!(matches!(lex_string(r#"'ab\xZ'"#), Err(LexerError::InvalidByteEscape(d, 1, 4)) if d == "Z"));
+        assert!(matches!(lex_string(r#""\U1F""#), Err(LexerError::InvalidLongUnicodeEscape(d, 1, 2)) if d == "1F"));
+        assert!(matches!(lex_string(r#""\U{12""#), Err(LexerError::InvalidCodePointEscape(d, 1, 2)) if d == "12"));
+        assert!(matches!(lex_string(r#"$"x \x4""#), Err(LexerError::InvalidByteEscape(d, 1, 5)) if d == "4"));
+        assert!(matches!(lex_string("'open"), Err(LexerError::UnterminatedString(1, 1))));
+    }
+
+    #[test]
+    fn test_template_string() {
+        let mut lexer = Lexer::new(r#"$"Hi {user.name}, \{literal\} {count + 1}!""#);
+        let tokens = lexer.tokenize().unwrap();
+
+        let segments = match &tokens[0].token_type {
+            TokenType::TemplateString(segments) => segments,
+            other => panic!("Expected template string, got {:?}", other),
+        };
+        assert_eq!(segments.len(), 5);
+        assert_eq!(segments[0], TemplateSegment::Text("Hi ".to_string()));
+        match &segments[1] {
+            TemplateSegment::Expression(tokens) => {
+                let types: Vec<_> = tokens.iter().map(|t| t.token_type.clone()).collect();
+                assert_eq!(types, vec![
+                    TokenType::Identifier("user".to_string()),
+                    TokenType::Dot,
+                    TokenType::Identifier("name".to_string()),
+                    TokenType::Eof,
+                ]);
+            }
+            other => panic!("Expected expression segment, got {:?}", other),
+        }
+        assert_eq!(segments[2], TemplateSegment::Text(", {literal} ".to_string()));
+        assert_eq!(segments[4], TemplateSegment::Text("!".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_template_string_nested_braces() {
+        let mut lexer = Lexer::new(r#"$"{ {a: "}"}.a }""#);
+        let tokens = lexer.tokenize().unwrap();
+
+        match &tokens[0].token_type {
+            TokenType::TemplateString(segments) => {
+                assert_eq!(segments.len(), 1);
+                assert!(matches!(&segments[0], TemplateSegment::Expression(tokens) if tokens.len() == 8));
+            }
+            other => panic!("Expected template string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unterminated_template_expression() {
+        let mut lexer = Lexer::new("$\"total: {price");
+        match lexer.tokenize() {
+            Err(LexerError::UnterminatedTemplateExpression(line, column)) => assert_eq!((line, column), (1, 10)),
+            other => panic!("Expected UnterminatedTemplateExpression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_regex_literals() {
+        let mut lexer = Lexer::new(r"isValid: /[0-5][0-9]/.test(second), path: /a\/b[/]/gi");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[2].token_type, TokenType::Regex { pattern: "[0-5][0-9]".to_string(), flags: String::new() });
+        assert_eq!(tokens[3].token_type, TokenType::Dot);
+        assert_eq!(tokens[11].token_type, TokenType::Regex { pattern: r"a\/b[/]".to_string(), flags: "gi".to_string() });
+    }
+
+    #[test]
+    fn test_slash_after_operand_is_division() {
+        let mut lexer = Lexer::new("a / b / (c) / 2");
+        let tokens = lexer.tokenize().unwrap();
+        let divides = tokens.iter().filter(|t| t.token_type == TokenType::Divide).count();
+
+        assert_eq!(divides, 3);
+    }
+
+    #[test]
+    fn test_double_slash_is_not_regex() {
+        let mut lexer = Lexer::new("x: //service/get-item?id={item.id}&v=2, y: 1");
+        let tokens = lexer.tokenize().unwrap();
+
+        let TokenType::HttpUrl(segments) = &tokens[2].token_type else {
+            panic!("Expected HTTP url, got {:?}", tokens[2].token_type);
+        };
+        assert_eq!(segments[0], TemplateSegment::Text("//service/get-item?id=".to_string()));
+        assert!(matches!(&segments[1], TemplateSegment::Expression(tokens) if tokens.len() == 4));
+        assert_eq!(segments[2], TemplateSegment::Text("&v=2".to_string()));
+        
+        // The url ends at the comma, and a slash after it divides
+        assert_eq!(tokens[3].token_type, TokenType::Comma);
+        let tokens = Lexer::new("a: //svc/x / 2").tokenize().unwrap();
+        assert_eq!(tokens[3].token_type, TokenType::Divide);
+    }
+
+    #[test]
+    fn test_invalid_regex_literals() {
+        assert!(matches!(Lexer::new("/abc").tokenize(), Err(LexerError::UnterminatedRegex(1, 1))));
+        assert!(matches!(Lexer::new("/abc/q").tokenize(), Err(LexerError::InvalidRegexFlag('q', 1, 1))));
+        assert!(matches!(Lexer::new("/abc/ii").tokenize(), Err(LexerError::InvalidRegexFlag('i', 1, 1))));
+        assert!(matches!(Lexer::new("/a(b/").tokenize(), Err(LexerError::InvalidRegex(_, 1, 1))));
+    }
+
+    #[test]
+    fn test_regex_source_applies_inline_flags() {
+        assert_eq!(regex_source("abc", ""), "abc");
+        assert_eq!(regex_source("abc", "g"), "abc");
+        assert_eq!(regex_source("abc", "gim"), "(?im)abc");
+    }
+
+    #[test]
+    fn test_comparison_and_boolean_operators() {
+        let mut lexer = Lexer::new("a < b <= c > d >= e = f != !g & h");
+        let operators: Vec<TokenType> = lexer.tokenize().unwrap().into_iter()
+            .map(|t| t.token_type)
+            .filter(|t| !matches!(t, TokenType::Identifier(_) | TokenType::Eof))
+            .collect();
+
+        assert_eq!(operators, vec![
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+            TokenType::Equal,
+            TokenType::NotEqual,
+            TokenType::Not,
+            TokenType::And,
+        ]);
+    }
+
+    #[test]
+    fn test_conditional_tokens() {
+        let mut lexer = Lexer::new("a ? b : c");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[1].token_type, TokenType::Question);
+        assert_eq!(tokens[3].token_type, TokenType::Colon);
+    }
+
+    #[test]
+    fn test_range_tokens() {
+        let mut lexer = Lexer::new("200..299 ..0 1.5.. [a, ..rest]");
+        let tokens: Vec<TokenType> = lexer.tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+
+        assert_eq!(tokens[..3], [TokenType::Number(BigDecimal::from(200)), TokenType::DotDot, TokenType::Number(BigDecimal::from(299))]);
+        assert_eq!(tokens[3], TokenType::DotDot);
+        assert_eq!(tokens[5].to_string(), "1.5");
+        assert_eq!(tokens[6], TokenType::DotDot);
+        assert_eq!(tokens[10], TokenType::DotDot);
+    }
+
+    #[test]
+    fn test_null_safe_tokens() {
+        let mut lexer = Lexer::new("a?.b ?? c ? d : 1");
+        let tokens: Vec<TokenType> = lexer.tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+
+        assert_eq!(tokens[1], TokenType::QuestionDot);
+        assert_eq!(tokens[3], TokenType::DoubleQuestion);
+        assert_eq!(tokens[5], TokenType::Question);
+        assert_eq!(tokens[7], TokenType::Colon);
+        
+        let mut lexer = Lexer::new("c ?.5 : 1");
+        let tokens: Vec<TokenType> = lexer.tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+        assert_eq!(tokens[1], TokenType::Question);
+        assert_eq!(tokens[2].to_string(), "0.5");
     }
 
     #[test]
@@ -419,10 +1429,18 @@ mod tests {
 
     #[test]
     fn test_complex_annotations() {
-        let mut lexer = Lexer::new("@endpoint:GET:/api/users @promise:fetch_user");
+        let mut lexer = Lexer::new(r#"@endpoint(GET, "/api/users") @test{equals: 1}"#);
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token_type, TokenType::Identifier("@endpoint:GET:/api/users".to_string()));
-        assert_eq!(tokens[1].token_type, TokenType::Identifier("@promise:fetch_user".to_string()));
+        assert_eq!(tokens[0].token_type, TokenType::Annotation("endpoint".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::LeftParen);
+        assert_eq!(tokens[2].token_type, TokenType::Identifier("GET".to_string()));
+        assert_eq!(tokens[4].token_type, TokenType::String("/api/users".to_string()));
+        assert_eq!(tokens[6].token_type, TokenType::Annotation("test".to_string()));
+        assert_eq!(tokens[7].token_type, TokenType::LeftBrace);
+        
+        // A bare `@` names nothing
+        let mut lexer = Lexer::new("@ endpoint");
+        assert!(matches!(lexer.tokenize(), Err(LexerError::UnexpectedCharacter('@', 1, 1))));
     }
 }
@@ -1,6 +1,12 @@
+// Types are built with `new()` and don't also implement `Default`
+#![allow(clippy::new_without_default)]
+
 pub mod lexer;
 pub mod parser;
 pub mod analyzer;
+pub mod project;
+pub mod diagnostics;
+pub mod formatter;
 pub mod codegen;
 pub mod runtime;
 pub mod cli;
@@ -9,7 +15,13 @@ pub mod test_runner;
 pub use lexer::*;
 pub use parser::*;
 pub use analyzer::*;
+pub use project::*;
+pub use diagnostics::*;
+pub use formatter::*;
 pub use codegen::*;
 pub use runtime::*;
 pub use cli::*;
 pub use test_runner::*;
+// The analyzer and the bytecode both describe endpoints and functions; the
+// bytecode's are the ones exported here
+pub use codegen::{EndpointInfo, FunctionInfo};
@@ -1,6 +1,8 @@
-use crate::lexer::{Token, TokenType};
+use crate::lexer::{Span, TemplateSegment, Token, TokenType};
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::fmt;
+use indexmap::IndexMap;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AstNode {
@@ -11,7 +13,37 @@ pub enum AstNode {
     Assignment {
         name: String,
         value: Box<AstNode>,
-        line: usize,
+        span: Span,
+    },
+    
+    // `(a, b): value` binds each name to an element of the tuple `value`
+    Destructure {
+        names: Vec<String>,
+        value: Box<AstNode>,
+        span: Span,
+    },
+    
+    // `namespace: @import "path.loaf"`, another file's globals under a namespace
+    Import {
+        namespace: String,
+        path: String,
+        span: Span,
+    },
+    
+    // `name(a, b): body`, a function with its own scope
+    Function {
+        name: String,
+        parameters: Vec<String>,
+        body: Box<AstNode>,
+        span: Span,
+    },
+    
+    // Annotations written before a declaration. At the top level
+    // `declaration` is the whole declaration, on an object field the value.
+    Annotated {
+        annotations: Vec<Annotation>,
+        declaration: Box<AstNode>,
+        span: Span,
     },
     
     // Expressions
@@ -19,77 +51,298 @@ pub enum AstNode {
         left: Box<AstNode>,
         operator: BinaryOp,
         right: Box<AstNode>,
-        line: usize,
+        span: Span,
     },
     
     Unary {
         operator: UnaryOp,
         operand: Box<AstNode>,
-        line: usize,
+        span: Span,
     },
     
     MemberAccess {
         object: Box<AstNode>,
         property: String,
-        line: usize,
+        null_safe: bool, // `?.` yields null instead of failing on a non-object
+        span: Span,
+    },
+    
+    MethodCall {
+        object: Box<AstNode>,
+        method: String,
+        arguments: Vec<AstNode>,
+        span: Span,
+    },
+    
+    // `object[index]`, an array element or a computed object key
+    Index {
+        object: Box<AstNode>,
+        index: Box<AstNode>,
+        span: Span,
+    },
+    
+    // `condition ? then_branch : else_branch`, only the taken branch runs
+    Conditional {
+        condition: Box<AstNode>,
+        then_branch: Box<AstNode>,
+        else_branch: Box<AstNode>,
+        span: Span,
+    },
+    
+    // `match subject { pattern: value, ... }`, only the first matching arm runs
+    Match {
+        subject: Box<AstNode>,
+        arms: Vec<MatchArm>,
+        span: Span,
     },
     
     // Literals
-    String(String),
-    Number(f64),
-    Boolean(bool),
-    Null,
-    Identifier(String),
+    String(String, Span),
+    Number(BigDecimal, Span),
+    Boolean(bool, Span),
+    Null(Span),
+    Identifier(String, Span),
+    Regex {
+        pattern: String,
+        flags: String,
+        span: Span,
+    },
+    
+    // Template string with embedded expressions
+    Template {
+        parts: Vec<TemplatePart>,
+        span: Span,
+    },
     
     // JSON Objects and Arrays
     Object {
-        fields: HashMap<String, AstNode>,
-        line: usize,
+        // Every field in declaration order, named or keyed by a computed value
+        fields: Vec<(ObjectKey, AstNode)>,
+        span: Span,
     },
     
     Array {
         elements: Vec<AstNode>,
-        line: usize,
+        span: Span,
+    },
+    
+    // `(a, b)`, a fixed number of values
+    Tuple {
+        elements: Vec<AstNode>,
+        span: Span,
     },
     
     // Function calls
     FunctionCall {
         name: String,
         arguments: Vec<AstNode>,
-        line: usize,
+        span: Span,
     },
     
-    // Annotations and special constructs
+    // Special constructs
     Promise {
         expression: Box<AstNode>,
-        line: usize,
-    },
-    
-    Endpoint {
-        name: String,
-        method: HttpMethod,
-        path: String,
-        handler: Box<AstNode>,
-        line: usize,
+        span: Span,
     },
     
     HttpCall {
         url: Box<AstNode>,
         method: HttpMethod,
         body: Option<Box<AstNode>>,
-        headers: Option<HashMap<String, AstNode>>,
-        line: usize,
+        headers: Option<IndexMap<String, AstNode>>,
+        span: Span,
     },
-    
-    // Test declarations
-    Test {
-        name: String,
-        expect_expression: Box<AstNode>,
-        inputs: HashMap<String, AstNode>,
-        expected_output: Box<AstNode>,
-        is_regex: bool,
-        line: usize,
+}
+
+impl AstNode {
+    /// The source text the node was parsed from; the program root has none
+    pub fn span(&self) -> Span {
+        match self {
+            AstNode::Program(_) => Span::default(),
+            AstNode::String(_, span)
+            | AstNode::Number(_, span)
+            | AstNode::Boolean(_, span)
+            | AstNode::Null(span)
+            | AstNode::Identifier(_, span)
+            | AstNode::Assignment { span, .. }
+            | AstNode::Destructure { span, .. }
+            | AstNode::Import { span, .. }
+            | AstNode::Function { span, .. }
+            | AstNode::Annotated { span, .. }
+            | AstNode::Binary { span, .. }
+            | AstNode::Unary { span, .. }
+            | AstNode::MemberAccess { span, .. }
+            | AstNode::MethodCall { span, .. }
+            | AstNode::Index { span, .. }
+            | AstNode::Conditional { span, .. }
+            | AstNode::Match { span, .. }
+            | AstNode::Regex { span, .. }
+            | AstNode::Template { span, .. }
+            | AstNode::Object { span, .. }
+            | AstNode::Array { span, .. }
+            | AstNode::Tuple { span, .. }
+            | AstNode::FunctionCall { span, .. }
+            | AstNode::Promise { span, .. }
+            | AstNode::HttpCall { span, .. } => *span,
+        }
+    }
+
+    /// The nodes directly inside this one, in no particular order
+    pub fn children(&self) -> Vec<&AstNode> {
+        match self {
+            AstNode::Program(statements) => statements.iter().collect(),
+            AstNode::Assignment { value, .. } | AstNode::Destructure { value, .. } => vec![value],
+            AstNode::Function { body, .. } => vec![body],
+            AstNode::Annotated { annotations, declaration, .. } => annotations.iter()
+                .flat_map(|annotation| annotation.arguments.iter().chain(annotation.config.as_deref()))
+                .chain(std::iter::once(declaration.as_ref()))
+                .collect(),
+            AstNode::Binary { left, right, .. } => vec![left, right],
+            AstNode::Unary { operand, .. } => vec![operand],
+            AstNode::MemberAccess { object, .. } => vec![object],
+            AstNode::MethodCall { object, arguments, .. } => std::iter::once(object.as_ref()).chain(arguments).collect(),
+            AstNode::Index { object, index, .. } => vec![object, index],
+            AstNode::Conditional { condition, then_branch, else_branch, .. } => vec![condition, then_branch, else_branch],
+            AstNode::Match { subject, arms, .. } => std::iter::once(subject.as_ref())
+                .chain(arms.iter().map(|arm| &arm.value))
+                .collect(),
+            AstNode::Template { parts, .. } => parts.iter()
+                .filter_map(|part| match part {
+                    TemplatePart::Expression(expression) => Some(expression),
+                    TemplatePart::Text(_) => None,
+                })
+                .collect(),
+            AstNode::Object { fields, .. } => fields.iter()
+                .flat_map(|(key, value)| match key {
+                    ObjectKey::Name(_) => vec![value],
+                    ObjectKey::Computed(key) => vec![key, value],
+                })
+                .collect(),
+            AstNode::Array { elements, .. } | AstNode::Tuple { elements, .. } => elements.iter().collect(),
+            AstNode::FunctionCall { arguments, .. } => arguments.iter().collect(),
+            AstNode::Promise { expression, .. } => vec![expression],
+            AstNode::HttpCall { url, body, headers, .. } => std::iter::once(url.as_ref())
+                .chain(body.as_deref())
+                .chain(headers.iter().flat_map(|headers| headers.values()))
+                .collect(),
+            AstNode::Import { .. }
+            | AstNode::String(..)
+            | AstNode::Number(..)
+            | AstNode::Boolean(..)
+            | AstNode::Null(_)
+            | AstNode::Identifier(..)
+            | AstNode::Regex { .. } => Vec::new(),
+        }
+    }
+
+    /// The value of an object's field called `name`
+    pub fn field(&self, name: &str) -> Option<&AstNode> {
+        match self {
+            AstNode::Object { fields, .. } => fields.iter()
+                .find(|(key, _)| key.name() == Some(name))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// The key of an object field: a name, or a number, array, object or other
+/// computed value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObjectKey {
+    Name(String),
+    Computed(AstNode),
+}
+
+impl ObjectKey {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            ObjectKey::Name(name) => Some(name),
+            ObjectKey::Computed(_) => None,
+        }
+    }
+}
+
+/// `@name`, `@name(arguments)` or `@name { config }` before a declaration.
+/// The analyzer gives each name its meaning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub name: String,
+    pub arguments: Vec<AstNode>,
+    // The `{ ... }` object, when there is one
+    pub config: Option<Box<AstNode>>,
+    pub span: Span,
+}
+
+impl Annotation {
+    /// The argument at `position`, or else the config field `name`, so
+    /// `@endpoint(GET, "/a")` and `@endpoint { method: GET, path: "/a" }` agree
+    pub fn argument(&self, position: usize, name: &str) -> Option<&AstNode> {
+        self.arguments.get(position).or_else(|| self.field(name))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&AstNode> {
+        self.config.as_deref().and_then(|config| config.field(name))
+    }
+}
+
+/// `pattern: value` inside a `match`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub value: AstNode,
+    pub span: Span,
+}
+
+/// What a `match` arm tests its subject against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    // `_`, matches anything
+    Wildcard,
+    // A name matches anything and holds the value in the arm
+    Binding(String, Span),
+    // A string, number, boolean or null, matched by equality
+    Literal(AstNode),
+    // `a..b`, `a..` or `..b`, the numbers between the bounds, both included
+    Range {
+        start: Option<BigDecimal>,
+        end: Option<BigDecimal>,
+    },
+    // A regex literal, matches the strings it finds a match in
+    Regex {
+        pattern: String,
+        flags: String,
+    },
+    // `[a, b]` matches arrays of two elements, `[a, ..rest]` arrays of at
+    // least one; `..` alone is a `Wildcard` rest
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
     },
+    // `{ status: 200, error }` matches objects that have these fields, and
+    // maybe others; `error` is short for `error: error`
+    Object(IndexMap<String, Pattern>),
+}
+
+impl Pattern {
+    /// Every name the pattern binds, in source order
+    pub fn bindings(&self) -> Vec<(&str, Span)> {
+        match self {
+            Pattern::Binding(name, span) => vec![(name.as_str(), *span)],
+            Pattern::Array { elements, rest } => elements.iter()
+                .chain(rest.as_deref())
+                .flat_map(Pattern::bindings)
+                .collect(),
+            Pattern::Object(fields) => fields.values().flat_map(Pattern::bindings).collect(),
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Regex { .. } => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TemplatePart {
+    Text(String),
+    Expression(AstNode),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -99,6 +352,13 @@ pub enum BinaryOp {
     Multiply,
     Divide,
     Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Coalesce,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -116,46 +376,173 @@ pub enum HttpMethod {
     Patch,
 }
 
+impl HttpMethod {
+    /// The method a name such as `GET` or `get` stands for
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "GET" => Some(HttpMethod::Get),
+            "POST" => Some(HttpMethod::Post),
+            "PUT" => Some(HttpMethod::Put),
+            "DELETE" => Some(HttpMethod::Delete),
+            "PATCH" => Some(HttpMethod::Patch),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HttpMethod {
+    /// The method as written in a request, such as `GET`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpMethod::Get => write!(f, "GET"),
+            HttpMethod::Post => write!(f, "POST"),
+            HttpMethod::Put => write!(f, "PUT"),
+            HttpMethod::Delete => write!(f, "DELETE"),
+            HttpMethod::Patch => write!(f, "PATCH"),
+        }
+    }
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    warnings: Vec<ParseWarning>,
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self { tokens, current: 0, warnings: Vec::new(), errors: Vec::new() }
+    }
+
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// Errors found by the last `parse_recovering`, in source order
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
     }
 
     pub fn parse(&mut self) -> Result<AstNode, ParseError> {
+        let program = self.parse_recovering();
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(program),
+        }
+    }
+
+    /// Parse the whole program, recording every error instead of stopping at
+    /// the first. A statement that fails is dropped and parsing picks up at
+    /// the next declaration; a failed object field is dropped and parsing
+    /// picks up at the next `,` or `}`.
+    pub fn parse_recovering(&mut self) -> AstNode {
         let mut statements = Vec::new();
+        self.errors.clear();
         
         while !self.is_at_end() {
-            if self.peek().token_type == TokenType::Eof {
+            let start = self.current;
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.report(error);
+                    self.synchronize_statement(start);
+                }
+            }
+        }
+        
+        AstNode::Program(statements)
+    }
+
+    /// Record an error, unless it is about a token the lexer already reported
+    fn report(&mut self, error: ParseError) {
+        if self.peek().token_type != TokenType::Invalid {
+            self.errors.push(error);
+        }
+    }
+
+    /// Skip past the statement starting at `start` to the next line that
+    /// starts a declaration. Brackets are not balanced here, so an unclosed
+    /// one doesn't swallow the rest of the file.
+    fn synchronize_statement(&mut self, start: usize) {
+        let mut index = self.current.max(start + 1);
+        while self.tokens[index].token_type != TokenType::Eof && !self.starts_declaration(index) {
+            index += 1;
+        }
+        self.current = index;
+    }
+
+    /// Skip past the object field starting at `start` to the `,` or `}` that
+    /// ends it
+    fn synchronize_field(&mut self, start: usize) {
+        let mut depth = 0usize;
+        let mut index = start;
+        
+        loop {
+            let token_type = &self.tokens[index].token_type;
+            let ends_field = matches!(token_type, TokenType::Comma | TokenType::RightBrace) && depth == 0;
+            if *token_type == TokenType::Eof || (index >= self.current && ends_field) {
                 break;
             }
-            statements.push(self.parse_statement()?);
+            depth = Self::nesting(depth, token_type);
+            index += 1;
+        }
+        
+        self.current = index;
+    }
+
+    /// The bracket depth after `token_type`
+    fn nesting(depth: usize, token_type: &TokenType) -> usize {
+        match token_type {
+            TokenType::LeftBrace | TokenType::LeftBracket | TokenType::LeftParen => depth + 1,
+            TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen => depth.saturating_sub(1),
+            _ => depth,
+        }
+    }
+
+    /// Whether the token at `index` is the first on its line and starts a
+    /// declaration or annotation
+    fn starts_declaration(&self, index: usize) -> bool {
+        let token = &self.tokens[index];
+        if index > 0 && self.tokens[index - 1].line == token.line {
+            return false;
         }
         
-        Ok(AstNode::Program(statements))
+        match &token.token_type {
+            TokenType::Annotation(_) | TokenType::Promise => true,
+            TokenType::LeftParen => self.parameter_count(index).is_some(),
+            TokenType::Identifier(_) => {
+                matches!(self.tokens.get(index + 1).map(|t| &t.token_type), Some(TokenType::Colon | TokenType::Equal))
+                    || self.parameter_count(index + 1).is_some()
+            }
+            _ => false,
+        }
     }
 
     fn parse_statement(&mut self) -> Result<AstNode, ParseError> {
-        // Check for annotations first
-        if let TokenType::Endpoint = self.peek().token_type {
-            return self.parse_endpoint();
+        if let TokenType::Annotation(_) = self.peek().token_type {
+            return self.parse_annotated();
         }
         
         if let TokenType::Promise = self.peek().token_type {
             return self.parse_promise();
         }
         
-        if let TokenType::Test = self.peek().token_type {
-            return self.parse_test();
+        if self.check(&TokenType::LeftParen) && self.parameter_count(self.current).is_some_and(|names| names > 1) {
+            return self.parse_destructuring();
         }
         
-        // Check for assignment
+        // Check for declaration (`name: expr`, or the deprecated `name = expr`)
         if let TokenType::Identifier(_) = self.peek().token_type {
-            if self.peek_next().map(|t| &t.token_type) == Some(&TokenType::Equal) {
+            if self.parameter_count(self.current + 1).is_some() {
+                return self.parse_function();
+            }
+            let is_import = self.tokens.get(self.current + 2)
+                .is_some_and(|token| token.token_type == TokenType::Import);
+            if is_import && self.peek_next().is_some_and(|token| token.token_type == TokenType::Colon) {
+                return self.parse_import();
+            }
+            if matches!(self.peek_next().map(|t| &t.token_type), Some(TokenType::Colon | TokenType::Equal)) {
                 return self.parse_assignment();
             }
         }
@@ -166,127 +553,276 @@ impl Parser {
 
     fn parse_assignment(&mut self) -> Result<AstNode, ParseError> {
         let name_token = self.advance();
-        let line = name_token.line;
+        let start = name_token.span;
         
         let name = match &name_token.token_type {
             TokenType::Identifier(name) => name.clone(),
-            _ => return Err(ParseError::ExpectedIdentifier(line)),
+            _ => return Err(ParseError::ExpectedIdentifier(start)),
         };
         
-        self.consume(TokenType::Equal, "Expected '=' after variable name")?;
+        if self.match_token_types(&[TokenType::Equal]) {
+            self.warnings.push(ParseWarning::DeprecatedAssignment(name.clone(), self.previous().span));
+        } else {
+            self.consume(TokenType::Colon, "Expected ':' after variable name")?;
+        }
         let value = self.parse_expression()?;
         
         Ok(AstNode::Assignment {
             name,
             value: Box::new(value),
-            line,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_endpoint(&mut self) -> Result<AstNode, ParseError> {
-        let endpoint_token = self.advance(); // consume @endpoint
-        let line = endpoint_token.line;
+    /// The number of names when the tokens from `open` read `(name, name, ...):`
+    fn parameter_count(&self, open: usize) -> Option<usize> {
+        if self.tokens.get(open)?.token_type != TokenType::LeftParen {
+            return None;
+        }
         
-        // Expect an object with endpoint configuration
-        let config = self.parse_object()?;
+        let mut expect_name = true;
+        let mut names = 0;
+        for (offset, token) in self.tokens[open + 1..].iter().enumerate() {
+            match (&token.token_type, expect_name) {
+                (TokenType::Identifier(_), true) => {
+                    names += 1;
+                    expect_name = false;
+                }
+                (TokenType::Comma, false) => expect_name = true,
+                (TokenType::RightParen, _) => {
+                    let next = self.tokens.get(open + offset + 2)?;
+                    return (next.token_type == TokenType::Colon).then_some(names);
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    fn parse_import(&mut self) -> Result<AstNode, ParseError> {
+        let name_token = self.advance();
+        let start = name_token.span;
+        let namespace = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => return Err(ParseError::ExpectedIdentifier(start)),
+        };
+        self.advance(); // consume ':'
+        self.advance(); // consume @import
+        
+        let path_token = self.advance();
+        match &path_token.token_type {
+            TokenType::String(path) => Ok(AstNode::Import {
+                namespace,
+                path: path.clone(),
+                span: start.to(path_token.span),
+            }),
+            _ => Err(ParseError::ExpectedString(path_token.span)),
+        }
+    }
+
+    fn parse_function(&mut self) -> Result<AstNode, ParseError> {
+        let name_token = self.advance();
+        let start = name_token.span;
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => return Err(ParseError::ExpectedIdentifier(start)),
+        };
+        
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+        let mut parameters = Vec::new();
+        while let TokenType::Identifier(parameter) = &self.peek().token_type {
+            if parameters.contains(parameter) {
+                return Err(ParseError::DuplicateParameter(parameter.clone(), self.peek().span));
+            }
+            parameters.push(parameter.clone());
+            self.advance();
+            if !self.match_token_types(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        self.consume(TokenType::Colon, "Expected ':' after parameters")?;
+        let body = self.parse_expression()?;
+        
+        Ok(AstNode::Function {
+            name,
+            parameters,
+            body: Box::new(body),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_destructuring(&mut self) -> Result<AstNode, ParseError> {
+        let start = self.advance().span; // consume '('
+        let mut names = Vec::new();
+        
+        loop {
+            let name_token = self.advance();
+            match &name_token.token_type {
+                TokenType::Identifier(name) => names.push(name.clone()),
+                _ => return Err(ParseError::ExpectedIdentifier(name_token.span)),
+            }
+            if !self.match_token_types(&[TokenType::Comma]) || self.check(&TokenType::RightParen) {
+                break;
+            }
+        }
+        
+        self.consume(TokenType::RightParen, "Expected ')' after destructured names")?;
+        self.consume(TokenType::Colon, "Expected ':' after destructured names")?;
+        let value = self.parse_expression()?;
+        
+        Ok(AstNode::Destructure {
+            names,
+            value: Box::new(value),
+            span: self.span_from(start),
+        })
+    }
+
+    /// Annotations and the declaration they are written before
+    fn parse_annotated(&mut self) -> Result<AstNode, ParseError> {
+        let annotations = self.parse_annotations()?;
+        let first = &annotations[0];
+        let misplaced = ParseError::MisplacedAnnotation(first.name.clone(), first.span);
+        let start = first.span;
+        if self.is_at_end() {
+            return Err(misplaced);
+        }
+        
+        let declaration = self.parse_statement()?;
+        if !matches!(declaration, AstNode::Assignment { .. } | AstNode::Function { .. } | AstNode::Destructure { .. }) {
+            return Err(misplaced);
+        }
+        Ok(AstNode::Annotated {
+            annotations,
+            declaration: Box::new(declaration),
+            span: self.span_from(start),
+        })
+    }
+
+    /// Any number of annotations. Arguments and config start on the line of
+    /// the annotation's name, so a declaration on the next line is not taken
+    /// for them.
+    fn parse_annotations(&mut self) -> Result<Vec<Annotation>, ParseError> {
+        let mut annotations = Vec::new();
         
-        if let AstNode::Object { fields, .. } = config {
-            let name = self.extract_string_field(&fields, "name", line)?;
-            let method_str = self.extract_string_field(&fields, "method", line)?;
-            let path = self.extract_string_field(&fields, "path", line)?;
+        while let TokenType::Annotation(name) = &self.peek().token_type {
+            let name = name.clone();
+            let start = self.advance().span;
+            let line = self.previous().line;
             
-            let method = match method_str.to_lowercase().as_str() {
-                "get" => HttpMethod::Get,
-                "post" => HttpMethod::Post,
-                "put" => HttpMethod::Put,
-                "delete" => HttpMethod::Delete,
-                "patch" => HttpMethod::Patch,
-                _ => return Err(ParseError::InvalidHttpMethod(method_str, line)),
-            };
+            let mut arguments = Vec::new();
+            if self.check(&TokenType::LeftParen) && self.peek().line == line {
+                self.advance();
+                while !self.check(&TokenType::RightParen) {
+                    arguments.push(self.parse_expression()?);
+                    if !self.match_token_types(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after annotation arguments")?;
+            }
             
-            let handler = fields.get("handler")
-                .ok_or_else(|| ParseError::MissingField("handler".to_string(), line))?
-                .clone();
+            let config = if self.check(&TokenType::LeftBrace) && self.peek().line == self.previous().line {
+                Some(Box::new(self.parse_object()?))
+            } else {
+                None
+            };
             
-            Ok(AstNode::Endpoint {
-                name,
-                method,
-                path,
-                handler: Box::new(handler),
-                line,
-            })
-        } else {
-            Err(ParseError::ExpectedObject(line))
+            annotations.push(Annotation { name, arguments, config, span: self.span_from(start) });
         }
+        
+        Ok(annotations)
     }
 
     fn parse_promise(&mut self) -> Result<AstNode, ParseError> {
-        let promise_token = self.advance(); // consume @promise
-        let line = promise_token.line;
+        let start = self.advance().span; // consume @promise
         
         let expression = self.parse_expression()?;
         
         Ok(AstNode::Promise {
             expression: Box::new(expression),
-            line,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_test(&mut self) -> Result<AstNode, ParseError> {
-        let test_token = self.advance(); // consume @test
-        let line = test_token.line;
+    fn parse_expression(&mut self) -> Result<AstNode, ParseError> {
+        self.parse_conditional()
+    }
+
+    fn parse_conditional(&mut self) -> Result<AstNode, ParseError> {
+        let condition = self.parse_coalesce()?;
         
-        // Expect a string literal for the test name
-        let name = if let TokenType::String(test_name) = &self.peek().token_type {
-            let name = test_name.clone();
-            self.advance(); // consume the string
-            name
-        } else {
-            return Err(ParseError::ExpectedString(line));
-        };
+        if self.match_token_types(&[TokenType::Question]) {
+            let then_branch = self.parse_conditional()?;
+            self.consume(TokenType::Colon, "Expected ':' between conditional branches")?;
+            let else_branch = self.parse_conditional()?;
+            return Ok(AstNode::Conditional {
+                span: self.span_from(condition.span()),
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            });
+        }
         
-        // Expect an object with test configuration
-        let config = self.parse_object()?;
+        Ok(condition)
+    }
+
+    fn parse_coalesce(&mut self) -> Result<AstNode, ParseError> {
+        let mut left = self.parse_and()?;
         
-        if let AstNode::Object { fields, .. } = config {
-            // Parse the expect field (what to test)
-            let expect_expression = fields.get("expect")
-                .ok_or_else(|| ParseError::MissingField("expect".to_string(), line))?
-                .clone();
-            
-            // For now, we'll create empty inputs and use the expect as expected_output
-            let inputs = HashMap::new();
-            
-            // Determine the assertion type and expected value
-            let (expected_output, is_regex) = if let Some(equals_value) = fields.get("equals") {
-                (equals_value.clone(), false)
-            } else if let Some(matches_value) = fields.get("matches") {
-                (matches_value.clone(), true)
-            } else if let Some(gt_value) = fields.get("greater_than") {
-                // For now, treat comparison as equals for simplicity
-                (gt_value.clone(), false)
-            } else if let Some(lt_value) = fields.get("less_than") {
-                // For now, treat comparison as equals for simplicity
-                (lt_value.clone(), false)
-            } else {
-                return Err(ParseError::MissingField("equals, matches, greater_than, or less_than".to_string(), line));
+        while self.match_token_types(&[TokenType::DoubleQuestion]) {
+            let right = self.parse_and()?;
+            left = self.binary(left, BinaryOp::Coalesce, right);
+        }
+        
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<AstNode, ParseError> {
+        let mut left = self.parse_equality()?;
+        
+        while self.match_token_types(&[TokenType::And]) {
+            let right = self.parse_equality()?;
+            left = self.binary(left, BinaryOp::And, right);
+        }
+        
+        Ok(left)
+    }
+
+    fn parse_equality(&mut self) -> Result<AstNode, ParseError> {
+        let mut left = self.parse_comparison()?;
+        
+        while self.match_token_types(&[TokenType::Equal, TokenType::NotEqual]) {
+            let operator_token = self.previous();
+            let operator = match operator_token.token_type {
+                TokenType::Equal => BinaryOp::Equal,
+                TokenType::NotEqual => BinaryOp::NotEqual,
+                _ => unreachable!(),
             };
-            
-            Ok(AstNode::Test {
-                name,
-                expect_expression: Box::new(expect_expression),
-                inputs,
-                expected_output: Box::new(expected_output),
-                is_regex,
-                line,
-            })
-        } else {
-            Err(ParseError::ExpectedObject(line))
+            let right = self.parse_comparison()?;
+            left = self.binary(left, operator, right);
         }
+        
+        Ok(left)
     }
 
-    fn parse_expression(&mut self) -> Result<AstNode, ParseError> {
-        self.parse_additive()
+    fn parse_comparison(&mut self) -> Result<AstNode, ParseError> {
+        let mut left = self.parse_additive()?;
+        
+        while self.match_token_types(&[TokenType::Less, TokenType::LessEqual, TokenType::Greater, TokenType::GreaterEqual]) {
+            let operator_token = self.previous();
+            let operator = match operator_token.token_type {
+                TokenType::Less => BinaryOp::Less,
+                TokenType::LessEqual => BinaryOp::LessEqual,
+                TokenType::Greater => BinaryOp::Greater,
+                TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+                _ => unreachable!(),
+            };
+            let right = self.parse_additive()?;
+            left = self.binary(left, operator, right);
+        }
+        
+        Ok(left)
     }
 
     fn parse_additive(&mut self) -> Result<AstNode, ParseError> {
@@ -299,14 +835,8 @@ impl Parser {
                 TokenType::Minus => BinaryOp::Subtract,
                 _ => unreachable!(),
             };
-            let line = operator_token.line;
             let right = self.parse_multiplicative()?;
-            left = AstNode::Binary {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-                line,
-            };
+            left = self.binary(left, operator, right);
         }
         
         Ok(left)
@@ -322,28 +852,27 @@ impl Parser {
                 TokenType::Divide => BinaryOp::Divide,
                 _ => unreachable!(),
             };
-            let line = operator_token.line;
             let right = self.parse_unary()?;
-            left = AstNode::Binary {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-                line,
-            };
+            left = self.binary(left, operator, right);
         }
         
         Ok(left)
     }
 
     fn parse_unary(&mut self) -> Result<AstNode, ParseError> {
-        if self.match_token_types(&[TokenType::Minus]) {
+        if self.match_token_types(&[TokenType::Minus, TokenType::Not]) {
             let operator_token = self.previous();
-            let line = operator_token.line;
+            let operator = match operator_token.token_type {
+                TokenType::Minus => UnaryOp::Negate,
+                TokenType::Not => UnaryOp::Not,
+                _ => unreachable!(),
+            };
+            let start = operator_token.span;
             let operand = self.parse_unary()?;
             return Ok(AstNode::Unary {
-                operator: UnaryOp::Negate,
+                operator,
                 operand: Box::new(operand),
-                line,
+                span: self.span_from(start),
             });
         }
         
@@ -352,142 +881,375 @@ impl Parser {
 
     fn parse_primary(&mut self) -> Result<AstNode, ParseError> {
         let token = self.advance();
+        let start = token.span;
         
         let mut node = match &token.token_type {
-            TokenType::String(s) => AstNode::String(s.clone()),
-            TokenType::Number(n) => AstNode::Number(*n),
-            TokenType::Boolean(b) => AstNode::Boolean(*b),
-            TokenType::Null => AstNode::Null,
+            TokenType::String(s) => AstNode::String(s.clone(), start),
+            TokenType::Number(n) => AstNode::Number(n.clone(), start),
+            TokenType::Boolean(b) => AstNode::Boolean(*b, start),
+            TokenType::Null => AstNode::Null(start),
+            TokenType::Regex { pattern, flags } => AstNode::Regex {
+                pattern: pattern.clone(),
+                flags: flags.clone(),
+                span: start,
+            },
+            TokenType::TemplateString(segments) => {
+                let parts = Self::parse_template_parts(segments.clone())?;
+                AstNode::Template { parts, span: start }
+            }
+            // The url is built like a template string when the call runs
+            TokenType::HttpUrl(segments) => {
+                let parts = Self::parse_template_parts(segments.clone())?;
+                AstNode::HttpCall {
+                    url: Box::new(AstNode::Template { parts, span: start }),
+                    method: HttpMethod::Get,
+                    body: None,
+                    headers: None,
+                    span: start,
+                }
+            }
             TokenType::Identifier(name) => {
                 let name = name.clone(); // Clone to avoid borrowing issues
-                let line = token.line;
                 
-                // Check if this identifier is followed by parentheses (function call)
-                if self.check(&TokenType::LeftParen) {
+                // `match` is only a keyword before a subject on the same line, so
+                // `text.match(/a/)` and values named `match` still work
+                if name == "match" && self.starts_match_subject() {
+                    return self.parse_match(start);
+                }
+                
+                // A `(` on a new line starts a tuple or destructuring, not a call
+                if self.check(&TokenType::LeftParen) && self.peek().line == self.previous().line {
                     self.advance(); // consume '('
                     let mut args = Vec::new();
                     
-                    if !self.check(&TokenType::RightParen) {
-                        loop {
-                            args.push(self.parse_expression()?);
-                            if !self.match_token_types(&[TokenType::Comma]) {
-                                break;
-                            }
+                    // A trailing comma is allowed, as in every other list
+                    while !self.check(&TokenType::RightParen) {
+                        args.push(self.parse_expression()?);
+                        if !self.match_token_types(&[TokenType::Comma]) {
+                            break;
                         }
                     }
                     
                     self.consume(TokenType::RightParen, "Expected ')' after function arguments")?;
-                    
-                    // For annotations, treat function calls as special identifiers with arguments
-                    if name.starts_with('@') {
-                        // Create a special identifier that includes the function call syntax
-                        let mut func_call = name.clone();
-                        func_call.push('(');
-                        for (i, arg) in args.iter().enumerate() {
-                            if i > 0 {
-                                func_call.push_str(", ");
-                            }
-                            // For now, just use the argument as is (this is a simplified approach)
-                            if let AstNode::Identifier(arg_name) = arg {
-                                func_call.push_str(arg_name);
-                            }
-                        }
-                        func_call.push(')');
-                        AstNode::Identifier(func_call)
-                    } else {
-                        // Regular function call - create a proper FunctionCall node
-                        AstNode::FunctionCall {
-                            name,
-                            arguments: args,
-                            line,
-                        }
+                    AstNode::FunctionCall {
+                        name,
+                        arguments: args,
+                        span: self.span_from(start),
                     }
                 } else {
-                    AstNode::Identifier(name)
+                    AstNode::Identifier(name, start)
                 }
             }
             TokenType::LeftBrace => {
                 self.current -= 1; // backtrack
-                return self.parse_object();
+                self.parse_object()?
             }
             TokenType::LeftBracket => {
                 self.current -= 1; // backtrack
-                return self.parse_array();
+                self.parse_array()?
             }
             TokenType::LeftParen => {
                 let expr = self.parse_expression()?;
-                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-                expr
+                
+                // A comma makes a tuple, `(a,)` has a single element
+                if self.match_token_types(&[TokenType::Comma]) {
+                    let mut elements = vec![expr];
+                    while !self.check(&TokenType::RightParen) {
+                        elements.push(self.parse_expression()?);
+                        if !self.match_token_types(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                    self.consume(TokenType::RightParen, "Expected ')' after tuple elements")?;
+                    AstNode::Tuple { elements, span: self.span_from(start) }
+                } else {
+                    self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+                    expr
+                }
             }
-            _ => return Err(ParseError::UnexpectedToken(token.token_type.clone(), token.line)),
+            TokenType::Annotation(name) => return Err(ParseError::MisplacedAnnotation(name.clone(), start)),
+            // The lexer has reported it, stand in a null so parsing carries on
+            TokenType::Invalid => AstNode::Null(start),
+            _ => return Err(ParseError::UnexpectedToken(token.token_type.clone(), start)),
         };
         
-        // Handle member access
-        while self.check(&TokenType::Dot) {
-            self.advance(); // consume '.'
+        // Handle member access, indexing and method calls
+        loop {
+            // A `[` on a new line starts an array literal, not an index
+            if self.check(&TokenType::LeftBracket) && self.peek().line == self.previous().line {
+                self.advance();
+                let index = self.parse_expression()?;
+                self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+                
+                node = AstNode::Index {
+                    span: self.span_from(node.span()),
+                    object: Box::new(node),
+                    index: Box::new(index),
+                };
+                continue;
+            }
+            
+            if !self.check(&TokenType::Dot) && !self.check(&TokenType::QuestionDot) {
+                break;
+            }
+            
+            let null_safe = self.advance().token_type == TokenType::QuestionDot;
             let property_token = self.advance();
             let property = match &property_token.token_type {
                 TokenType::Identifier(name) => name.clone(),
-                _ => return Err(ParseError::ExpectedIdentifier(property_token.line)),
+                _ => return Err(ParseError::ExpectedIdentifier(property_token.span)),
             };
             
-            node = AstNode::MemberAccess {
-                object: Box::new(node),
-                property,
-                line: property_token.line,
-            };
+            let is_call = self.check(&TokenType::LeftParen) && self.peek().line == self.previous().line;
+            if null_safe && is_call {
+                return Err(ParseError::UnexpectedToken(TokenType::LeftParen, self.peek().span));
+            }
+            
+            if is_call {
+                self.advance(); // consume '('
+                let mut arguments = Vec::new();
+                while !self.check(&TokenType::RightParen) {
+                    arguments.push(self.parse_expression()?);
+                    if !self.match_token_types(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after method arguments")?;
+                
+                node = AstNode::MethodCall {
+                    span: self.span_from(node.span()),
+                    object: Box::new(node),
+                    method: property,
+                    arguments,
+                };
+            } else {
+                node = AstNode::MemberAccess {
+                    span: self.span_from(node.span()),
+                    object: Box::new(node),
+                    property,
+                    null_safe,
+                };
+            }
         }
         
         Ok(node)
     }
 
+    /// Whether the next token, on the same line, can start an expression
+    fn starts_match_subject(&self) -> bool {
+        self.peek().line == self.previous().line && matches!(
+            self.peek().token_type,
+            TokenType::Identifier(_)
+                | TokenType::String(_)
+                | TokenType::Number(_)
+                | TokenType::Boolean(_)
+                | TokenType::Null
+                | TokenType::Regex { .. }
+                | TokenType::TemplateString(_)
+                | TokenType::HttpUrl(_)
+                | TokenType::LeftParen
+                | TokenType::LeftBracket
+                | TokenType::LeftBrace
+                | TokenType::Minus
+                | TokenType::Not
+                | TokenType::Promise
+        )
+    }
+
+    fn parse_match(&mut self, start: Span) -> Result<AstNode, ParseError> {
+        let subject = self.parse_expression()?;
+        self.consume(TokenType::LeftBrace, "Expected '{' after match subject")?;
+        let mut arms = Vec::new();
+        
+        while !self.check(&TokenType::RightBrace) {
+            let arm_start = self.peek().span;
+            let pattern = self.parse_pattern()?;
+            self.consume(TokenType::Colon, "Expected ':' after pattern")?;
+            let value = self.parse_expression()?;
+            arms.push(MatchArm { pattern, value, span: self.span_from(arm_start) });
+            
+            if !self.match_token_types(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        
+        self.consume(TokenType::RightBrace, "Expected '}' after match arms")?;
+        Ok(AstNode::Match { subject: Box::new(subject), arms, span: self.span_from(start) })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let token = self.advance().clone();
+        
+        let pattern = match token.token_type {
+            TokenType::Identifier(name) if name == "_" => Pattern::Wildcard,
+            TokenType::Identifier(name) => Pattern::Binding(name, token.span),
+            TokenType::String(value) => Pattern::Literal(AstNode::String(value, token.span)),
+            TokenType::Boolean(value) => Pattern::Literal(AstNode::Boolean(value, token.span)),
+            TokenType::Null => Pattern::Literal(AstNode::Null(token.span)),
+            TokenType::Regex { pattern, flags } => Pattern::Regex { pattern, flags },
+            TokenType::DotDot => Pattern::Range { start: None, end: Some(self.parse_pattern_number()?) },
+            TokenType::Number(_) | TokenType::Minus => {
+                self.current -= 1; // backtrack
+                let number = self.parse_pattern_number()?;
+                if self.match_token_types(&[TokenType::DotDot]) {
+                    let end = match self.peek().token_type {
+                        TokenType::Number(_) | TokenType::Minus => Some(self.parse_pattern_number()?),
+                        _ => None,
+                    };
+                    Pattern::Range { start: Some(number), end }
+                } else {
+                    Pattern::Literal(AstNode::Number(number, self.span_from(token.span)))
+                }
+            }
+            TokenType::LeftBracket => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while !self.check(&TokenType::RightBracket) {
+                    // `..rest` or `..` takes the remaining elements and must come last
+                    if self.match_token_types(&[TokenType::DotDot]) {
+                        rest = Some(Box::new(match self.peek().token_type.clone() {
+                            TokenType::Identifier(name) if name != "_" => Pattern::Binding(name, self.advance().span),
+                            TokenType::Identifier(_) => {
+                                self.advance();
+                                Pattern::Wildcard
+                            }
+                            _ => Pattern::Wildcard,
+                        }));
+                        self.match_token_types(&[TokenType::Comma]);
+                        break;
+                    }
+                    elements.push(self.parse_pattern()?);
+                    if !self.match_token_types(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightBracket, "Expected ']' after array pattern")?;
+                Pattern::Array { elements, rest }
+            }
+            TokenType::LeftBrace => {
+                let mut fields = IndexMap::new();
+                while !self.check(&TokenType::RightBrace) {
+                    let key = self.advance().clone();
+                    let field = match key.token_type {
+                        TokenType::String(name) | TokenType::Identifier(name) => name,
+                        other => return Err(ParseError::ExpectedStringOrIdentifier(other, key.span)),
+                    };
+                    let pattern = if self.match_token_types(&[TokenType::Colon]) {
+                        self.parse_pattern()?
+                    } else {
+                        Pattern::Binding(field.clone(), key.span)
+                    };
+                    fields.insert(field, pattern);
+                    if !self.match_token_types(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expected '}' after object pattern")?;
+                Pattern::Object(fields)
+            }
+            other => return Err(ParseError::UnexpectedToken(other, token.span)),
+        };
+        
+        Ok(pattern)
+    }
+
+    /// A number in a pattern, which may be negative
+    fn parse_pattern_number(&mut self) -> Result<BigDecimal, ParseError> {
+        let negative = self.match_token_types(&[TokenType::Minus]);
+        let token = self.advance();
+        match &token.token_type {
+            TokenType::Number(number) if negative => Ok(-number),
+            TokenType::Number(number) => Ok(number.clone()),
+            other => Err(ParseError::UnexpectedToken(other.clone(), token.span)),
+        }
+    }
+
     fn parse_object(&mut self) -> Result<AstNode, ParseError> {
-        let start_token = self.advance(); // consume '{'
-        let line = start_token.line;
-        let mut fields = HashMap::new();
+        let start = self.advance().span; // consume '{'
+        let mut fields = Vec::new();
         
         if self.check(&TokenType::RightBrace) {
             self.advance(); // consume '}'
-            return Ok(AstNode::Object { fields, line });
+            return Ok(AstNode::Object { fields, span: self.span_from(start) });
         }
         
         loop {
-            // Parse key
-            let key_token = self.advance();
-            let key = match &key_token.token_type {
-                TokenType::String(s) => s.clone(),
-                TokenType::Identifier(s) => s.clone(),
-                _ => return Err(ParseError::ExpectedStringOrIdentifier(key_token.token_type.clone(), line)),
-            };
-            
-            self.consume(TokenType::Colon, "Expected ':' after object key")?;
-            
-            // Parse value
-            let value = self.parse_expression()?;
-            fields.insert(key, value);
+            let field_start = self.current;
+            if let Err(error) = self.parse_field(&mut fields) {
+                self.report(error);
+                self.synchronize_field(field_start);
+            }
             
-            if !self.match_token_types(&[TokenType::Comma]) {
-                break;
+            if self.match_token_types(&[TokenType::Comma]) {
+                // Allow trailing comma
+                if self.check(&TokenType::RightBrace) {
+                    break;
+                }
+                continue;
             }
             
-            // Allow trailing comma
-            if self.check(&TokenType::RightBrace) {
+            // A missing comma before another `key:` is reported and the field still read
+            let next_is_field = matches!(self.peek().token_type, TokenType::Annotation(_))
+                || matches!(self.peek().token_type, TokenType::String(_) | TokenType::Identifier(_))
+                    && self.peek_next().is_some_and(|token| token.token_type == TokenType::Colon);
+            if !next_is_field {
                 break;
             }
+            self.report(ParseError::ExpectedToken(TokenType::Comma, self.previous_end(), "Expected ',' between object fields".to_string()));
         }
         
         self.consume(TokenType::RightBrace, "Expected '}' after object fields")?;
-        Ok(AstNode::Object { fields, line })
+        Ok(AstNode::Object { fields, span: self.span_from(start) })
+    }
+
+    fn parse_field(&mut self, fields: &mut Vec<(ObjectKey, AstNode)>) -> Result<(), ParseError> {
+        let annotations = self.parse_annotations()?;
+        
+        // Parse key: a field name, or any other value such as `1`, `[a, b]` or `(expr)`
+        let named_key = match &self.peek().token_type {
+            TokenType::String(s) | TokenType::Identifier(s) => Some(s.clone()),
+            _ if !annotations.is_empty() => {
+                return Err(ParseError::MisplacedAnnotation(annotations[0].name.clone(), annotations[0].span));
+            }
+            TokenType::RightBrace | TokenType::Comma | TokenType::Colon | TokenType::Eof => {
+                return Err(ParseError::ExpectedStringOrIdentifier(self.peek().token_type.clone(), self.peek().span));
+            }
+            _ => None,
+        };
+        
+        if let Some(key) = named_key {
+            self.advance();
+            self.consume(TokenType::Colon, "Expected ':' after object key")?;
+            let value = self.parse_expression()?;
+            let value = match annotations.first() {
+                Some(first) => AstNode::Annotated {
+                    span: first.span.to(value.span()),
+                    annotations,
+                    declaration: Box::new(value),
+                },
+                None => value,
+            };
+            // A repeated name keeps its first place and takes the last value
+            match fields.iter_mut().find(|(existing, _)| existing.name() == Some(key.as_str())) {
+                Some((_, existing)) => *existing = value,
+                None => fields.push((ObjectKey::Name(key), value)),
+            }
+        } else {
+            let key = self.parse_expression()?;
+            self.consume(TokenType::Colon, "Expected ':' after object key")?;
+            fields.push((ObjectKey::Computed(key), self.parse_expression()?));
+        }
+        
+        Ok(())
     }
 
     fn parse_array(&mut self) -> Result<AstNode, ParseError> {
-        let start_token = self.advance(); // consume '['
-        let line = start_token.line;
+        let start = self.advance().span; // consume '['
         let mut elements = Vec::new();
         
         if self.check(&TokenType::RightBracket) {
             self.advance(); // consume ']'
-            return Ok(AstNode::Array { elements, line });
+            return Ok(AstNode::Array { elements, span: self.span_from(start) });
         }
         
         loop {
@@ -504,67 +1266,56 @@ impl Parser {
         }
         
         self.consume(TokenType::RightBracket, "Expected ']' after array elements")?;
-        Ok(AstNode::Array { elements, line })
+        Ok(AstNode::Array { elements, span: self.span_from(start) })
     }
 
-    fn parse_http_call(&mut self) -> Result<AstNode, ParseError> {
-        let http_token = self.previous(); // we already consumed @http
-        let line = http_token.line;
-        
-        // Expect an object with HTTP call configuration
-        let config = self.parse_object()?;
-        
-        if let AstNode::Object { fields, .. } = config {
-            let url = fields.get("url")
-                .ok_or_else(|| ParseError::MissingField("url".to_string(), line))?
-                .clone();
-            
-            let method_str = self.extract_string_field(&fields, "method", line)?;
-            let method = match method_str.to_lowercase().as_str() {
-                "get" => HttpMethod::Get,
-                "post" => HttpMethod::Post,
-                "put" => HttpMethod::Put,
-                "delete" => HttpMethod::Delete,
-                "patch" => HttpMethod::Patch,
-                _ => return Err(ParseError::InvalidHttpMethod(method_str, line)),
-            };
-            
-            let body = fields.get("body").cloned().map(Box::new);
-            
-            // Parse headers if present
-            let headers = if let Some(headers_node) = fields.get("headers") {
-                if let AstNode::Object { fields, .. } = headers_node {
-                    Some(fields.clone())
-                } else {
-                    return Err(ParseError::ExpectedObject(line));
+    /// Turn the segments of a template string or url into text and expression parts
+    fn parse_template_parts(segments: Vec<TemplateSegment>) -> Result<Vec<TemplatePart>, ParseError> {
+        let mut parts = Vec::new();
+        for segment in segments {
+            match segment {
+                TemplateSegment::Text(text) => parts.push(TemplatePart::Text(text)),
+                TemplateSegment::Expression(tokens) => {
+                    parts.push(TemplatePart::Expression(Self::parse_template_expression(tokens)?));
                 }
-            } else {
-                None
-            };
-            
-            Ok(AstNode::HttpCall {
-                url: Box::new(url),
-                method,
-                body,
-                headers,
-                line,
-            })
-        } else {
-            Err(ParseError::ExpectedObject(line))
+            }
+        }
+        Ok(parts)
+    }
+
+    /// Parse the tokens of one `{expr}` segment, which must hold exactly one expression
+    fn parse_template_expression(tokens: Vec<Token>) -> Result<AstNode, ParseError> {
+        let mut parser = Parser::new(tokens);
+        let expression = parser.parse_expression()?;
+        
+        if !parser.is_at_end() {
+            let token = parser.peek();
+            return Err(ParseError::UnexpectedToken(token.token_type.clone(), token.span));
         }
+        
+        Ok(expression)
     }
 
     // Helper methods
-    fn extract_string_field(&self, fields: &HashMap<String, AstNode>, field_name: &str, line: usize) -> Result<String, ParseError> {
-        fields.get(field_name)
-            .ok_or_else(|| ParseError::MissingField(field_name.to_string(), line))
-            .and_then(|node| {
-                if let AstNode::String(s) = node {
-                    Ok(s.clone())
-                } else {
-                    Err(ParseError::ExpectedString(line))
-                }
-            })
+    fn binary(&self, left: AstNode, operator: BinaryOp, right: AstNode) -> AstNode {
+        AstNode::Binary {
+            span: left.span().to(right.span()),
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }
+    }
+
+    /// The span from `start` to the end of the last token consumed
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    /// An empty span just after the last token consumed, where a missing
+    /// token belongs
+    fn previous_end(&self) -> Span {
+        let previous = self.previous().span;
+        Span::new(previous.end, previous.end, previous.line)
     }
 
     fn match_token_types(&mut self, types: &[TokenType]) -> bool {
@@ -616,36 +1367,39 @@ impl Parser {
         if self.check(&token_type) {
             Ok(self.advance())
         } else {
-            Err(ParseError::ExpectedToken(token_type, self.peek().line, message.to_string()))
+            Err(ParseError::ExpectedToken(token_type, self.peek().span, message.to_string()))
         }
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ParseError {
-    #[error("Unexpected token {0} at line {1}")]
-    UnexpectedToken(TokenType, usize),
-    
-    #[error("Expected identifier at line {0}")]
-    ExpectedIdentifier(usize),
+    #[error("Unexpected token {0} at line {}", .1.line)]
+    UnexpectedToken(TokenType, Span),
     
-    #[error("Expected string at line {0}")]
-    ExpectedString(usize),
+    #[error("Expected identifier at line {}", .0.line)]
+    ExpectedIdentifier(Span),
     
-    #[error("Expected object at line {0}")]
-    ExpectedObject(usize),
+    #[error("Expected string at line {}", .0.line)]
+    ExpectedString(Span),
     
-    #[error("Expected string or identifier, got {0} at line {1}")]
-    ExpectedStringOrIdentifier(TokenType, usize),
+    #[error("Expected string or identifier, got {0} at line {}", .1.line)]
+    ExpectedStringOrIdentifier(TokenType, Span),
     
-    #[error("Expected token {0} at line {1}: {2}")]
-    ExpectedToken(TokenType, usize, String),
+    #[error("Expected token {0} at line {}: {2}", .1.line)]
+    ExpectedToken(TokenType, Span, String),
     
-    #[error("Missing required field '{0}' at line {1}")]
-    MissingField(String, usize),
+    #[error("Duplicate parameter '{0}' at line {}", .1.line)]
+    DuplicateParameter(String, Span),
     
-    #[error("Invalid HTTP method '{0}' at line {1}")]
-    InvalidHttpMethod(String, usize),
+    #[error("Annotation '@{0}' at line {} must come before a declaration", .1.line)]
+    MisplacedAnnotation(String, Span),
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ParseWarning {
+    #[error("'{0} = ...' at line {} is deprecated, use '{0}: ...' instead", .1.line)]
+    DeprecatedAssignment(String, Span),
 }
 
 #[cfg(test)]
@@ -676,15 +1430,189 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_colon_declarations() {
+        let source = r#"
+            subtotal: 100
+            tax: subtotal * 0.08
+            order: { total: subtotal + tax, label: "order" }
+        "#;
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let ast = parser.parse().unwrap();
+        
+        if let AstNode::Program(statements) = ast {
+            let names: Vec<_> = statements.iter().map(|statement| match statement {
+                AstNode::Assignment { name, .. } => name.as_str(),
+                other => panic!("Expected assignment, got {:?}", other),
+            }).collect();
+            assert_eq!(names, vec!["subtotal", "tax", "order"]);
+        } else {
+            panic!("Expected program");
+        }
+        assert!(parser.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_equals_declaration_is_deprecated() {
+        let mut lexer = Lexer::new("a: 1\nb = a + 1");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let ast = parser.parse().unwrap();
+        
+        if let AstNode::Program(statements) = ast {
+            assert!(matches!(&statements[1], AstNode::Assignment { name, span, .. } if name == "b" && span.line == 2));
+        } else {
+            panic!("Expected program");
+        }
+        // The warning points at the `=`
+        assert!(matches!(parser.warnings(), [ParseWarning::DeprecatedAssignment(name, span)] if name == "b" && span.line == 2 && span.end - span.start == 1));
+    }
+
+    #[test]
+    fn test_error_recovery_collects_every_error() {
+        let source = "a: (1\nb: { x: ), y: 2 z: 3, w }\nc: * 2\nd: 4";
+        let mut parser = Parser::new(Lexer::new(source).tokenize().unwrap());
+        let ast = parser.parse_recovering();
+        
+        let lines: Vec<_> = parser.errors().iter().map(|error| match error {
+            ParseError::UnexpectedToken(_, span)
+            | ParseError::ExpectedToken(_, span, _)
+            | ParseError::ExpectedStringOrIdentifier(_, span) => span.line,
+            other => panic!("Unexpected error {:?}", other),
+        }).collect();
+        assert_eq!(lines, vec![2, 2, 2, 2, 3]);
+        
+        // Statements after each error are still parsed, with the good fields kept
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let names: Vec<_> = statements.iter().filter_map(|statement| match statement {
+            AstNode::Assignment { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(names, vec!["b", "d"]);
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Object { fields, .. } = value.as_ref() else { panic!("Expected object") };
+        assert_eq!(fields.len(), 2);
+        assert!(value.field("y").is_some() && value.field("z").is_some());
+    }
+
+    #[test]
+    fn test_lexer_errors_are_not_reported_twice() {
+        let mut lexer = Lexer::new("a: { b: ~, c: 1 }\nd: \"\\q\"\ne: 2");
+        let tokens = lexer.tokenize_recovering();
+        assert_eq!(lexer.errors().len(), 2);
+        
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_recovering();
+        assert!(parser.errors().is_empty());
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 3);
+    }
+
+    #[test]
+    fn test_comparison_precedence() {
+        // `&` binds loosest, then equality, then ordering, then arithmetic
+        let ast = parse_source("ok: a + 1 < b = !c & d != e").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Binary { left, operator: BinaryOp::And, right, .. } = value.as_ref() else {
+            panic!("Expected '&' at the root, got {:?}", value);
+        };
+        
+        let AstNode::Binary { left: less, operator: BinaryOp::Equal, right: not, .. } = left.as_ref() else {
+            panic!("Expected '=' on the left, got {:?}", left);
+        };
+        assert!(matches!(less.as_ref(), AstNode::Binary { operator: BinaryOp::Less, left, .. }
+            if matches!(left.as_ref(), AstNode::Binary { operator: BinaryOp::Add, .. })));
+        assert!(matches!(not.as_ref(), AstNode::Unary { operator: UnaryOp::Not, .. }));
+        assert!(matches!(right.as_ref(), AstNode::Binary { operator: BinaryOp::NotEqual, .. }));
+    }
+
+    #[test]
+    fn test_conditional_parsing() {
+        let ast = parse_source("price: gold ? 10 : silver ? 5 : 1\nnext: { a: x < 1 ? \"low\" : \"high\", b: 2 }").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 2);
+        
+        // Conditionals nest to the right
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Conditional { condition, else_branch, .. } = value.as_ref() else {
+            panic!("Expected conditional, got {:?}", value);
+        };
+        assert!(matches!(condition.as_ref(), AstNode::Identifier(name, _) if name == "gold"));
+        assert!(matches!(else_branch.as_ref(), AstNode::Conditional { .. }));
+        
+        // A conditional inside an object field stops at the next comma
+        let AstNode::Assignment { value, .. } = &statements[1] else { panic!("Expected assignment") };
+        let AstNode::Object { fields, .. } = value.as_ref() else { panic!("Expected object") };
+        assert!(matches!(value.field("a"), Some(AstNode::Conditional { condition, .. })
+            if matches!(condition.as_ref(), AstNode::Binary { operator: BinaryOp::Less, .. })));
+        assert_eq!(fields.len(), 2);
+    }
+
+    #[test]
+    fn test_null_safe_parsing() {
+        let ast = parse_source("name: user?.profile.name ?? fallback ?? \"anonymous\"").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        
+        // `??` groups to the left and binds looser than member access
+        let AstNode::Binary { left, operator: BinaryOp::Coalesce, right, .. } = value.as_ref() else {
+            panic!("Expected coalesce, got {:?}", value);
+        };
+        assert!(matches!(right.as_ref(), AstNode::String(value, _) if value == "anonymous"));
+        let AstNode::Binary { left, operator: BinaryOp::Coalesce, .. } = left.as_ref() else {
+            panic!("Expected nested coalesce, got {:?}", left);
+        };
+        
+        let AstNode::MemberAccess { object, property, null_safe: false, .. } = left.as_ref() else {
+            panic!("Expected plain member access, got {:?}", left);
+        };
+        assert_eq!(property, "name");
+        assert!(matches!(object.as_ref(), AstNode::MemberAccess { null_safe: true, property, .. } if property == "profile"));
+        
+        assert!(parse_source("x: a?.run()").is_err());
+    }
+
+    #[test]
+    fn test_index_parsing() {
+        let ast = parse_source("first: orders[0].items[-1]\nlabel: labels[key + 1]\n[1, 2]").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 3);
+        
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Index { object, index, .. } = value.as_ref() else {
+            panic!("Expected index, got {:?}", value);
+        };
+        assert!(matches!(index.as_ref(), AstNode::Unary { operator: UnaryOp::Negate, .. }));
+        let AstNode::MemberAccess { object, property, .. } = object.as_ref() else {
+            panic!("Expected member access, got {:?}", object);
+        };
+        assert_eq!(property, "items");
+        assert!(matches!(object.as_ref(), AstNode::Index { object, .. } if matches!(object.as_ref(), AstNode::Identifier(name, _) if name == "orders")));
+        
+        let AstNode::Assignment { value, .. } = &statements[1] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::Index { index, .. } if matches!(index.as_ref(), AstNode::Binary { .. })));
+        
+        // A bracket on a new line starts an array instead of indexing
+        assert!(matches!(&statements[2], AstNode::Array { .. }));
+        
+        assert!(parse_source("x: a[0").is_err());
+    }
+
     #[test]
     fn test_object_parsing() {
         let ast = parse_source(r#"{"name": "test", "value": 42}"#).unwrap();
         
         if let AstNode::Program(statements) = ast {
-            if let AstNode::Object { fields, .. } = &statements[0] {
+            if let object @ AstNode::Object { fields, .. } = &statements[0] {
                 assert_eq!(fields.len(), 2);
-                assert!(fields.contains_key("name"));
-                assert!(fields.contains_key("value"));
+                assert!(object.field("name").is_some());
+                assert!(object.field("value").is_some());
+                assert_eq!(fields.iter().map(|(key, _)| key.name()).collect::<Vec<_>>(), [Some("name"), Some("value")]);
             } else {
                 panic!("Expected object");
             }
@@ -692,25 +1620,246 @@ mod tests {
     }
 
     #[test]
-    fn test_endpoint_declaration() {
-        let source = r#"{
-            "handler": "@endpoint:GET:/api/users/{id}",
-            "user": "@http:GET:https://api.example.com/users/123"
-        }"#;
+    fn test_complex_key_parsing() {
+        let ast = parse_source(r#"x: { name: "n", 1: "one", [region, year]: total, (prefix + "id"): 2, }"#).unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Object { fields, .. } = value.as_ref() else { panic!("Expected object") };
+        
+        // Named and computed keys stay in declaration order
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].0, ObjectKey::Name("name".to_string()));
+        assert!(matches!(&fields[1].0, ObjectKey::Computed(AstNode::Number(..))));
+        assert!(matches!(&fields[2], (ObjectKey::Computed(AstNode::Array { elements, .. }), AstNode::Identifier(total, _)) if elements.len() == 2 && total == "total"));
+        assert!(matches!(&fields[3].0, ObjectKey::Computed(AstNode::Binary { operator: BinaryOp::Add, .. })));
+        
+        assert!(parse_source("x: { : 1 }").is_err());
+    }
+
+    #[test]
+    fn test_tuple_and_destructuring_parsing() {
+        let ast = parse_source("pair: (1, \"a\",)\ngrouped: (1 + 2) * 3\n(q, r): divmod(7, 2)").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::Tuple { elements, .. } if elements.len() == 2));
+        
+        let AstNode::Assignment { value, .. } = &statements[1] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::Binary { operator: BinaryOp::Multiply, .. }));
+        
+        let AstNode::Destructure { names, value, .. } = &statements[2] else { panic!("Expected destructuring") };
+        assert_eq!(names, &["q", "r"]);
+        assert!(matches!(value.as_ref(), AstNode::FunctionCall { name, .. } if name == "divmod"));
+        
+        assert!(parse_source("(q, 1): pair").is_err());
+    }
+
+    #[test]
+    fn test_destructuring_after_a_line_ending_in_a_name() {
+        let ast = parse_source("b: a\n(q, r): (a, 2)\nc: user.name\n(s, t): (b, c)").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 4);
+        assert!(matches!(&statements[0], AstNode::Assignment { value, .. } if matches!(value.as_ref(), AstNode::Identifier(name, _) if name == "a")));
+        assert!(matches!(&statements[1], AstNode::Destructure { names, .. } if names == &["q", "r"]));
+        assert!(matches!(&statements[2], AstNode::Assignment { value, .. } if matches!(value.as_ref(), AstNode::MemberAccess { .. })));
+        assert!(matches!(&statements[3], AstNode::Destructure { names, .. } if names == &["s", "t"]));
+    }
+
+    #[test]
+    fn test_function_declaration_parsing() {
+        let ast = parse_source("fullName(u): u.first + \" \" + u.last\nnow(): 1\nname: fullName(ada)").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Function { name, parameters, body, .. } = &statements[0] else { panic!("Expected function") };
+        assert_eq!(name, "fullName");
+        assert_eq!(parameters, &["u"]);
+        assert!(matches!(body.as_ref(), AstNode::Binary { operator: BinaryOp::Add, .. }));
+        assert!(matches!(&statements[1], AstNode::Function { parameters, .. } if parameters.is_empty()));
+        
+        // A call with arguments is not a declaration
+        let AstNode::Assignment { value, .. } = &statements[2] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::FunctionCall { name, .. } if name == "fullName"));
+        
+        assert!(matches!(parse_source("f(a, a): a"), Err(ParseError::DuplicateParameter(name, span)) if name == "a" && span.start == 5));
+    }
+
+    #[test]
+    fn test_calls_need_their_parenthesis_on_the_same_line() {
+        let ast = parse_source("half(n): n / 2\nb: a\n(q, r): (half(a), text.trim())\nc: half\n(x, y): (1, 2)\nd: text.trim\n(u, v): (3, 4)").unwrap();
+        
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 7);
+        let AstNode::Destructure { value, .. } = &statements[2] else { panic!("Expected destructuring") };
+        let AstNode::Tuple { elements, .. } = value.as_ref() else { panic!("Expected tuple") };
+        assert!(matches!(&elements[0], AstNode::FunctionCall { name, .. } if name == "half"));
+        assert!(matches!(&elements[1], AstNode::MethodCall { method, .. } if method == "trim"));
+        
+        // A name ending its line is not called by a parenthesis on the next
+        assert!(matches!(&statements[3], AstNode::Assignment { value, .. } if matches!(value.as_ref(), AstNode::Identifier(name, _) if name == "half")));
+        assert!(matches!(&statements[4], AstNode::Destructure { names, .. } if names == &["x", "y"]));
+        assert!(matches!(&statements[5], AstNode::Assignment { value, .. } if matches!(value.as_ref(), AstNode::MemberAccess { .. })));
+        assert!(matches!(&statements[6], AstNode::Destructure { names, .. } if names == &["u", "v"]));
+    }
+
+    #[test]
+    fn test_trailing_commas_in_lists() {
+        let ast = parse_source("(q, r,): pair\nadd(a, b,): a + b\nsum: add(1, 2,)\nsize: items.slice(0, 2,)").unwrap();
+
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        assert!(matches!(&statements[0], AstNode::Destructure { names, .. } if names.len() == 2));
+        assert!(matches!(&statements[1], AstNode::Function { parameters, .. } if parameters.len() == 2));
+        let AstNode::Assignment { value, .. } = &statements[2] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::FunctionCall { arguments, .. } if arguments.len() == 2));
+        let AstNode::Assignment { value, .. } = &statements[3] else { panic!("Expected assignment") };
+        assert!(matches!(value.as_ref(), AstNode::MethodCall { arguments, .. } if arguments.len() == 2));
+
+        assert!(parse_source("sum: add(1,,)").is_err());
+    }
+
+    #[test]
+    fn test_template_string_parsing() {
+        let ast = parse_source(r#"{ greeting: $"Hello {user.name}, you are {age + 1}" }"#).unwrap();
+        
+        if let AstNode::Program(statements) = ast {
+            if let object @ AstNode::Object { .. } = &statements[0] {
+                if let Some(AstNode::Template { parts, .. }) = object.field("greeting") {
+                    assert_eq!(parts.len(), 4);
+                    assert_eq!(parts[0], TemplatePart::Text("Hello ".to_string()));
+                    assert!(matches!(&parts[1], TemplatePart::Expression(AstNode::MemberAccess { property, .. }) if property == "name"));
+                    assert_eq!(parts[2], TemplatePart::Text(", you are ".to_string()));
+                    assert!(matches!(&parts[3], TemplatePart::Expression(AstNode::Binary { operator: BinaryOp::Add, .. })));
+                } else {
+                    panic!("Expected template");
+                }
+            } else {
+                panic!("Expected object");
+            }
+        }
+    }
+
+    #[test]
+    fn test_http_call_parsing() {
+        let ast = parse_source("rate: //tax/rates?zip={cust.zip}&n={count + 1}").unwrap();
         
-        let ast = parse_source(source).unwrap();
+        let AstNode::Program(statements) = ast else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::HttpCall { url, method: HttpMethod::Get, .. } = value.as_ref() else { panic!("Expected HTTP call") };
+        let AstNode::Template { parts, .. } = url.as_ref() else { panic!("Expected template url") };
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], TemplatePart::Text("//tax/rates?zip=".to_string()));
+        assert!(matches!(&parts[1], TemplatePart::Expression(AstNode::MemberAccess { property, .. }) if property == "zip"));
+        assert_eq!(parts[2], TemplatePart::Text("&n=".to_string()));
+        assert!(matches!(&parts[3], TemplatePart::Expression(AstNode::Binary { operator: BinaryOp::Add, .. })));
+    }
+
+    #[test]
+    fn test_template_expression_must_be_single_expression() {
+        let result = parse_source(r#"{ bad: $"{a b}" }"#);
+        assert!(matches!(result, Err(ParseError::UnexpectedToken(TokenType::Identifier(name), _)) if name == "b"));
+    }
+
+    #[test]
+    fn test_regex_method_call_parsing() {
+        let ast = parse_source(r#"{ valid: /[0-5][0-9]/.test(second), fixed: /a/g.replace(text, "b") }"#).unwrap();
         
         if let AstNode::Program(statements) = ast {
-            if let AstNode::Object { fields, .. } = &statements[0] {
-                assert!(fields.contains_key("handler"));
-                assert!(fields.contains_key("user"));
-                // Check that handler value is a string with endpoint annotation
-                if let AstNode::String(handler_val) = &fields["handler"] {
-                    assert!(handler_val.starts_with("@endpoint:"));
+            if let object @ AstNode::Object { .. } = &statements[0] {
+                match object.field("valid").unwrap() {
+                    AstNode::MethodCall { object, method, arguments, .. } => {
+                        assert_eq!(method, "test");
+                        assert_eq!(arguments.len(), 1);
+                        assert!(matches!(object.as_ref(), AstNode::Regex { pattern, flags, .. } if pattern == "[0-5][0-9]" && flags.is_empty()));
+                    }
+                    other => panic!("Expected method call, got {:?}", other),
                 }
+                assert!(matches!(object.field("fixed").unwrap(), AstNode::MethodCall { method, arguments, .. } if method == "replace" && arguments.len() == 2));
             } else {
-                panic!("Expected object with endpoint declaration");
+                panic!("Expected object");
             }
         }
     }
+
+    #[test]
+    fn test_annotation_parsing() {
+        let source = r#"@endpoint(GET, "/api/users") @cache { ttl: 60 }
+users: load()
+config: {
+  @test("port is set") { equals: 8080 }
+  port: 8080,
+}"#;
+        
+        let AstNode::Program(statements) = parse_source(source).unwrap() else { panic!("Expected program") };
+        let AstNode::Annotated { annotations, declaration, .. } = &statements[0] else { panic!("Expected annotated declaration") };
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].name, "endpoint");
+        assert!(matches!(annotations[0].argument(0, "method"), Some(AstNode::Identifier(name, _)) if name == "GET"));
+        assert!(matches!(annotations[0].argument(1, "path"), Some(AstNode::String(path, _)) if path == "/api/users"));
+        assert!(matches!(annotations[1].argument(0, "ttl"), Some(AstNode::Number(..))));
+        assert!(matches!(declaration.as_ref(), AstNode::Assignment { name, .. } if name == "users"));
+        
+        // On an object field the annotations wrap the field's value
+        let AstNode::Assignment { value, .. } = &statements[1] else { panic!("Expected assignment") };
+        let Some(AstNode::Annotated { annotations, declaration, .. }) = value.field("port") else { panic!("Expected annotated field") };
+        assert!(matches!(&annotations[0].arguments[..], [AstNode::String(name, _)] if name == "port is set"));
+        assert!(annotations[0].field("equals").is_some());
+        assert!(matches!(declaration.as_ref(), AstNode::Number(..)));
+    }
+
+    #[test]
+    fn test_annotations_need_a_declaration() {
+        let misplaced = |source: &str| matches!(parse_source(source), Err(ParseError::MisplacedAnnotation(name, _)) if name == "endpoint");
+        assert!(misplaced(r#"x: @endpoint(GET, "/x")"#));
+        assert!(misplaced(r#"@endpoint(GET, "/x")"#));
+        assert!(misplaced("@endpoint
+{ a: 1 }"));
+        assert!(misplaced("{ @endpoint 1: 2 }"));
+    }
+
+    #[test]
+    fn test_match_parsing() {
+        let source = r#"label: match response {
+            { status: 200, body }: body,
+            { status: 500..599, error }: error,
+            [first, ..rest]: first,
+            ..-1: "negative",
+            /^ok/i: "ok",
+            null: "none",
+            _: "other",
+        }"#;
+        let AstNode::Program(statements) = parse_source(source).unwrap() else { panic!("Expected program") };
+        let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+        let AstNode::Match { subject, arms, .. } = value.as_ref() else { panic!("Expected match, got {:?}", value) };
+        
+        assert!(matches!(subject.as_ref(), AstNode::Identifier(name, _) if name == "response"));
+        assert_eq!(arms.len(), 7);
+        let Pattern::Object(fields) = &arms[1].pattern else { panic!("Expected object pattern") };
+        assert_eq!(fields["status"], Pattern::Range { start: Some(BigDecimal::from(500)), end: Some(BigDecimal::from(599)) });
+        assert!(matches!(&fields["error"], Pattern::Binding(name, _) if name == "error"));
+        assert_eq!(arms[2].pattern.bindings().iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["first", "rest"]);
+        assert_eq!(arms[3].pattern, Pattern::Range { start: None, end: Some(BigDecimal::from(-1)) });
+        assert!(matches!(&arms[4].pattern, Pattern::Regex { flags, .. } if flags == "i"));
+        assert_eq!(arms[6].pattern, Pattern::Wildcard);
+        
+        // Anywhere else `match` is a name
+        assert!(parse_source("m: text.match(/a/)\nmatch: 1\nn: match + 1").is_ok());
+    }
+
+    #[test]
+    fn test_match_subjects_start_like_any_expression() {
+        let subject = |source: &str| {
+            let AstNode::Program(statements) = parse_source(source).unwrap() else { panic!("Expected program") };
+            let AstNode::Assignment { value, .. } = &statements[0] else { panic!("Expected assignment") };
+            let AstNode::Match { subject, .. } = value.as_ref() else { panic!("Expected match, got {:?}", value) };
+            *subject.clone()
+        };
+        
+        assert!(matches!(subject("x: match (a, b) { _: 1 }"), AstNode::Tuple { elements, .. } if elements.len() == 2));
+        assert!(matches!(subject("x: match(a) { _: 1 }"), AstNode::Identifier(name, _) if name == "a"));
+        assert!(matches!(subject("x: match [a, b] { _: 1 }"), AstNode::Array { elements, .. } if elements.len() == 2));
+        assert!(matches!(subject("x: match -a { _: 1 }"), AstNode::Unary { operator: UnaryOp::Negate, .. }));
+        assert!(matches!(subject("x: match !a { _: 1 }"), AstNode::Unary { operator: UnaryOp::Not, .. }));
+        assert!(matches!(subject("x: match $\"{a}\" { _: 1 }"), AstNode::Template { .. }));
+    }
 }
@@ -0,0 +1,260 @@
+use crate::diagnostics::{Diagnostic, SourceMap};
+use crate::lexer::{Lexer, LexerError, Span};
+use crate::parser::{AstNode, ParseError, ParseWarning, Parser};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One source file of a project
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: PathBuf,
+    // Prefix of the file's global names, `None` for the entry file
+    pub prefix: Option<String>,
+    pub ast: AstNode,
+    // The module each import namespace of this file refers to
+    pub imports: HashMap<String, usize>,
+    pub token_count: usize,
+    pub warnings: Vec<ParseWarning>,
+    // Lexer and parser errors; `ast` then holds what could still be parsed
+    pub errors: Vec<SyntaxError>,
+}
+
+/// A program and every file it imports, directly or not. The entry file is
+/// the first module.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub modules: Vec<Module>,
+    // The source of every module, in module order
+    pub sources: SourceMap,
+}
+
+impl Project {
+    /// Parse `entry` and the files it imports. Import paths are relative to
+    /// the importing file, and a file imported from several places is loaded
+    /// once, so files may import each other. Imported files must be inside the
+    /// entry file's directory. Syntax errors don't stop loading; they are kept
+    /// on their module, see `errors`.
+    pub fn load(entry: &Path) -> Result<Self, ProjectError> {
+        let root = fs::canonicalize(entry)
+            .map_err(|error| ProjectError::Read { path: entry.display().to_string(), message: error.to_string() })?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let mut project = Project { modules: Vec::new(), sources: SourceMap::new() };
+        let mut loaded = HashMap::new();
+        project.load_module(entry, &root, &mut loaded)?;
+        Ok(project)
+    }
+
+    fn load_module(&mut self, path: &Path, root: &Path, loaded: &mut HashMap<PathBuf, usize>) -> Result<usize, ProjectError> {
+        let display = path.display().to_string();
+        let canonical = fs::canonicalize(path)
+            .map_err(|error| ProjectError::Read { path: display.clone(), message: error.to_string() })?;
+        if let Some(&index) = loaded.get(&canonical) {
+            return Ok(index);
+        }
+
+        let source = fs::read_to_string(&canonical)
+            .map_err(|error| ProjectError::Read { path: display.clone(), message: error.to_string() })?;
+        // Spans are offsets into the whole project, so they also tell the file apart
+        let offset = self.sources.add(path, &source);
+        let mut lexer = Lexer::with_offset(&source, offset);
+        let tokens = lexer.tokenize_recovering();
+        let token_count = tokens.len();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_recovering();
+        let index = self.modules.len();
+        let mut errors: Vec<SyntaxError> = lexer.errors().iter().cloned().map(SyntaxError::Lex)
+            .chain(parser.errors().iter().cloned().map(SyntaxError::Parse))
+            .collect();
+        // The lexer and parser each report in order, so merge the two by position
+        errors.sort_by_key(|error| self.sources.syntax_diagnostic(index, error).span.map(|span| span.start));
+
+        let prefix = (index > 0).then(|| Self::module_prefix(root, &canonical));
+        let imports: Vec<(String, String, Span)> = match &ast {
+            AstNode::Program(statements) => statements.iter()
+                .filter_map(|statement| match statement {
+                    AstNode::Import { namespace, path, span } => Some((namespace.clone(), path.clone(), *span)),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        self.modules.push(Module {
+            path: path.to_path_buf(),
+            prefix,
+            ast,
+            imports: HashMap::new(),
+            token_count,
+            warnings: parser.warnings().to_vec(),
+            errors,
+        });
+        loaded.insert(canonical.clone(), index);
+
+        let directory = canonical.parent().map(Path::to_path_buf).unwrap_or_default();
+        for (namespace, import_path, span) in imports {
+            if self.modules[index].imports.contains_key(&namespace) {
+                return Err(ProjectError::DuplicateNamespace { namespace, path: display, span });
+            }
+            let target = directory.join(&import_path);
+            // Module prefixes are paths from the entry file's directory
+            if fs::canonicalize(&target).is_ok_and(|canonical| !canonical.starts_with(root)) {
+                return Err(ProjectError::ImportOutsideProject { import: import_path, path: display, span });
+            }
+            let imported = self.load_module(&target, root, loaded)?;
+            self.modules[index].imports.insert(namespace, imported);
+        }
+
+        Ok(index)
+    }
+
+    /// Every syntax error in the project with the file it was found in, in
+    /// source order
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &SyntaxError)> {
+        self.modules.iter()
+            .flat_map(|module| module.errors.iter().map(move |error| (module.path.as_path(), error)))
+    }
+
+    /// Every syntax error and parser warning of the project, in source order
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = self.modules.iter().enumerate()
+            .flat_map(|(index, module)| module.errors.iter()
+                .map(move |error| self.sources.syntax_diagnostic(index, error))
+                .chain(module.warnings.iter().map(Diagnostic::from)))
+            .collect();
+        // Spans grow from file to file, so this also keeps files in order
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.map(|span| span.start));
+        diagnostics
+    }
+
+    /// The file's path from the entry file's directory, without the extension
+    fn module_prefix(root: &Path, path: &Path) -> String {
+        let relative = path.strip_prefix(root).unwrap_or(path).with_extension("");
+        relative.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// A lexer or parser error in one file
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SyntaxError {
+    #[error("{0}")]
+    Lex(LexerError),
+
+    #[error("{0}")]
+    Parse(ParseError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProjectError {
+    #[error("Failed to read {path}: {message}")]
+    Read { path: String, message: String },
+
+    #[error("Namespace '{namespace}' is imported twice in {path} at line {}", .span.line)]
+    DuplicateNamespace { namespace: String, path: String, span: Span },
+
+    #[error("'{import}' imported in {path} at line {} is outside the entry file's directory", .span.line)]
+    ImportOutsideProject { import: String, path: String, span: Span },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(directory: &TempDir, name: &str, source: &str) -> PathBuf {
+        let path = directory.path().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_follows_imports_once() {
+        let directory = TempDir::new().unwrap();
+        let entry = write(&directory, "main.loaf", "a: @import \"shared/a.loaf\"\nb: @import \"b.loaf\"\nx: a.value");
+        write(&directory, "shared/a.loaf", "back: @import \"../main.loaf\"\nvalue: 1");
+        write(&directory, "b.loaf", "a: @import \"shared/a.loaf\"\nvalue: 2");
+
+        let project = Project::load(&entry).unwrap();
+        let prefixes: Vec<_> = project.modules.iter().map(|module| module.prefix.clone()).collect();
+        assert_eq!(prefixes, [None, Some("shared/a".to_string()), Some("b".to_string())]);
+
+        // Every import of a file refers to the same module, including the entry file
+        assert_eq!(project.modules[0].imports["a"], 1);
+        assert_eq!(project.modules[1].imports["back"], 0);
+        assert_eq!(project.modules[2].imports["a"], 1);
+    }
+
+    #[test]
+    fn test_load_errors_name_the_file() {
+        let directory = TempDir::new().unwrap();
+        let entry = write(&directory, "main.loaf", "a: @import \"missing.loaf\"");
+        assert!(matches!(Project::load(&entry), Err(ProjectError::Read { path, .. }) if path.ends_with("missing.loaf")));
+
+        let entry = write(&directory, "twice.loaf", "a: @import \"b.loaf\"\na: @import \"b.loaf\"");
+        write(&directory, "b.loaf", "x: 1");
+        assert!(matches!(Project::load(&entry), Err(ProjectError::DuplicateNamespace { span, .. }) if span.line == 2));
+
+        let entry = write(&directory, "app/main.loaf", "ok: @import \"lib/ok.loaf\"\nshared: @import \"../shared.loaf\"");
+        write(&directory, "app/lib/ok.loaf", "x: 1");
+        write(&directory, "shared.loaf", "x: 1");
+        assert!(matches!(Project::load(&entry), Err(ProjectError::ImportOutsideProject { import, span, .. }) if import == "../shared.loaf" && span.line == 2));
+
+        let entry = write(&directory, "broken.loaf", "a: @import \"bad.loaf\"");
+        write(&directory, "bad.loaf", "x: (1");
+        let project = Project::load(&entry).unwrap();
+        let errors: Vec<_> = project.errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.ends_with("bad.loaf"));
+        assert!(matches!(errors[0].1, SyntaxError::Parse(_)));
+    }
+
+    #[test]
+    fn test_load_collects_errors_from_every_file() {
+        let directory = TempDir::new().unwrap();
+        let entry = write(&directory, "main.loaf", "a: @import \"a.loaf\"\nx: [1, 2\ny: \"open");
+        write(&directory, "a.loaf", "p: { q: ), r: 2 }\ns: ~\nt: 3");
+
+        let project = Project::load(&entry).unwrap();
+        assert_eq!(project.modules.len(), 2);
+        // Lexer and parser errors come out in source order
+        assert!(matches!(project.modules[0].errors[..], [SyntaxError::Parse(_), SyntaxError::Lex(LexerError::UnterminatedString(3, 4))]));
+        assert!(matches!(project.modules[1].errors[..], [SyntaxError::Parse(_), SyntaxError::Lex(LexerError::UnexpectedCharacter('~', 2, 4))]));
+
+        // The rest of the broken file is still parsed
+        let AstNode::Program(statements) = &project.modules[1].ast else { panic!("Expected program") };
+        assert_eq!(statements.len(), 3);
+
+        // Each error is rendered against the file it was found in
+        let rendered: Vec<_> = project.diagnostics().iter().map(|diagnostic| project.sources.render(diagnostic)).collect();
+        assert_eq!(rendered.len(), 4);
+        assert!(rendered[2].contains("a.loaf:1:9\n  |\n1 | p: { q: ), r: 2 }\n  |         ^"), "{}", rendered[2]);
+    }
+
+    #[tokio::test]
+    async fn test_project_compiles_to_one_program() {
+        let directory = TempDir::new().unwrap();
+        let entry = write(&directory, "main.loaf", "h: @import \"lib/helpers.loaf\"\nname: h.fullName({ first: \"Ada\", last: \"Lovelace\" })\ntotal: 100 * h.rate");
+        write(&directory, "lib/helpers.loaf", "fullName(u): u.first + \" \" + u.last\nrate: 2");
+
+        let project = Project::load(&entry).unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze_project(&project).unwrap();
+        let program = crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap();
+        assert!(program.functions.contains_key("lib/helpers.fullName"));
+
+        let vm = crate::runtime::VirtualMachine::new(program);
+        let mut heap = crate::runtime::IsolatedHeap::new();
+        vm.execute(0, &mut heap).await.unwrap();
+        assert_eq!(heap.get_variable("name").unwrap().to_json(), serde_json::json!("Ada Lovelace"));
+        assert_eq!(heap.get_variable("total").unwrap().to_json(), serde_json::json!(200));
+        assert_eq!(heap.get_variable("lib/helpers.rate").unwrap().to_json(), serde_json::json!(2));
+    }
+}
@@ -1,8 +1,14 @@
-use crate::codegen::{BytecodeProgram, EndpointInfo, Instruction, Value};
+use crate::codegen::{BytecodeProgram, EndpointInfo, Instruction, KeyValue, Value, ValuePattern};
+use crate::lexer::regex_source;
 use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
+use indexmap::IndexMap;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::num::NonZeroU64;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
@@ -42,11 +48,30 @@ pub struct Promise {
     pub dependents: Vec<String>,
 }
 
+/// A function call in progress, with its own variables
+#[derive(Debug)]
+struct CallFrame {
+    return_ip: usize,
+    variables: HashMap<String, Value>,
+}
+
+/// Calls nested deeper than this are reported instead of exhausting memory
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Significant digits kept by a division that doesn't terminate, as in IEEE
+/// decimal128
+const DIVISION_DIGITS: NonZeroU64 = NonZeroU64::new(34).unwrap();
+
 /// Virtual machine for executing bytecode
 pub struct VirtualMachine {
     pub program: BytecodeProgram,
     pub global_heap: Arc<RwLock<IsolatedHeap>>,
-    pub endpoint_handlers: HashMap<String, EndpointInfo>,
+    // In declaration order, so the first matching endpoint wins
+    pub endpoint_handlers: IndexMap<String, EndpointInfo>,
+    // Regex literals compiled once per program, keyed by regex source
+    regexes: HashMap<String, Regex>,
+    // `@cache` values shared by every run, with the time they go stale
+    cache: Arc<RwLock<HashMap<String, (Value, Instant)>>>,
 }
 
 impl IsolatedHeap {
@@ -101,7 +126,7 @@ impl IsolatedHeap {
     }
 
     pub fn is_promise_resolved(&self, id: &str) -> bool {
-        self.promises.get(id).map_or(false, |p| p.resolved)
+        self.promises.get(id).is_some_and(|p| p.resolved)
     }
 
     pub fn get_promise_value(&self, id: &str) -> Option<&Value> {
@@ -113,16 +138,29 @@ impl VirtualMachine {
     pub fn new(program: BytecodeProgram) -> Self {
         let endpoint_handlers = program.endpoints.clone();
         
+        let mut regexes = HashMap::new();
+        for constant in &program.constants {
+            if let Value::Regex { pattern, flags } = constant {
+                let source = regex_source(pattern, flags);
+                if let Ok(regex) = Regex::new(&source) {
+                    regexes.insert(source, regex);
+                }
+            }
+        }
+        
         Self {
             program,
             global_heap: Arc::new(RwLock::new(IsolatedHeap::new())),
             endpoint_handlers,
+            regexes,
+            cache: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
     /// Execute bytecode starting from a given instruction pointer
     pub async fn execute(&self, start_ip: usize, heap: &mut IsolatedHeap) -> Result<Option<Value>> {
         let mut ip = start_ip;
+        let mut frames: Vec<CallFrame> = Vec::new();
         
         while ip < self.program.instructions.len() {
             let instruction = &self.program.instructions[ip];
@@ -133,7 +171,7 @@ impl VirtualMachine {
                 }
 
                 Instruction::LoadVariable(name) => {
-                    if let Some(value) = heap.get_variable(name) {
+                    if let Some(value) = Self::lookup_variable(&frames, heap, name) {
                         heap.push(value.clone());
                     } else {
                         return Err(anyhow!("Variable not found: {}", name));
@@ -142,7 +180,12 @@ impl VirtualMachine {
 
                 Instruction::StoreVariable(name) => {
                     let value = heap.pop()?;
-                    heap.set_variable(name.clone(), value);
+                    match frames.last_mut() {
+                        Some(frame) => {
+                            frame.variables.insert(name.clone(), value);
+                        }
+                        None => heap.set_variable(name.clone(), value),
+                    }
                 }
 
                 Instruction::Add => {
@@ -173,37 +216,131 @@ impl VirtualMachine {
                     heap.push(result);
                 }
 
+                Instruction::Negate => {
+                    match heap.pop()? {
+                        Value::Number(n) => heap.push(Value::Number(-n)),
+                        other => return Err(anyhow!("Cannot negate non-numeric value {:?}", other)),
+                    }
+                }
+
+                Instruction::Equal => {
+                    let b = heap.pop()?;
+                    let a = heap.pop()?;
+                    heap.push(Value::Boolean(self.values_equal(&a, &b)));
+                }
+
+                Instruction::NotEqual => {
+                    let b = heap.pop()?;
+                    let a = heap.pop()?;
+                    heap.push(Value::Boolean(!self.values_equal(&a, &b)));
+                }
+
+                Instruction::Less | Instruction::LessEqual | Instruction::Greater | Instruction::GreaterEqual => {
+                    let b = heap.pop()?;
+                    let a = heap.pop()?;
+                    let ordering = self.compare_values(&a, &b)?;
+                    let result = match instruction {
+                        Instruction::Less => ordering.is_lt(),
+                        Instruction::LessEqual => ordering.is_le(),
+                        Instruction::Greater => ordering.is_gt(),
+                        _ => ordering.is_ge(),
+                    };
+                    heap.push(Value::Boolean(result));
+                }
+
+                Instruction::And => {
+                    let b = heap.pop()?;
+                    let a = heap.pop()?;
+                    match (a, b) {
+                        (Value::Boolean(x), Value::Boolean(y)) => heap.push(Value::Boolean(x && y)),
+                        (a, b) => return Err(anyhow!("Cannot AND non-boolean values {:?} and {:?}", a, b)),
+                    }
+                }
+
+                Instruction::Not => {
+                    match heap.pop()? {
+                        Value::Boolean(x) => heap.push(Value::Boolean(!x)),
+                        other => return Err(anyhow!("Cannot NOT non-boolean value {:?}", other)),
+                    }
+                }
+
+                Instruction::Concat(count) => {
+                    if heap.stack.len() < *count {
+                        return Err(anyhow!("Stack underflow"));
+                    }
+                    let start = heap.stack.len() - count;
+                    let mut result = String::new();
+                    for value in heap.stack.drain(start..) {
+                        result.push_str(&self.stringify_value(&value));
+                    }
+                    heap.push(Value::String(result));
+                }
+
                 Instruction::CreateObject => {
-                    heap.push(Value::Object(HashMap::new()));
+                    heap.push(Value::Object(IndexMap::new()));
                 }
 
                 Instruction::SetProperty(key) => {
                     let value = heap.pop()?;
                     let mut object = heap.pop()?;
                     
-                    if let Value::Object(ref mut map) = object {
-                        map.insert(key.clone(), value);
-                        heap.push(object);
-                    } else {
-                        return Err(anyhow!("Cannot set property on non-object"));
-                    }
+                    match object {
+                        Value::Object(ref mut map) => map.insert(key.clone(), value),
+                        Value::Map(ref mut map) => map.insert(KeyValue::String(key.clone()), value),
+                        _ => return Err(anyhow!("Cannot set property on non-object")),
+                    };
+                    heap.push(object);
+                }
+
+                Instruction::SetEntry => {
+                    let value = heap.pop()?;
+                    let key = heap.pop()?;
+                    let object = heap.pop()?;
+                    
+                    let mut entries = object.to_entries()
+                        .ok_or_else(|| anyhow!("Cannot set entry on non-object"))?;
+                    let key = key.to_key()
+                        .ok_or_else(|| anyhow!("Cannot use {:?} as an object key", key))?;
+                    entries.insert(key, value);
+                    heap.push(Value::from_entries(entries));
                 }
 
                 Instruction::GetProperty(key) => {
                     let object = heap.pop()?;
                     
-                    if let Value::Object(ref map) = object {
-                        let value = map.get(key).cloned().unwrap_or(Value::Null);
-                        heap.push(value);
-                    } else {
+                    if !matches!(object, Value::Object(_) | Value::Map(_)) {
                         return Err(anyhow!("Cannot get property from non-object"));
                     }
+                    heap.push(object.field(key).cloned().unwrap_or(Value::Null));
+                }
+
+                // Only a null receiver short-circuits, anything else must be an object
+                Instruction::GetPropertyOrNull(key) => {
+                    let value = match heap.pop()? {
+                        Value::Null => Value::Null,
+                        object @ (Value::Object(_) | Value::Map(_)) => object.field(key).cloned().unwrap_or(Value::Null),
+                        _ => return Err(anyhow!("Cannot get property from non-object")),
+                    };
+                    heap.push(value);
+                }
+
+                Instruction::GetIndex => {
+                    let index = heap.pop()?;
+                    let collection = heap.pop()?;
+                    heap.push(self.index_value(&collection, &index)?);
                 }
 
                 Instruction::CreateArray => {
                     heap.push(Value::Array(Vec::new()));
                 }
 
+                Instruction::CreateTuple(count) => {
+                    let start = heap.stack.len().checked_sub(*count)
+                        .ok_or_else(|| anyhow!("Stack underflow"))?;
+                    let elements = heap.stack.split_off(start);
+                    heap.push(Value::Tuple(elements));
+                }
+
                 Instruction::AppendArray => {
                     let value = heap.pop()?;
                     let mut array = heap.pop()?;
@@ -216,6 +353,16 @@ impl VirtualMachine {
                     }
                 }
 
+                Instruction::CallMethod { method, argument_count } => {
+                    if heap.stack.len() < argument_count + 1 {
+                        return Err(anyhow!("Stack underflow"));
+                    }
+                    let arguments = heap.stack.split_off(heap.stack.len() - argument_count);
+                    let receiver = heap.pop()?;
+                    let result = self.call_method(&receiver, method, &arguments)?;
+                    heap.push(result);
+                }
+
                 Instruction::CreatePromise(id) => {
                     heap.create_promise(id.clone())?;
                     heap.push(Value::Promise(id.clone()));
@@ -233,6 +380,9 @@ impl VirtualMachine {
                         } else {
                             heap.push(Value::Null);
                         }
+                    } else if let Some(value) = Self::lookup_variable(&frames, heap, id) {
+                        // A promise-typed symbol that has already been stored
+                        heap.push(value.clone());
                     } else {
                         // In a real implementation, this would suspend execution
                         // For now, we'll return null for unresolved promises
@@ -240,13 +390,49 @@ impl VirtualMachine {
                     }
                 }
 
-                Instruction::HttpCall { method, url, body } => {
+                Instruction::HttpCall { method, body } => {
+                    let url = match heap.pop()? {
+                        Value::String(url) => url,
+                        other => return Err(anyhow!("HTTP url must be a string, found {:?}", other)),
+                    };
                     // Simulate HTTP call - in a real implementation this would make actual HTTP requests
-                    let response = self.simulate_http_call(method, url, body.as_deref()).await?;
-                    heap.push(Value::String(response));
+                    let response = Value::String(self.simulate_http_call(method, &url, body.as_deref()).await?);
+                    // The call is a promise of its response body; it is awaited here,
+                    // so the expression yields the resolved body
+                    let promise_id = format!("http:{}", Uuid::new_v4());
+                    heap.create_promise(promise_id.clone())?;
+                    heap.resolve_promise(&promise_id, response.clone())?;
+                    heap.push(response);
+                }
+
+                Instruction::UrlEncode => {
+                    let value = heap.pop()?;
+                    heap.push(Value::String(Self::url_encode(&self.stringify_value(&value))));
+                }
+
+                Instruction::Call { function, argument_count } => {
+                    let info = self.program.functions.get(function)
+                        .ok_or_else(|| anyhow!("Function not found: {}", function))?;
+                    if frames.len() >= MAX_CALL_DEPTH {
+                        return Err(anyhow!("Call stack overflow in {}", function));
+                    }
+                    let start = heap.stack.len().checked_sub(*argument_count)
+                        .ok_or_else(|| anyhow!("Stack underflow"))?;
+                    let arguments = heap.stack.split_off(start);
+                    frames.push(CallFrame {
+                        return_ip: ip + 1,
+                        variables: info.parameters.iter().cloned().zip(arguments).collect(),
+                    });
+                    ip = info.start;
+                    continue;
                 }
 
                 Instruction::Return => {
+                    // A function leaves its result on the stack for the caller
+                    if let Some(frame) = frames.pop() {
+                        ip = frame.return_ip;
+                        continue;
+                    }
                     return Ok(heap.stack.last().cloned());
                 }
 
@@ -263,6 +449,54 @@ impl VirtualMachine {
                     }
                 }
 
+                Instruction::JumpIfSet(name, target) => {
+                    if Self::lookup_variable(&frames, heap, name).is_some() {
+                        ip = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::LoadCached(name, target) => {
+                    let cached = self.cache.read().await.get(name)
+                        .filter(|(_, expires)| Instant::now() < *expires)
+                        .map(|(value, _)| value.clone());
+                    if let Some(value) = cached {
+                        match frames.last_mut() {
+                            Some(frame) => {
+                                frame.variables.insert(name.clone(), value);
+                            }
+                            None => heap.set_variable(name.clone(), value),
+                        }
+                        ip = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::StoreCached { name, seconds } => {
+                    let value = Self::lookup_variable(&frames, heap, name)
+                        .ok_or_else(|| anyhow!("Variable not found: {}", name))?
+                        .clone();
+                    let expires = Instant::now() + Duration::from_secs(*seconds);
+                    self.cache.write().await.insert(name.clone(), (value, expires));
+                }
+
+                Instruction::MatchPattern(pattern) => {
+                    let value = heap.pop()?;
+                    let mut bindings = Vec::new();
+                    let matched = self.match_value(pattern, &value, &mut bindings)?;
+                    if matched {
+                        for (name, value) in bindings {
+                            match frames.last_mut() {
+                                Some(frame) => {
+                                    frame.variables.insert(name, value);
+                                }
+                                None => heap.set_variable(name, value),
+                            }
+                        }
+                    }
+                    heap.push(Value::Boolean(matched));
+                }
+
                 Instruction::Duplicate => {
                     let value = heap.peek()?.clone();
                     heap.push(value);
@@ -284,10 +518,6 @@ impl VirtualMachine {
                     // Endpoint registration is handled during startup
                     // Skip this instruction during execution
                 }
-
-                _ => {
-                    return Err(anyhow!("Unsupported instruction: {:?}", instruction));
-                }
             }
             
             ip += 1;
@@ -296,6 +526,13 @@ impl VirtualMachine {
         Ok(heap.stack.last().cloned())
     }
 
+    /// Variables of the innermost call shadow the globals
+    fn lookup_variable<'a>(frames: &'a [CallFrame], heap: &'a IsolatedHeap, name: &str) -> Option<&'a Value> {
+        frames.last()
+            .and_then(|frame| frame.variables.get(name))
+            .or_else(|| heap.get_variable(name))
+    }
+
     /// Handle HTTP request by finding matching endpoint and executing it
     pub async fn handle_request(&self, request: HttpRequest) -> Result<HttpResponse> {
         // Find matching endpoint
@@ -306,7 +543,7 @@ impl VirtualMachine {
         
         // Set up request context variables
         isolated_heap.set_variable("request".to_string(), Value::Object({
-            let mut req_obj = HashMap::new();
+            let mut req_obj = IndexMap::new();
             req_obj.insert("method".to_string(), Value::String(request.method.clone()));
             req_obj.insert("path".to_string(), Value::String(request.path.clone()));
             if let Some(body) = &request.body {
@@ -321,9 +558,7 @@ impl VirtualMachine {
         // Convert result to HTTP response
         let response_body = match result {
             Some(Value::String(s)) => Some(s),
-            Some(Value::Object(obj)) => Some(serde_json::to_string(&obj)?),
-            Some(Value::Array(arr)) => Some(serde_json::to_string(&arr)?),
-            Some(other) => Some(format!("{:?}", other)),
+            Some(other) => Some(serde_json::to_string(&other.to_json())?),
             None => None,
         };
 
@@ -362,6 +597,19 @@ impl VirtualMachine {
         match (a, b) {
             (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x + y)),
             (Value::String(x), Value::String(y)) => Ok(Value::String(format!("{}{}", x, y))),
+            (Value::Array(x), Value::Array(y)) => Ok(Value::Array(x.iter().chain(y).cloned().collect())),
+            (Value::Array(x), item) => Ok(Value::Array(x.iter().chain([item]).cloned().collect())),
+            // Merge, keys on the right replace keys on the left
+            (Value::Object(x), Value::Object(y)) => {
+                let mut merged = x.clone();
+                merged.extend(y.iter().map(|(key, value)| (key.clone(), value.clone())));
+                Ok(Value::Object(merged))
+            }
+            (Value::Object(_) | Value::Map(_), Value::Object(_) | Value::Map(_)) => {
+                let mut merged = a.to_entries().unwrap_or_default();
+                merged.extend(b.to_entries().unwrap_or_default());
+                Ok(Value::from_entries(merged))
+            }
             _ => Err(anyhow!("Cannot add values of different types")),
         }
     }
@@ -369,38 +617,265 @@ impl VirtualMachine {
     fn subtract_values(&self, a: &Value, b: &Value) -> Result<Value> {
         match (a, b) {
             (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x - y)),
-            _ => Err(anyhow!("Cannot subtract non-numeric values")),
+            // Remove every occurrence of the substring
+            (Value::String(x), Value::String(y)) if y.is_empty() => Ok(Value::String(x.clone())),
+            (Value::String(x), Value::String(y)) => Ok(Value::String(x.replace(y.as_str(), ""))),
+            (Value::Array(x), items) => {
+                let items = Self::operand_items(items);
+                Ok(Value::Array(x.iter()
+                    .filter(|value| !items.iter().any(|item| self.values_equal(value, item)))
+                    .cloned()
+                    .collect()))
+            }
+            (Value::Object(_) | Value::Map(_), keys) => {
+                let keys = Self::object_keys(a, keys);
+                Ok(Value::from_entries(a.to_entries().unwrap_or_default().into_iter()
+                    .filter(|(key, _)| !keys.contains(key))
+                    .collect()))
+            }
+            _ => Err(anyhow!("Cannot subtract {:?} from {:?}", b, a)),
         }
     }
 
     fn multiply_values(&self, a: &Value, b: &Value) -> Result<Value> {
         match (a, b) {
             (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x * y)),
-            _ => Err(anyhow!("Cannot multiply non-numeric values")),
+            // Keep the characters that also appear on the right
+            (Value::String(x), Value::String(y)) => Ok(Value::String(x.chars().filter(|c| y.contains(*c)).collect())),
+            (Value::Array(x), items) => {
+                let items = Self::operand_items(items);
+                Ok(Value::Array(x.iter()
+                    .filter(|value| items.iter().any(|item| self.values_equal(value, item)))
+                    .cloned()
+                    .collect()))
+            }
+            (Value::Object(_) | Value::Map(_), keys) => {
+                let keys = Self::object_keys(a, keys);
+                Ok(Value::from_entries(a.to_entries().unwrap_or_default().into_iter()
+                    .filter(|(key, _)| keys.contains(key))
+                    .collect()))
+            }
+            _ => Err(anyhow!("Cannot intersect {:?} with {:?}", a, b)),
+        }
+    }
+
+    /// The items on the right of an array `-` or `*`; a non-array is a single item
+    fn operand_items(value: &Value) -> &[Value] {
+        match value {
+            Value::Array(items) => items,
+            item => std::slice::from_ref(item),
         }
     }
 
+    /// The keys on the right of an object `-` or `*`: a key or an array of
+    /// keys. Any value can be a key, and a number also picks the string key
+    /// it is written as, as it does in `object[1]`.
+    fn object_keys(object: &Value, keys: &Value) -> Vec<KeyValue> {
+        Self::operand_items(keys).iter()
+            .filter_map(Value::to_key)
+            .map(|key| match (&key, object) {
+                (_, Value::Map(map)) if map.contains_key(&key) => key,
+                (KeyValue::Number(n), _) if object.field(&n.to_string()).is_some() => KeyValue::String(n.to_string()),
+                _ => key,
+            })
+            .collect()
+    }
+
     fn divide_values(&self, a: &Value, b: &Value) -> Result<Value> {
         match (a, b) {
             (Value::Number(x), Value::Number(y)) => {
-                if *y == 0.0 {
+                if y.is_zero() {
                     Err(anyhow!("Division by zero"))
                 } else {
-                    Ok(Value::Number(x / y))
+                    Ok(Value::Number(Self::round_quotient(x / y)))
                 }
             }
             _ => Err(anyhow!("Cannot divide non-numeric values")),
         }
     }
 
+    /// A quotient that doesn't terminate within `DIVISION_DIGITS` significant
+    /// digits is rounded to that many, half to even. Shorter quotients are exact.
+    fn round_quotient(quotient: BigDecimal) -> BigDecimal {
+        if quotient.digits() <= DIVISION_DIGITS.get() {
+            return quotient;
+        }
+        quotient.with_precision_round(DIVISION_DIGITS, RoundingMode::HalfEven)
+    }
+
+    /// Deep structural equality; numbers compare by value, so `1.0 = 1`
+    fn values_equal(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Null, Value::Null) => true,
+            (Value::Boolean(x), Value::Boolean(y)) => x == y,
+            (Value::Number(x), Value::Number(y)) => x == y,
+            (Value::String(x), Value::String(y)) => x == y,
+            (Value::Array(x), Value::Array(y)) | (Value::Tuple(x), Value::Tuple(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(a, b)| self.values_equal(a, b))
+            }
+            (Value::Object(x), Value::Object(y)) => {
+                x.len() == y.len() && x.iter().all(|(key, a)| y.get(key).is_some_and(|b| self.values_equal(a, b)))
+            }
+            (Value::Map(x), Value::Map(y)) => {
+                x.len() == y.len() && x.iter().all(|(key, a)| y.get(key).is_some_and(|b| self.values_equal(a, b)))
+            }
+            (Value::Regex { pattern: p1, flags: f1 }, Value::Regex { pattern: p2, flags: f2 }) => p1 == p2 && f1 == f2,
+            (Value::Promise(x), Value::Promise(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    /// Whether `value` matches `pattern`, collecting what it binds
+    fn match_value(&self, pattern: &ValuePattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> Result<bool> {
+        match (pattern, value) {
+            (ValuePattern::Any, _) => Ok(true),
+            (ValuePattern::Bind(name), _) => {
+                bindings.push((name.clone(), value.clone()));
+                Ok(true)
+            }
+            (ValuePattern::Value(expected), _) => Ok(self.values_equal(expected, value)),
+            (ValuePattern::Range { start, end }, Value::Number(number)) => Ok(start.as_ref().is_none_or(|start| start <= number)
+                && end.as_ref().is_none_or(|end| number <= end)),
+            (ValuePattern::Regex { pattern, flags }, Value::String(text)) => {
+                let regex = self.regexes.get(&regex_source(pattern, flags))
+                    .ok_or_else(|| anyhow!("Regex /{}/{} was not compiled with the program", pattern, flags))?;
+                Ok(regex.is_match(text))
+            }
+            (ValuePattern::Array { elements, rest }, Value::Array(items) | Value::Tuple(items)) => {
+                let length_matches = match rest {
+                    Some(_) => elements.len() <= items.len(),
+                    None => elements.len() == items.len(),
+                };
+                if !length_matches {
+                    return Ok(false);
+                }
+                for (element, item) in elements.iter().zip(items) {
+                    if !self.match_value(element, item, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                // The rest keeps the kind of sequence it was taken from
+                match rest {
+                    Some(rest) => {
+                        let remaining = items[elements.len()..].to_vec();
+                        let remaining = match value {
+                            Value::Tuple(_) => Value::Tuple(remaining),
+                            _ => Value::Array(remaining),
+                        };
+                        self.match_value(rest, &remaining, bindings)
+                    }
+                    None => Ok(true),
+                }
+            }
+            (ValuePattern::Object(fields), Value::Object(_) | Value::Map(_)) => {
+                for (field, field_pattern) in fields {
+                    match value.field(field) {
+                        Some(field_value) if self.match_value(field_pattern, field_value, bindings)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn compare_values(&self, a: &Value, b: &Value) -> Result<std::cmp::Ordering> {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => Ok(x.cmp(y)),
+            // Strings order by code point
+            (Value::String(x), Value::String(y)) => Ok(x.cmp(y)),
+            _ => Err(anyhow!("Cannot compare {:?} and {:?}", a, b)),
+        }
+    }
+
+    /// Arrays are sparse: reads outside the array, or at a non-integer
+    /// index, give null. Negative indexes count back from the end.
+    fn index_value(&self, collection: &Value, index: &Value) -> Result<Value> {
+        match (collection, index) {
+            (Value::Array(items) | Value::Tuple(items), Value::Number(n)) => {
+                let position = n.to_i64().filter(|_| n.is_integer()).and_then(|i| {
+                    if i < 0 { items.len().checked_sub(i.unsigned_abs() as usize) } else { Some(i as usize) }
+                });
+                Ok(position.and_then(|i| items.get(i)).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Object(_) | Value::Map(_), key) => Ok(key.to_key().and_then(|key| collection.entry(&key).cloned()).unwrap_or(Value::Null)),
+            (Value::Array(_) | Value::Tuple(_), _) => Ok(Value::Null),
+            _ => Err(anyhow!("Cannot index into {:?}", collection)),
+        }
+    }
+
+    fn call_method(&self, receiver: &Value, method: &str, arguments: &[Value]) -> Result<Value> {
+        match receiver {
+            Value::Regex { pattern, flags } => {
+                let regex = self.regexes.get(&regex_source(pattern, flags))
+                    .ok_or_else(|| anyhow!("Regex /{}/{} was not compiled with the program", pattern, flags))?;
+                let global = flags.contains('g');
+                
+                match (method, arguments) {
+                    ("test", [Value::String(text)]) => Ok(Value::Boolean(regex.is_match(text))),
+                    ("match", [Value::String(text)]) if global => {
+                        let matches: Vec<Value> = regex.find_iter(text)
+                            .map(|m| Value::String(m.as_str().to_string()))
+                            .collect();
+                        Ok(if matches.is_empty() { Value::Null } else { Value::Array(matches) })
+                    }
+                    ("match", [Value::String(text)]) => {
+                        // The whole match followed by each capture group, or null
+                        Ok(regex.captures(text).map_or(Value::Null, |captures| {
+                            Value::Array(captures.iter()
+                                .map(|group| group.map_or(Value::Null, |m| Value::String(m.as_str().to_string())))
+                                .collect())
+                        }))
+                    }
+                    ("replace", [Value::String(text), Value::String(replacement)]) => {
+                        let replaced = if global {
+                            regex.replace_all(text, replacement.as_str())
+                        } else {
+                            regex.replace(text, replacement.as_str())
+                        };
+                        Ok(Value::String(replaced.into_owned()))
+                    }
+                    ("test" | "match" | "replace", _) => {
+                        Err(anyhow!("Invalid arguments for regex method '{}': {:?}", method, arguments))
+                    }
+                    _ => Err(anyhow!("Unknown regex method '{}'", method)),
+                }
+            }
+            _ => Err(anyhow!("Cannot call method '{}' on {:?}", method, receiver)),
+        }
+    }
+
+    /// Percent-encode everything but unreserved characters, so an embedded
+    /// value stays within its path segment or query parameter
+    fn url_encode(text: &str) -> String {
+        let mut encoded = String::new();
+        for byte in text.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+                _ => encoded.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        encoded
+    }
+
+    fn stringify_value(&self, value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_json().to_string(),
+        }
+    }
+
     fn is_truthy(&self, value: &Value) -> bool {
         match value {
             Value::Null => false,
             Value::Boolean(b) => *b,
-            Value::Number(n) => *n != 0.0,
+            Value::Number(n) => !n.is_zero(),
             Value::String(s) => !s.is_empty(),
-            Value::Array(arr) => !arr.is_empty(),
+            Value::Array(arr) | Value::Tuple(arr) => !arr.is_empty(),
             Value::Object(obj) => !obj.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+            Value::Regex { .. } => true,
             Value::Promise(_) => true, // Promises are always truthy
         }
     }
@@ -442,17 +917,19 @@ impl LoafServer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
 
     #[test]
     fn test_isolated_heap() {
         let mut heap = IsolatedHeap::new();
         
         // Test stack operations
-        heap.push(Value::Number(42.0));
+        heap.push(Value::Number(BigDecimal::from(42)));
         assert_eq!(heap.stack.len(), 1);
         
         let value = heap.pop().unwrap();
-        assert!(matches!(value, Value::Number(42.0)));
+        assert!(matches!(value, Value::Number(n) if n == 42));
         assert_eq!(heap.stack.len(), 0);
         
         // Test variable operations
@@ -470,11 +947,574 @@ mod tests {
         
         assert!(!heap.is_promise_resolved(&promise_id));
         
-        heap.resolve_promise(&promise_id, Value::Number(123.0)).unwrap();
+        heap.resolve_promise(&promise_id, Value::Number(BigDecimal::from(123))).unwrap();
         assert!(heap.is_promise_resolved(&promise_id));
         
         let value = heap.get_promise_value(&promise_id).unwrap();
-        assert!(matches!(value, Value::Number(123.0)));
+        assert!(matches!(value, Value::Number(n) if *n == 123));
+    }
+
+    #[tokio::test]
+    async fn test_decimal_arithmetic_is_exact() {
+        let program = BytecodeProgram {
+            instructions: vec![
+                Instruction::LoadConstant(Value::Number(BigDecimal::from_str("0.05").unwrap())),
+                Instruction::LoadConstant(Value::Number(BigDecimal::from_str("0.10").unwrap())),
+                Instruction::Add,
+                Instruction::Return,
+            ],
+            constants: vec![],
+            endpoints: {
+                let mut endpoints = IndexMap::new();
+                endpoints.insert("total".to_string(), EndpointInfo {
+                    method: "GET".to_string(),
+                    path: "/total".to_string(),
+                    handler_start: 0,
+                    handler_end: 4,
+                });
+                endpoints
+            },
+            functions: IndexMap::new(),
+            entry_point: 0,
+        };
+
+        let vm = VirtualMachine::new(program);
+        let mut heap = IsolatedHeap::new();
+        let result = vm.execute(0, &mut heap).await.unwrap();
+        assert!(matches!(result, Some(Value::Number(n)) if n.to_string() == "0.15"));
+
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/total".to_string(),
+            headers: HashMap::new(),
+            body: None,
+            query_params: HashMap::new(),
+        };
+        let response = vm.handle_request(request).await.unwrap();
+        assert_eq!(response.body.as_deref(), Some("0.15"));
+    }
+
+    #[tokio::test]
+    async fn test_division_rounds_repeating_quotients() {
+        let heap = run_source("third: 1 / 3\ntwoThirds: -2 / 3\nquarter: 1 / 4\nlong: 12345678901234567890123456789012345678 / 1").await;
+        let number = |name: &str| heap.get_variable(name).unwrap().to_json().to_string();
+        
+        // 34 significant digits, rounded half to even
+        assert_eq!(number("third"), format!("0.{}", "3".repeat(34)));
+        assert_eq!(number("twoThirds"), format!("-0.{}7", "6".repeat(33)));
+        // A quotient that terminates is exact
+        assert_eq!(number("quarter"), "0.25");
+        assert_eq!(number("long"), "12345678901234567890123456789012350000");
+    }
+
+    #[test]
+    fn test_number_json_keeps_scale() {
+        let value = Value::Array(vec![
+            Value::Number(BigDecimal::from_str("0.10").unwrap()),
+            Value::Number(BigDecimal::from_str("123.00").unwrap()),
+        ]);
+        assert_eq!(serde_json::to_string(&value.to_json()).unwrap(), "[0.10,123.00]");
+    }
+
+    #[tokio::test]
+    async fn test_concat_stringifies_values() {
+        let program = BytecodeProgram {
+            instructions: vec![
+                Instruction::LoadConstant(Value::String("total: ".to_string())),
+                Instruction::LoadConstant(Value::Number(BigDecimal::from_str("0.10").unwrap())),
+                Instruction::LoadConstant(Value::String(", paid: ".to_string())),
+                Instruction::LoadConstant(Value::Boolean(true)),
+                Instruction::Concat(4),
+            ],
+            constants: vec![],
+            endpoints: IndexMap::new(),
+            functions: IndexMap::new(),
+            entry_point: 0,
+        };
+
+        let vm = VirtualMachine::new(program);
+        let mut heap = IsolatedHeap::new();
+        let result = vm.execute(0, &mut heap).await.unwrap();
+        assert!(matches!(result, Some(Value::String(s)) if s == "total: 0.10, paid: true"));
+        assert_eq!(heap.stack.len(), 1);
+    }
+
+    fn regex_program(pattern: &str, flags: &str, method: &str, arguments: Vec<Value>) -> BytecodeProgram {
+        let regex = Value::Regex { pattern: pattern.to_string(), flags: flags.to_string() };
+        let mut instructions = vec![Instruction::LoadConstant(regex.clone())];
+        let argument_count = arguments.len();
+        instructions.extend(arguments.into_iter().map(Instruction::LoadConstant));
+        instructions.push(Instruction::CallMethod { method: method.to_string(), argument_count });
+        
+        BytecodeProgram {
+            instructions,
+            constants: vec![regex],
+            endpoints: IndexMap::new(),
+            functions: IndexMap::new(),
+            entry_point: 0,
+        }
+    }
+
+    async fn run_program(program: BytecodeProgram) -> Result<Option<Value>> {
+        let vm = VirtualMachine::new(program);
+        let mut heap = IsolatedHeap::new();
+        vm.execute(0, &mut heap).await
+    }
+
+    #[tokio::test]
+    async fn test_regex_methods() {
+        let text = || Value::String("time 12:34, 56:78".to_string());
+        
+        let result = run_program(regex_program("[0-5][0-9]", "", "test", vec![text()])).await.unwrap();
+        assert!(matches!(result, Some(Value::Boolean(true))));
+        
+        let result = run_program(regex_program("(\\d+):(\\d+)", "", "match", vec![text()])).await.unwrap();
+        match result {
+            Some(Value::Array(groups)) => {
+                let groups: Vec<String> = groups.iter().map(|g| g.to_json().to_string()).collect();
+                assert_eq!(groups, vec!["\"12:34\"", "\"12\"", "\"34\""]);
+            }
+            other => panic!("Expected capture array, got {:?}", other),
+        }
+        
+        let result = run_program(regex_program("\\d+:\\d+", "g", "match", vec![text()])).await.unwrap();
+        assert!(matches!(result, Some(Value::Array(ref all)) if all.len() == 2));
+        
+        let result = run_program(regex_program("x", "", "match", vec![text()])).await.unwrap();
+        assert!(matches!(result, Some(Value::Null)));
+        
+        let replacement = Value::String("#".to_string());
+        let result = run_program(regex_program("\\d", "g", "replace", vec![text(), replacement.clone()])).await.unwrap();
+        assert!(matches!(result, Some(Value::String(s)) if s == "time ##:##, ##:##"));
+        
+        let result = run_program(regex_program("\\d", "", "replace", vec![text(), replacement])).await.unwrap();
+        assert!(matches!(result, Some(Value::String(s)) if s == "time #2:34, 56:78"));
+        
+        let swap = Value::String("$2:$1".to_string());
+        let result = run_program(regex_program("(\\d+):(\\d+)", "g", "replace", vec![text(), swap])).await.unwrap();
+        assert!(matches!(result, Some(Value::String(s)) if s == "time 34:12, 78:56"));
+        
+        let result = run_program(regex_program("TIME", "i", "test", vec![text()])).await.unwrap();
+        assert!(matches!(result, Some(Value::Boolean(true))));
+    }
+
+    #[tokio::test]
+    async fn test_regex_compiled_once_per_program() {
+        let program = regex_program("a+", "", "test", vec![Value::String("caab".to_string())]);
+        let vm = VirtualMachine::new(program);
+        assert_eq!(vm.regexes.len(), 1);
+        
+        // Repeated executions reuse the same compiled regex
+        for _ in 0..3 {
+            let mut heap = IsolatedHeap::new();
+            let result = vm.execute(0, &mut heap).await.unwrap();
+            assert!(matches!(result, Some(Value::Boolean(true))));
+        }
+        assert_eq!(vm.regexes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_regex_method_argument_errors() {
+        let result = run_program(regex_program("a", "", "test", vec![Value::Boolean(true)])).await;
+        assert!(result.is_err());
+    }
+
+    async fn run_source(source: &str) -> IsolatedHeap {
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let program = crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap();
+        
+        let vm = VirtualMachine::new(program);
+        let mut heap = IsolatedHeap::new();
+        vm.execute(0, &mut heap).await.unwrap();
+        heap
+    }
+
+    #[tokio::test]
+    async fn test_object_fields_resolve_within_their_scope() {
+        let heap = run_source(r#"
+            x: 1
+            pair: { ab: a + b, a: x + 10, b: 20, x: 100 }
+        "#).await;
+        
+        // The inner `x` shadows the global one
+        let pair = heap.get_variable("pair").unwrap().to_json();
+        assert_eq!(pair, serde_json::json!({ "ab": 130, "a": 110, "b": 20, "x": 100 }));
+        assert_eq!(heap.get_variable("x").unwrap().to_json(), serde_json::json!(1));
+    }
+
+    #[tokio::test]
+    async fn test_comparison_and_boolean_operators() {
+        let heap = run_source(r#"
+            less: 1.5 < 2
+            scale: 1.0 <= 1
+            text: "apple" > "Apple"
+            equal_objects: { a: [1, { b: "c" }], d: null } = { d: null, a: [1.00, { b: "c" }] }
+            unequal_arrays: [1, 2] != [2, 1]
+            mixed_types: 1 = "1"
+            both: less & !mixed_types
+            negated: -(2 - 5) >= 3
+        "#).await;
+        
+        let value = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(value("less"), serde_json::json!(true));
+        assert_eq!(value("scale"), serde_json::json!(true));
+        assert_eq!(value("text"), serde_json::json!(true));
+        assert_eq!(value("equal_objects"), serde_json::json!(true));
+        assert_eq!(value("unequal_arrays"), serde_json::json!(true));
+        assert_eq!(value("mixed_types"), serde_json::json!(false));
+        assert_eq!(value("both"), serde_json::json!(true));
+        assert_eq!(value("negated"), serde_json::json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_conditional_only_runs_the_taken_branch() {
+        let heap = run_source(r#"
+            is_gold: 1 > 2
+            gold: gold_price()
+            price: is_gold ? gold : 10 + 5
+        "#).await;
+        
+        assert_eq!(heap.get_variable("price").unwrap().to_json(), serde_json::json!(15));
+        assert!(heap.get_variable("gold").is_none());
+        assert!(heap.promises.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_match_runs_only_the_chosen_arm() {
+        let heap = run_source(r#"
+            response: { status: 404, error: "missing" }
+            message: match response {
+                { status: 200..299 }: "ok",
+                { status: 400..499, error }: "client: " + error,
+                _: server_error(),
+            }
+            numbers: [1, 2, 3]
+            rest: match numbers { []: null, [first, ..others]: others }
+            n: -3
+            sign: match n { ..-1: "negative", 0: "zero", _: "positive" }
+            kind: match "v1.2" { /^v\d/: "version", _: "name" }
+            head(list): match list { [first, ..]: first, _: null }
+            heads: [head([7, 8]), head([]), head("x")]
+        "#).await;
+        
+        let value = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(value("message"), serde_json::json!("client: missing"));
+        assert_eq!(value("rest"), serde_json::json!([2, 3]));
+        assert_eq!(value("sign"), serde_json::json!("negative"));
+        assert_eq!(value("kind"), serde_json::json!("version"));
+        assert_eq!(value("heads"), serde_json::json!([7, null, null]));
+        
+        // The fallback arm's call never ran
+        assert!(heap.promises.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_object_patterns_match_maps_by_string_key() {
+        let heap = run_source(r#"
+            scores: { name: "Ada", 1: "first" }
+            label: match scores { { name }: "player " + name, _: "unknown" }
+            missing: match scores { { rank }: rank, _: "unranked" }
+        "#).await;
+        
+        assert_eq!(heap.get_variable("label").unwrap().to_json(), serde_json::json!("player Ada"));
+        assert_eq!(heap.get_variable("missing").unwrap().to_json(), serde_json::json!("unranked"));
+    }
+
+    #[tokio::test]
+    async fn test_null_safe_access_and_coalesce() {
+        let heap = run_source(r#"
+            settings: { theme: null }
+            missing: null
+            theme: settings.theme ?? "light"
+            color: missing?.palette?.primary
+            port: settings.port ?? 8080
+            first: "set" ?? expensive
+            expensive: lookup_default()
+        "#).await;
+        
+        assert_eq!(heap.get_variable("theme").unwrap().to_json(), serde_json::json!("light"));
+        assert_eq!(heap.get_variable("color").unwrap().to_json(), serde_json::Value::Null);
+        assert_eq!(heap.get_variable("port").unwrap().to_json(), serde_json::json!(8080));
+        assert_eq!(heap.get_variable("first").unwrap().to_json(), serde_json::json!("set"));
+        
+        // The fallback is never evaluated when the left side is set
+        assert!(heap.get_variable("expensive").is_none());
+        assert!(heap.promises.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_null_safe_access_only_skips_null() {
+        let heap = run_source("flag: true\nhalf: flag ?.5 : 1").await;
+        assert_eq!(heap.get_variable("half").unwrap().to_json(), serde_json::json!(0.5));
+        
+        // Anything but null or an object is still an error
+        let tokens = crate::lexer::Lexer::new("count: 3\nbad: count?.size").tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let vm = VirtualMachine::new(crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap());
+        let error = vm.execute(0, &mut IsolatedHeap::new()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Cannot get property from non-object");
+    }
+
+    #[tokio::test]
+    async fn test_index_reads_are_sparse() {
+        let heap = run_source(r#"
+            items: [10, 20, 30]
+            key: "content type"
+            headers: { "content type": "json", "2": "two", "20": "twenty" }
+            first: items[0]
+            last: items[-1]
+            past_end: items[3]
+            before_start: items[-4]
+            fraction: items[0.5]
+            kind: headers[key]
+            numbered: headers[1 + 1]
+            tens: headers[20.0]
+            missing: headers["nope"]
+        "#).await;
+        
+        let get = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(get("first"), serde_json::json!(10));
+        assert_eq!(get("last"), serde_json::json!(30));
+        assert_eq!(get("past_end"), serde_json::Value::Null);
+        assert_eq!(get("before_start"), serde_json::Value::Null);
+        assert_eq!(get("fraction"), serde_json::Value::Null);
+        assert_eq!(get("kind"), serde_json::json!("json"));
+        assert_eq!(get("numbered"), serde_json::json!("two"));
+        assert_eq!(get("tens"), serde_json::json!("twenty"));
+        assert_eq!(get("missing"), serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_set_operators() {
+        let heap = run_source(r#"
+            user: { id: 1, name: "ada", password: "secret" }
+            public: user - "password"
+            summary: user * ["id", "name", "missing"]
+            stripped: user - ["id", "password"]
+            merged: user + { name: "grace", admin: true }
+            appended: [1, 2] + 3
+            joined: [1, 2] + [2, 3]
+            removed: [1, 2, 3, 2] - 2
+            removed_all: [1, 2, 3, 4] - [2, 4]
+            common: [1, 2, 3] * [3, 1, 5]
+            dashless: "2024-01-02" - "-"
+            vowels: "loaf language" * "aeiou"
+        "#).await;
+        
+        let get = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(get("public"), serde_json::json!({ "id": 1, "name": "ada" }));
+        assert_eq!(get("summary"), serde_json::json!({ "id": 1, "name": "ada" }));
+        assert_eq!(get("stripped"), serde_json::json!({ "name": "ada" }));
+        assert_eq!(get("merged"), serde_json::json!({ "id": 1, "name": "grace", "password": "secret", "admin": true }));
+        assert_eq!(get("appended"), serde_json::json!([1, 2, 3]));
+        assert_eq!(get("joined"), serde_json::json!([1, 2, 2, 3]));
+        assert_eq!(get("removed"), serde_json::json!([1, 3]));
+        assert_eq!(get("removed_all"), serde_json::json!([1, 3]));
+        assert_eq!(get("common"), serde_json::json!([1, 3]));
+        assert_eq!(get("dashless"), serde_json::json!("20240102"));
+        assert_eq!(get("vowels"), serde_json::json!("oaauae"));
+    }
+
+    #[tokio::test]
+    async fn test_number_keys_read_objects_and_maps_alike() {
+        let heap = run_source(r#"
+            plain: { "2": "two", a: 1 }
+            mixed: { "2": "two", [0]: "zero", 3: "three" }
+            from_plain: plain[2]
+            from_mixed: mixed[2]
+            exact: mixed[3]
+            plain_trimmed: plain - 2
+            mixed_trimmed: mixed - [2, [0]]
+            picked: mixed * [3, true]
+        "#).await;
+        
+        let json = |name: &str| serde_json::to_string(&heap.get_variable(name).unwrap().to_json()).unwrap();
+        assert_eq!(json("from_plain"), r#""two""#);
+        assert_eq!(json("from_mixed"), r#""two""#);
+        assert_eq!(json("exact"), r#""three""#);
+        assert_eq!(json("plain_trimmed"), r#"{"a":1}"#);
+        assert_eq!(json("mixed_trimmed"), r#"[[3,"three"]]"#);
+        assert_eq!(json("picked"), r#"[[3,"three"]]"#);
+    }
+
+    #[tokio::test]
+    async fn test_complex_object_keys() {
+        let heap = run_source(r#"
+            region: "west"
+            sales: {
+                [region, 2024]: 120,
+                ["east", 2024]: 80,
+                [region, 2024.0]: 150,
+                1: "one",
+                label: "by region",
+            }
+            west: sales[["west", 2024]]
+            one: sales[1]
+            label: sales.label
+            grid: { { x: 0, y: 1 }: "origin" }
+            origin: grid[{ y: 1, x: 0 }]
+            trimmed: sales - [1, "label"]
+        "#).await;
+        
+        let get = |name: &str| heap.get_variable(name).unwrap().to_json();
+        // Equal keys collapse, so the later entry wins
+        assert_eq!(get("west"), serde_json::json!(150));
+        assert_eq!(get("one"), serde_json::json!("one"));
+        assert_eq!(get("label"), serde_json::json!("by region"));
+        assert_eq!(get("origin"), serde_json::json!("origin"));
+        
+        // Serialized as `[key, value]` pairs in declaration order; a replaced
+        // key keeps its first position
+        assert_eq!(get("trimmed"), serde_json::json!([[["west", 2024], 150], [["east", 2024], 80]]));
+        assert_eq!(get("grid"), serde_json::json!([[{ "x": 0, "y": 1 }, "origin"]]));
+    }
+
+    #[tokio::test]
+    async fn test_objects_keep_declaration_order() {
+        let heap = run_source(r#"
+            order: { zebra: 1, apple: { y: 2, x: 3 }, mango: zebra + 1 }
+            merged: order + { apple: 0, kiwi: 4 }
+            keyed: { b: 1, 1: "one", a: 2 }
+        "#).await;
+        
+        let json = |name: &str| serde_json::to_string(&heap.get_variable(name).unwrap().to_json()).unwrap();
+        assert_eq!(json("order"), r#"{"zebra":1,"apple":{"y":2,"x":3},"mango":2}"#);
+        // A replaced key keeps its place, new keys go last
+        assert_eq!(json("merged"), r#"{"zebra":1,"apple":0,"mango":2,"kiwi":4}"#);
+        // Named and computed keys keep their places
+        assert_eq!(json("keyed"), r#"[["b",1],[1,"one"],["a",2]]"#);
+    }
+
+    #[tokio::test]
+    async fn test_tuples_and_destructuring() {
+        let heap = run_source(r#"
+            pair: (1 + 1, "two")
+            (count, label): pair
+            second: pair[1]
+            same: pair = (2, "two")
+            keyed: { (1, 2): "tuple", [1, 2]: "array" }
+            by_tuple: keyed[(1, 2)]
+        "#).await;
+        
+        let get = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(get("pair"), serde_json::json!([2, "two"]));
+        assert_eq!(get("count"), serde_json::json!(2));
+        assert_eq!(get("label"), serde_json::json!("two"));
+        assert_eq!(get("second"), serde_json::json!("two"));
+        assert_eq!(get("same"), serde_json::json!(true));
+        
+        // A tuple and an array with the same items are different keys
+        assert_eq!(get("by_tuple"), serde_json::json!("tuple"));
+    }
+
+    #[tokio::test]
+    async fn test_function_calls() {
+        let heap = run_source(r#"
+            fullName(u): u.first + " " + u.last
+            ada: { first: "Ada", last: "Lovelace" }
+            name: fullName(ada)
+            other: fullName({ first: "Alan", last: "Turing" })
+            fact(n): n <= 1 ? 1 : n * fact(n - 1)
+            f5: fact(5)
+            rate: 2
+            scale(x): { value: x * rate, big: x > 10 ? "yes" : "no" }
+            scaled: scale(21)
+            zero(): 0
+            z: zero()
+        "#).await;
+        
+        let get = |name: &str| heap.get_variable(name).unwrap().to_json();
+        assert_eq!(get("name"), serde_json::json!("Ada Lovelace"));
+        assert_eq!(get("other"), serde_json::json!("Alan Turing"));
+        assert_eq!(get("f5"), serde_json::json!(120));
+        assert_eq!(get("scaled"), serde_json::json!({ "value": 42, "big": "yes" }));
+        assert_eq!(get("z"), serde_json::json!(0));
+        
+        // Parameters and locals live in the call's frame, not the globals
+        assert!(heap.get_variable("fullName.u").is_none());
+        assert!(heap.get_variable("fact()").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http_call_url_is_computed() {
+        let heap = run_source(r#"
+            city: "new york"
+            weather: //weather/current?city={city}&days={1 + 2}
+        "#).await;
+        
+        let weather = heap.get_variable("weather").unwrap().to_json().to_string();
+        assert!(weather.contains("GET://weather/current?city=new%20york&days=3"));
+    }
+
+    #[tokio::test]
+    async fn test_http_call_is_a_promise_of_the_response_body() {
+        let source = "zip: 98101\ntax: //tax/rates?zip={zip}";
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let tax_type = &analyzed.symbol_table.get_symbol("tax").unwrap().symbol_type;
+        assert_eq!(*tax_type, crate::analyzer::Type::Promise(Some(Box::new(crate::analyzer::Type::String))));
+        
+        let heap = run_source(source).await;
+        let body = r#"{"simulated_response": "GET://tax/rates?zip=98101"}"#;
+        assert!(matches!(heap.get_variable("tax"), Some(Value::String(s)) if s == body));
+        
+        // The call's promise is resolved with the same body
+        let (id, promise) = heap.promises.iter().find(|(id, _)| id.starts_with("http:")).unwrap();
+        assert!(heap.is_promise_resolved(id));
+        assert!(matches!(&promise.value, Some(Value::String(s)) if s == body));
+    }
+
+    #[tokio::test]
+    async fn test_deferred_symbol_runs_once_across_branches() {
+        let heap = run_source(r#"
+            is_gold: 2 > 1
+            gold: gold_price()
+            prices: [is_gold ? gold : 0, is_gold ? gold : 1]
+        "#).await;
+        
+        assert!(heap.get_variable("gold").is_some());
+        assert_eq!(heap.promises.keys().filter(|id| id.starts_with("gold_price:")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_skips_http_call_in_other_branch() {
+        use crate::lexer::Span;
+        use crate::parser::{AstNode, HttpMethod};
+        
+        let call = |url: &str| AstNode::HttpCall {
+            url: Box::new(AstNode::String(url.to_string(), Span::default())),
+            method: HttpMethod::Get,
+            body: None,
+            headers: None,
+            span: Span::default(),
+        };
+        let ast = AstNode::Program(vec![AstNode::Assignment {
+            name: "response".to_string(),
+            value: Box::new(AstNode::Conditional {
+                condition: Box::new(AstNode::Boolean(false, Span::default())),
+                then_branch: Box::new(call("//metered/expensive")),
+                else_branch: Box::new(call("//cheap/lookup")),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        }]);
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let program = crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap();
+        
+        let vm = VirtualMachine::new(program);
+        let mut heap = IsolatedHeap::new();
+        vm.execute(0, &mut heap).await.unwrap();
+        
+        let response = heap.get_variable("response").unwrap().to_json().to_string();
+        assert!(response.contains("//cheap/lookup"));
+        assert!(!response.contains("//metered/expensive"));
+        
+        // The untaken branch never ran, so nothing anywhere holds its response
+        assert!(heap.variables.values().all(|value| !value.to_json().to_string().contains("expensive")));
     }
 
     #[tokio::test]
@@ -487,7 +1527,7 @@ mod tests {
             ],
             constants: vec![],
             endpoints: {
-                let mut endpoints = HashMap::new();
+                let mut endpoints = IndexMap::new();
                 endpoints.insert("test_handler".to_string(), EndpointInfo {
                     method: "GET".to_string(),
                     path: "/test".to_string(),
@@ -496,6 +1536,7 @@ mod tests {
                 });
                 endpoints
             },
+            functions: IndexMap::new(),
             entry_point: 0,
         };
 
@@ -513,4 +1554,84 @@ mod tests {
         assert_eq!(response.status, 200);
         assert!(response.body.is_some());
     }
+
+    #[tokio::test]
+    async fn test_annotated_endpoint_answers_requests() {
+        let tokens = crate::lexer::Lexer::new(r#"
+            @endpoint(GET, "/hello")
+            hello: { message: $"Hello {name}" }
+            name: "Ada"
+        "#).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let program = crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap();
+        let vm = VirtualMachine::new(program);
+        
+        // The handler computes what it needs in the request's own heap
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/hello".to_string(),
+            headers: HashMap::new(),
+            body: None,
+            query_params: HashMap::new(),
+        };
+        let response = vm.handle_request(request).await.unwrap();
+        assert_eq!(response.body.as_deref(), Some(r#"{"message":"Hello Ada"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_function_endpoint_is_called_with_the_request() {
+        let tokens = crate::lexer::Lexer::new(r#"
+            @endpoint(GET, "/method")
+            describe(request): { method: request.method, greeting: greeting }
+            greeting: "hi"
+        "#).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let vm = VirtualMachine::new(crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap());
+        
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/method".to_string(),
+            headers: HashMap::new(),
+            body: None,
+            query_params: HashMap::new(),
+        };
+        let response = vm.handle_request(request).await.unwrap();
+        assert_eq!(response.body.as_deref(), Some(r#"{"method":"GET","greeting":"hi"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_cached_value_in_a_function_is_reused_across_calls() {
+        let source = "rated(n): { @cache(60)\nrate: now(), total: n * 2 }\na: rated(1)\nb: rated(2)";
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let vm = VirtualMachine::new(crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap());
+        
+        let mut heap = IsolatedHeap::new();
+        vm.execute(0, &mut heap).await.unwrap();
+        
+        // Only the first call computes the rate
+        assert_eq!(heap.promises.keys().filter(|id| id.starts_with("now:")).count(), 1);
+        assert!(vm.cache.read().await.contains_key("rated().rate"));
+    }
+
+    #[tokio::test]
+    async fn test_cached_value_is_reused_across_runs() {
+        let tokens = crate::lexer::Lexer::new("@cache(60)\nstamp: now()\nfresh: now()").tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let analyzed = crate::analyzer::SemanticAnalyzer::new().analyze(&ast).unwrap();
+        let vm = VirtualMachine::new(crate::codegen::CodeGenerator::new().generate(&analyzed).unwrap());
+        
+        let mut first = IsolatedHeap::new();
+        vm.execute(0, &mut first).await.unwrap();
+        let mut second = IsolatedHeap::new();
+        vm.execute(0, &mut second).await.unwrap();
+        
+        // Only `fresh` calls out again on the second run
+        let calls = |heap: &IsolatedHeap| heap.promises.keys().filter(|id| id.starts_with("now:")).count();
+        assert_eq!((calls(&first), calls(&second)), (2, 1));
+        assert!(second.get_variable("stamp").is_some());
+    }
 }
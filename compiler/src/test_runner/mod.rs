@@ -1,5 +1,8 @@
-use crate::analyzer::{AnalyzedProgram, TestInfo};
-use crate::parser::AstNode;
+use crate::analyzer::{AnalyzedProgram, Assertion, TestInfo};
+use crate::parser::{AstNode, TemplatePart};
+use bigdecimal::BigDecimal;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use regex::Regex;
 use std::fmt;
@@ -125,6 +128,12 @@ impl TestRunner {
             }
         };
 
+        let expected = match test.assertion {
+            Assertion::Equals | Assertion::Matches => self.value_to_string(&expected_value),
+            Assertion::GreaterThan => format!("greater than {}", self.value_to_string(&expected_value)),
+            Assertion::LessThan => format!("less than {}", self.value_to_string(&expected_value)),
+        };
+
         // Evaluate the actual value by executing the expect expression against the program's symbol table
         let actual_value = match self.evaluate_ast_node(&test.expect_expression, analyzed) {
             Ok(value) => value,
@@ -132,42 +141,40 @@ impl TestRunner {
                 return TestResult {
                     test_name: test.name.clone(),
                     passed: false,
-                    expected: self.value_to_string(&expected_value),
+                    expected,
                     actual: "".to_string(),
                     error_message: Some(format!("Failed to evaluate expect expression: {}", error)),
                 };
             }
         };
 
+        let actual = self.value_to_string(&actual_value);
+
         // Compare expected vs actual using deep value comparison
-        let passed = if test.is_regex {
-            match Regex::new(&self.value_to_string(&expected_value)) {
-                Ok(regex) => regex.is_match(&self.value_to_string(&actual_value)),
-                Err(error) => {
-                    return TestResult {
-                        test_name: test.name.clone(),
-                        passed: false,
-                        expected: self.value_to_string(&expected_value),
-                        actual: self.value_to_string(&actual_value),
-                        error_message: Some(format!("Invalid regex pattern: {}", error)),
-                    };
-                }
-            }
-        } else {
-            self.values_equal(&expected_value, &actual_value)
+        let compared = match test.assertion {
+            Assertion::Equals => Ok(self.values_equal(&expected_value, &actual_value)),
+            Assertion::Matches => Regex::new(&self.value_to_string(&expected_value))
+                .map(|regex| regex.is_match(&actual))
+                .map_err(|error| format!("Invalid regex pattern: {}", error)),
+            Assertion::GreaterThan => self.compare_values(&actual_value, &expected_value).map(|ordering| ordering.is_gt()),
+            Assertion::LessThan => self.compare_values(&actual_value, &expected_value).map(|ordering| ordering.is_lt()),
         };
 
+        let (passed, error_message) = match compared {
+            Ok(passed) => (passed, None),
+            Err(error) => (false, Some(error)),
+        };
         TestResult {
             test_name: test.name.clone(),
             passed,
-            expected: self.value_to_string(&expected_value),
-            actual: self.value_to_string(&actual_value),
-            error_message: None,
+            expected,
+            actual,
+            error_message,
         }
     }
 
     /// Create a test environment with input values set as variables
-    fn create_test_environment(&self, inputs: &HashMap<String, AstNode>, analyzed: &AnalyzedProgram) -> TestEnvironment {
+    pub fn create_test_environment(&self, inputs: &HashMap<String, AstNode>, analyzed: &AnalyzedProgram) -> TestEnvironment {
         let mut variables = HashMap::new();
         
         for (name, ast_node) in inputs {
@@ -190,10 +197,10 @@ impl TestRunner {
     /// Evaluate an AST node to produce a test value
     fn evaluate_ast_node(&self, node: &AstNode, analyzed: &AnalyzedProgram) -> Result<TestValue, String> {
         match node {
-            AstNode::String(s) => Ok(TestValue::String(s.clone())),
-            AstNode::Number(n) => Ok(TestValue::Number(*n)),
-            AstNode::Boolean(b) => Ok(TestValue::Boolean(*b)),
-            AstNode::Null => Ok(TestValue::Null),
+            AstNode::String(s, _) => Ok(TestValue::String(s.clone())),
+            AstNode::Number(n, _) => Ok(TestValue::Number(n.clone())),
+            AstNode::Boolean(b, _) => Ok(TestValue::Boolean(*b)),
+            AstNode::Null(_) => Ok(TestValue::Null),
             AstNode::Array { elements, .. } => {
                 let mut values = Vec::new();
                 for element in elements {
@@ -202,13 +209,29 @@ impl TestRunner {
                 Ok(TestValue::Array(values))
             }
             AstNode::Object { fields, .. } => {
-                let mut object = HashMap::new();
+                let mut object = IndexMap::new();
                 for (key, value) in fields {
-                    object.insert(key.clone(), self.evaluate_ast_node(value, analyzed)?);
+                    let Some(name) = key.name() else {
+                        return Err("Objects with complex keys cannot be evaluated in tests".to_string());
+                    };
+                    object.insert(name.to_string(), self.evaluate_ast_node(value, analyzed)?);
                 }
                 Ok(TestValue::Object(object))
             }
-            AstNode::Identifier(name) => {
+            AstNode::Template { parts, .. } => {
+                let mut result = String::new();
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(text) => result.push_str(text),
+                        TemplatePart::Expression(expression) => {
+                            let value = self.evaluate_ast_node(expression, analyzed)?;
+                            result.push_str(&self.value_to_string(&value));
+                        }
+                    }
+                }
+                Ok(TestValue::String(result))
+            }
+            AstNode::Identifier(name, _) => {
                 // Look up the identifier in the program's symbol table
                 if let Some(symbol) = analyzed.symbol_table.get_symbol(name) {
                     if let Some(ast_node) = &symbol.ast_node {
@@ -221,7 +244,7 @@ impl TestRunner {
                     Err(format!("Undefined symbol '{}'", name))
                 }
             }
-            AstNode::MemberAccess { object, property, .. } => {
+            AstNode::MemberAccess { object, property, null_safe, .. } => {
                 // Evaluate the object first
                 let object_value = self.evaluate_ast_node(object, analyzed)?;
                 
@@ -234,6 +257,7 @@ impl TestRunner {
                             Err(format!("Property '{}' not found in object", property))
                         }
                     }
+                    _ if *null_safe => Ok(TestValue::Null),
                     _ => {
                         Err(format!("Cannot access property '{}' on non-object value", property))
                     }
@@ -272,7 +296,8 @@ impl TestRunner {
         match (a, b) {
             (Null, Null) => true,
             (Boolean(ba), Boolean(bb)) => ba == bb,
-            (Number(na), Number(nb)) => (na - nb).abs() < f64::EPSILON,
+            // Decimal equality ignores scale, so 0.1 equals 0.10
+            (Number(na), Number(nb)) => na == nb,
             (String(sa), String(sb)) => sa == sb,
             (Array(ae), Array(be)) => {
                 if ae.len() != be.len() {
@@ -303,7 +328,14 @@ impl TestRunner {
         }
     }
 
-
+    /// Order two numbers or two strings, for `greater_than` and `less_than`
+    fn compare_values(&self, a: &TestValue, b: &TestValue) -> Result<Ordering, String> {
+        match (a, b) {
+            (TestValue::Number(na), TestValue::Number(nb)) => Ok(na.cmp(nb)),
+            (TestValue::String(sa), TestValue::String(sb)) => Ok(sa.cmp(sb)),
+            _ => Err(format!("Cannot order {} and {}", self.value_to_string(a), self.value_to_string(b))),
+        }
+    }
 }
 
 /// Test execution environment containing input variables
@@ -317,10 +349,10 @@ pub struct TestEnvironment {
 pub enum TestValue {
     Null,
     Boolean(bool),
-    Number(f64),
+    Number(BigDecimal),
     String(String),
     Array(Vec<TestValue>),
-    Object(HashMap<String, TestValue>),
+    Object(IndexMap<String, TestValue>),
 }
 
 impl fmt::Display for TestValue {
@@ -354,7 +386,8 @@ mod tests {
     use super::*;
     use crate::analyzer::SemanticAnalyzer;
     use crate::parser::{Parser, AstNode};
-    use crate::lexer::Lexer;
+    use crate::lexer::{Lexer, Span};
+    use std::str::FromStr;
 
     fn create_test_program(source: &str) -> AnalyzedProgram {
         let mut lexer = Lexer::new(source);
@@ -381,7 +414,7 @@ mod tests {
         
         assert_eq!(runner.value_to_string(&TestValue::Null), "null");
         assert_eq!(runner.value_to_string(&TestValue::Boolean(true)), "true");
-        assert_eq!(runner.value_to_string(&TestValue::Number(42.0)), "42");
+        assert_eq!(runner.value_to_string(&TestValue::Number(BigDecimal::from(42))), "42");
         assert_eq!(runner.value_to_string(&TestValue::String("hello".to_string())), "hello");
     }
 
@@ -390,33 +423,89 @@ mod tests {
         let runner = TestRunner::new(false);
         let program = create_test_program("x = 5");
         
-        let string_node = AstNode::String("test".to_string());
+        let string_node = AstNode::String("test".to_string(), Span::default());
         let result = runner.evaluate_ast_node(&string_node, &program).unwrap();
         assert_eq!(result, TestValue::String("test".to_string()));
         
-        let number_node = AstNode::Number(42.0);
+        let number_node = AstNode::Number(BigDecimal::from(42), Span::default());
         let result = runner.evaluate_ast_node(&number_node, &program).unwrap();
-        assert_eq!(result, TestValue::Number(42.0));
+        assert_eq!(result, TestValue::Number(BigDecimal::from(42)));
         
-        let bool_node = AstNode::Boolean(true);
+        let bool_node = AstNode::Boolean(true, Span::default());
         let result = runner.evaluate_ast_node(&bool_node, &program).unwrap();
         assert_eq!(result, TestValue::Boolean(true));
     }
 
+    #[test]
+    fn test_decimal_values_equal() {
+        let runner = TestRunner::new(false);
+        let program = create_test_program("total = 0.05 + 0.10");
+
+        let tenth = TestValue::Number(BigDecimal::from_str("0.1").unwrap());
+        let padded = TestValue::Number(BigDecimal::from_str("0.10").unwrap());
+        assert!(runner.values_equal(&tenth, &padded));
+        assert_eq!(runner.value_to_string(&padded), "0.10");
+
+        let sum = runner.evaluate_ast_node(&AstNode::Number(BigDecimal::from_str("0.15").unwrap(), Span::default()), &program).unwrap();
+        assert!(!runner.values_equal(&sum, &TestValue::Number(BigDecimal::from_str("0.15000000000000002").unwrap())));
+    }
+
+    #[test]
+    fn test_assertion_kinds() {
+        let runner = TestRunner::new(false);
+        let program = create_test_program(r#"
+            @test("equal") { equals: 30 }
+            @test("not equal") { equals: 31 }
+            @test("older") { greater_than: 25 }
+            @test("not older") { greater_than: 30 }
+            @test("younger") { less_than: 31 }
+            @test("not younger") { less_than: 30 }
+            age: 30
+            @test("matching") { matches: "^Al" }
+            @test("not matching") { matches: "^Bo" }
+            @test("after") { greater_than: "Aa" }
+            @test("not before") { less_than: "Aa" }
+            name: "Alice"
+        "#);
+        
+        let summary = runner.run_tests(&program);
+        let outcomes: Vec<(&str, bool)> = summary.results.iter()
+            .map(|result| (result.test_name.as_str(), result.passed))
+            .collect();
+        assert_eq!(outcomes, vec![
+            ("equal", true), ("not equal", false),
+            ("older", true), ("not older", false),
+            ("younger", true), ("not younger", false),
+            ("matching", true), ("not matching", false),
+            ("after", true), ("not before", false),
+        ]);
+        assert_eq!((summary.results[3].expected.as_str(), summary.results[3].actual.as_str()), ("greater than 30", "30"));
+    }
+
+    #[test]
+    fn test_ordering_needs_comparable_values() {
+        let runner = TestRunner::new(false);
+        let program = create_test_program("@test(\"mixed\") { greater_than: 1 }\nname: \"Alice\"");
+        
+        let result = &runner.run_tests(&program).results[0];
+        assert!(!result.passed);
+        assert_eq!(result.error_message.as_deref(), Some("Cannot order Alice and 1"));
+    }
+
     #[test]
     fn test_create_test_environment() {
         let runner = TestRunner::new(false);
         let program = create_test_program("x = 5");
         
         let mut inputs = HashMap::new();
-        inputs.insert("test_var".to_string(), AstNode::String("hello".to_string()));
-        inputs.insert("test_num".to_string(), AstNode::Number(42.0));
+        inputs.insert("test_var".to_string(), AstNode::String("hello".to_string(), Span::default()));
+        inputs.insert("test_num".to_string(), AstNode::Number(BigDecimal::from(42), Span::default()));
         
         let env = runner.create_test_environment(&inputs, &program);
         
         assert_eq!(env.variables.len(), 2);
         assert_eq!(env.variables.get("test_var"), Some(&TestValue::String("hello".to_string())));
-        assert_eq!(env.variables.get("test_num"), Some(&TestValue::Number(42.0)));
+        assert_eq!(env.variables.get("test_num"), Some(&TestValue::Number(BigDecimal::from(42))));
     }
 
     #[test]
@@ -6,6 +6,8 @@ pub use instruction::Instruction;
 pub use opcode::OpCode;
 pub use parser::{Parser, ParseError};
 
+use bigdecimal::BigDecimal;
+
 /// Represents a constant value in the bytecode
 #[derive(Debug, Clone)]
 pub enum Constant {
@@ -14,6 +16,7 @@ pub enum Constant {
     String(String),
     Boolean(bool),
     Null,
+    Decimal(BigDecimal),
 }
 
 /// Represents a bytecode module
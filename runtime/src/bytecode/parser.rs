@@ -1,4 +1,6 @@
 use std::io::{Error as IoError, Read};
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
 use byteorder::{ReadBytesExt, BigEndian};
 use thiserror::Error;
 use crate::bytecode::{BytecodeModule, Constant, Instruction, OpCode};
@@ -66,6 +68,15 @@ impl Parser {
                     let value = reader.read_u8()? != 0;
                     Constant::Boolean(value)
                 },
+                5 => {
+                    let str_len = reader.read_u32::<BigEndian>()? as usize;
+                    let mut str_bytes = vec![0u8; str_len];
+                    reader.read_exact(&mut str_bytes)?;
+                    let text = String::from_utf8_lossy(&str_bytes);
+                    let value = BigDecimal::from_str(&text)
+                        .map_err(|_| ParseError::InvalidFormat(format!("Invalid decimal constant: {}", text)))?;
+                    Constant::Decimal(value)
+                },
                 _ => return Err(ParseError::InvalidFormat(format!("Unknown constant type: {}", const_type))),
             };
             module.constants.push(constant);
@@ -339,6 +350,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_decimal_constant() {
+        let mut data = create_valid_header();
+        
+        // Module name "dec"
+        data.write_u32::<BigEndian>(3).unwrap();
+        data.extend_from_slice(b"dec");
+        
+        // Two decimal constants
+        data.write_u32::<BigEndian>(2).unwrap();
+        data.write_u8(5).unwrap();
+        data.write_u32::<BigEndian>(4).unwrap();
+        data.extend_from_slice(b"0.10");
+        data.write_u8(5).unwrap();
+        data.write_u32::<BigEndian>(30).unwrap();
+        data.extend_from_slice(b"123456789012345678901234567.89");
+        
+        // No instructions
+        data.write_u32::<BigEndian>(0).unwrap();
+        
+        let mut cursor = Cursor::new(data);
+        let module = Parser::parse(&mut cursor).unwrap();
+        
+        match &module.constants[0] {
+            Constant::Decimal(d) => assert_eq!(d.to_string(), "0.10"),
+            _ => panic!("Expected Decimal(0.10)"),
+        }
+        
+        match &module.constants[1] {
+            Constant::Decimal(d) => assert_eq!(d.to_string(), "123456789012345678901234567.89"),
+            _ => panic!("Expected Decimal(123456789012345678901234567.89)"),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_decimal_constant() {
+        let mut data = create_valid_header();
+        
+        // Module name "dec"
+        data.write_u32::<BigEndian>(3).unwrap();
+        data.extend_from_slice(b"dec");
+        
+        data.write_u32::<BigEndian>(1).unwrap();
+        data.write_u8(5).unwrap();
+        data.write_u32::<BigEndian>(3).unwrap();
+        data.extend_from_slice(b"1.x");
+        
+        let mut cursor = Cursor::new(data);
+        match Parser::parse(&mut cursor).unwrap_err() {
+            ParseError::InvalidFormat(msg) => assert_eq!(msg, "Invalid decimal constant: 1.x"),
+            _ => panic!("Expected InvalidFormat error"),
+        }
+    }
+
     #[test]
     fn test_parse_unknown_constant_type() {
         let mut data = create_valid_header();
@@ -6,6 +6,12 @@ pub struct HeapManager {
     next_id: AtomicU32,
 }
 
+impl Default for HeapManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HeapManager {
     pub fn new() -> Self {
         Self {
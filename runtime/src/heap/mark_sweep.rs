@@ -3,11 +3,13 @@ use crate::heap::HeapResult;
 use crate::memory::ObjectReference;
 
 /// Mark-Sweep garbage collector implementation
+#[allow(dead_code)]
 pub struct MarkSweepGC {
     // Root objects that are directly accessible
     roots: HashSet<ObjectReference>,
 }
 
+#[allow(dead_code)]
 impl MarkSweepGC {
     pub fn new() -> Self {
         Self {
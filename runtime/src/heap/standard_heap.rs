@@ -13,6 +13,7 @@ pub struct StandardHeap {
 }
 
 /// A type-erased wrapper for storing objects of different types
+#[allow(dead_code)]
 struct ObjectBox {
     object: Arc<dyn Any + Send + Sync>,
     size: usize,
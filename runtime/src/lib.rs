@@ -1,5 +1,15 @@
 // Loaf - A bytecode runtime with multiple memory-managed heaps
 
+// The tests spell out literal values and trait calls on purpose
+#![cfg_attr(test, allow(
+    clippy::approx_constant,
+    clippy::bool_assert_comparison,
+    clippy::clone_on_copy,
+    clippy::field_reassign_with_default,
+    clippy::manual_range_contains,
+    clippy::unnecessary_literal_unwrap,
+))]
+
 pub mod bytecode;
 pub mod heap;
 pub mod memory;
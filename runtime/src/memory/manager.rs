@@ -16,6 +16,12 @@ impl From<HeapError> for MemoryError {
     }
 }
 
+impl Default for MemoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MemoryManager {
     pub fn new() -> Self {
         let heap_manager = Arc::new(HeapManager::new());
@@ -421,7 +427,7 @@ mod tests {
         
         // All allocations should succeed and get unique object IDs
         assert_eq!(references.len(), 10);
-        for (i, obj_ref) in references.iter().enumerate() {
+        for obj_ref in references.iter() {
             assert_eq!(obj_ref.heap_id(), 1);
             // Objects should have sequential IDs 1-10
             assert!(obj_ref.object_id() >= 1 && obj_ref.object_id() <= 10);
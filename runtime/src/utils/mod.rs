@@ -47,6 +47,12 @@ pub fn write_bytecode<P: AsRef<Path>>(module: &BytecodeModule, path: P) -> Resul
                 writer.write_u8(4)?;
                 writer.write_u8(*b as u8)?;
             },
+            Constant::Decimal(d) => {
+                let text = d.to_string();
+                writer.write_u8(5)?;
+                writer.write_u32::<BigEndian>(text.len() as u32)?;
+                writer.write_all(text.as_bytes())?;
+            },
         }
     }
     
@@ -218,6 +224,7 @@ mod tests {
         module.constants.push(Constant::String("Hello".to_string()));
         module.constants.push(Constant::Boolean(true));
         module.constants.push(Constant::Boolean(false));
+        module.constants.push(Constant::Decimal("0.10".parse().unwrap()));
         
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("constants.bytecode");
@@ -228,7 +235,7 @@ mod tests {
         // Parse it back and verify
         let parsed_module = Parser::parse(&mut BufReader::new(File::open(&file_path).unwrap())).unwrap();
         assert_eq!(parsed_module.name, "test_constants");
-        assert_eq!(parsed_module.constants.len(), 6);
+        assert_eq!(parsed_module.constants.len(), 7);
         
         match &parsed_module.constants[0] {
             Constant::Null => {},
@@ -259,6 +266,11 @@ mod tests {
             Constant::Boolean(b) => assert_eq!(*b, false),
             _ => panic!("Expected Boolean constant"),
         }
+        
+        match &parsed_module.constants[6] {
+            Constant::Decimal(d) => assert_eq!(d.to_string(), "0.10"),
+            _ => panic!("Expected Decimal constant"),
+        }
     }
 
     #[test]
@@ -165,6 +165,7 @@ impl ExecutionContext {
             Constant::Float(f) => Value::Float(*f),
             Constant::String(s) => Value::String(s.clone()),
             Constant::Boolean(b) => Value::Boolean(*b),
+            Constant::Decimal(d) => Value::Decimal(d.clone()),
         })
     }
     
@@ -226,8 +227,8 @@ impl ExecutionContext {
     /// Take the current exception, clearing it from the context
     pub fn take_exception(&mut self) -> Option<Value> {
         let exc = self.current_exception.take();
-        if self.stack_trace_enabled && exc.is_some() {
-            println!("TAKING EXCEPTION: {}", exc.as_ref().unwrap());
+        if let Some(exc) = exc.as_ref().filter(|_| self.stack_trace_enabled) {
+            println!("TAKING EXCEPTION: {}", exc);
         }
         exc
     }
@@ -235,13 +236,8 @@ impl ExecutionContext {
     /// Find the appropriate exception handler for the current PC
     pub fn find_handler(&self, pc: usize) -> Option<&ExceptionHandler> {
         // Search from the newest handler to the oldest
-        for handler in self.exception_handlers.iter().rev() {
-            // A handler applies if the PC is within the protected region
-            if pc < handler.end_pc {
-                return Some(handler);
-            }
-        }
-        None
+        // A handler applies if the PC is within the protected region
+        self.exception_handlers.iter().rev().find(|handler| pc < handler.end_pc)
     }
 }
 
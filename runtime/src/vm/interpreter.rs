@@ -1,5 +1,7 @@
 use std::sync::Arc;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
 use crate::memory::MemoryManager;
 use crate::vm::{ExecutionContext, Value, VMError, VMResult};
 use crate::bytecode::{BytecodeModule, OpCode};
@@ -59,7 +61,7 @@ impl VM {
             let pc = context.pc();
             
             // Check if there's an exception that needs to be handled
-            if let Some(_) = context.get_exception() {
+            if context.get_exception().is_some() {
                 // We need to handle the exception case separately to avoid borrowing issues
                 // First, find the appropriate handler if any
                 let handler_info = {
@@ -118,7 +120,7 @@ impl VM {
                 },
 
                 OpCode::Push => {
-                    let const_idx = instruction.operands.get(0).copied().unwrap_or(0);
+                    let const_idx = instruction.operands.first().copied().unwrap_or(0);
                     let value = context.get_constant(const_idx)?;
                     context.push(value)?;
                 },
@@ -149,7 +151,16 @@ impl VM {
                     
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
-                            context.push(Value::Integer(i1 + i2))?;
+                            // Promote to decimal rather than overflowing
+                            let result = match i1.checked_add(i2) {
+                                Some(r) => Value::Integer(r),
+                                None => Value::Decimal(BigDecimal::from(i1) + BigDecimal::from(i2)),
+                            };
+                            context.push(result)?;
+                        },
+                        (a @ Value::Decimal(_), b) | (a, b @ Value::Decimal(_)) => {
+                            let (d1, d2) = Self::decimal_operands(&a, &b, "add")?;
+                            context.push(Value::Decimal(d1 + d2))?;
                         },
                         (Value::Float(f1), Value::Float(f2)) => {
                             context.push(Value::Float(f1 + f2))?;
@@ -177,7 +188,16 @@ impl VM {
                     
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
-                            context.push(Value::Integer(i1 - i2))?;
+                            // Promote to decimal rather than overflowing
+                            let result = match i1.checked_sub(i2) {
+                                Some(r) => Value::Integer(r),
+                                None => Value::Decimal(BigDecimal::from(i1) - BigDecimal::from(i2)),
+                            };
+                            context.push(result)?;
+                        },
+                        (a @ Value::Decimal(_), b) | (a, b @ Value::Decimal(_)) => {
+                            let (d1, d2) = Self::decimal_operands(&a, &b, "subtract")?;
+                            context.push(Value::Decimal(d1 - d2))?;
                         },
                         (Value::Float(f1), Value::Float(f2)) => {
                             context.push(Value::Float(f1 - f2))?;
@@ -202,7 +222,16 @@ impl VM {
                     
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
-                            context.push(Value::Integer(i1 * i2))?;
+                            // Promote to decimal rather than overflowing
+                            let result = match i1.checked_mul(i2) {
+                                Some(r) => Value::Integer(r),
+                                None => Value::Decimal(BigDecimal::from(i1) * BigDecimal::from(i2)),
+                            };
+                            context.push(result)?;
+                        },
+                        (a @ Value::Decimal(_), b) | (a, b @ Value::Decimal(_)) => {
+                            let (d1, d2) = Self::decimal_operands(&a, &b, "multiply")?;
+                            context.push(Value::Decimal(d1 * d2))?;
                         },
                         (Value::Float(f1), Value::Float(f2)) => {
                             context.push(Value::Float(f1 * f2))?;
@@ -230,10 +259,27 @@ impl VM {
                             if i2 == 0 {
                                 return Err(VMError::DivisionByZero);
                             }
-                            let q = i1 / i2;
-                            let r = i1 % i2;
-                            context.push(Value::Integer(r))?;
-                            context.push(Value::Integer(q))?;
+                            match (i1.checked_div(i2), i1.checked_rem(i2)) {
+                                (Some(q), Some(r)) => {
+                                    context.push(Value::Integer(r))?;
+                                    context.push(Value::Integer(q))?;
+                                },
+                                _ => {
+                                    // Only i64::MIN / -1 overflows; its quotient needs a decimal
+                                    let (r, q) = Self::decimal_divmod(BigDecimal::from(i1), BigDecimal::from(i2));
+                                    context.push(Value::Decimal(r))?;
+                                    context.push(Value::Decimal(q))?;
+                                },
+                            }
+                        },
+                        (a @ Value::Decimal(_), b) | (a, b @ Value::Decimal(_)) => {
+                            let (d1, d2) = Self::decimal_operands(&a, &b, "divide")?;
+                            if d2.is_zero() {
+                                return Err(VMError::DivisionByZero);
+                            }
+                            let (r, q) = Self::decimal_divmod(d1, d2);
+                            context.push(Value::Decimal(r))?;
+                            context.push(Value::Decimal(q))?;
                         },
                         (Value::Float(f1), Value::Float(f2)) => {
                             if f2 == 0.0 {
@@ -271,8 +317,12 @@ impl VM {
                     let value_clone = value.clone(); // Clone for error reporting
                     
                     match value {
-                        Value::Integer(i) => context.push(Value::Integer(-i))?,
+                        Value::Integer(i) => match i.checked_neg() {
+                            Some(n) => context.push(Value::Integer(n))?,
+                            None => context.push(Value::Decimal(-BigDecimal::from(i)))?,
+                        },
                         Value::Float(f) => context.push(Value::Float(-f))?,
+                        Value::Decimal(d) => context.push(Value::Decimal(-d))?,
                         _ => return Err(VMError::TypeError(format!("Cannot negate {:?}", value_clone))),
                     }
                 },
@@ -285,7 +335,7 @@ impl VM {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             context.push(Value::Integer(i1 & i2))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform bitwise AND on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform bitwise AND on non-integer values".to_string())),
                     }
                 },
                 
@@ -297,7 +347,7 @@ impl VM {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             context.push(Value::Integer(i1 | i2))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform bitwise OR on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform bitwise OR on non-integer values".to_string())),
                     }
                 },
                 
@@ -309,7 +359,7 @@ impl VM {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             context.push(Value::Integer(i1 ^ i2))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform bitwise XOR on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform bitwise XOR on non-integer values".to_string())),
                     }
                 },
                 
@@ -318,7 +368,7 @@ impl VM {
                     
                     match value {
                         Value::Integer(i) => context.push(Value::Integer(!i))?,
-                        _ => return Err(VMError::TypeError(format!("Cannot perform bitwise NOT on non-integer value"))),
+                        _ => return Err(VMError::TypeError("Cannot perform bitwise NOT on non-integer value".to_string())),
                     }
                 },
                 
@@ -329,11 +379,11 @@ impl VM {
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             if i2 < 0 {
-                                return Err(VMError::InvalidOperation(format!("Cannot shift left by negative amount")));
+                                return Err(VMError::InvalidOperation("Cannot shift left by negative amount".to_string()));
                             }
                             context.push(Value::Integer(i1 << i2))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform shift left on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform shift left on non-integer values".to_string())),
                     }
                 },
                 
@@ -344,11 +394,11 @@ impl VM {
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             if i2 < 0 {
-                                return Err(VMError::InvalidOperation(format!("Cannot shift right by negative amount")));
+                                return Err(VMError::InvalidOperation("Cannot shift right by negative amount".to_string()));
                             }
                             context.push(Value::Integer(i1 >> i2))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform shift right on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform shift right on non-integer values".to_string())),
                     }
                 },
                 
@@ -359,11 +409,11 @@ impl VM {
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             if i2 < 0 {
-                                return Err(VMError::InvalidOperation(format!("Cannot rotate left by negative amount")));
+                                return Err(VMError::InvalidOperation("Cannot rotate left by negative amount".to_string()));
                             }
                             context.push(Value::Integer((i1 << i2) | (i1 >> (32 - i2))))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform rotate left on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform rotate left on non-integer values".to_string())),
                     }
                 },
                 
@@ -374,11 +424,11 @@ impl VM {
                     match (v1, v2) {
                         (Value::Integer(i1), Value::Integer(i2)) => {
                             if i2 < 0 {
-                                return Err(VMError::InvalidOperation(format!("Cannot rotate right by negative amount")));
+                                return Err(VMError::InvalidOperation("Cannot rotate right by negative amount".to_string()));
                             }
                             context.push(Value::Integer((i1 >> i2) | (i1 << (32 - i2))))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot perform rotate right on non-integer values"))),
+                        _ => return Err(VMError::TypeError("Cannot perform rotate right on non-integer values".to_string())),
                     }
                 },
                 
@@ -414,6 +464,9 @@ impl VM {
                     
                     // Simple equality check - could be enhanced for more complex comparisons
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2).map(Ordering::is_eq).unwrap_or(false)
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 == i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 == f2,
                         (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
@@ -431,6 +484,9 @@ impl VM {
                     
                     // Not equal check
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2).map(Ordering::is_ne).unwrap_or(true)
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 != i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 != f2,
                         (Value::Boolean(b1), Value::Boolean(b2)) => b1 != b2,
@@ -452,6 +508,11 @@ impl VM {
                     
                     // Less than comparison
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2)
+                                .map_err(|_| VMError::TypeError(format!("Cannot compare {:?} and {:?} with <", v1_clone, v2_clone)))?
+                                .is_lt()
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 < i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 < f2,
                         (Value::Integer(i), Value::Float(f)) => (*i as f64) < *f,
@@ -473,6 +534,11 @@ impl VM {
                     
                     // Less than or equal comparison
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2)
+                                .map_err(|_| VMError::TypeError(format!("Cannot compare {:?} and {:?} with <=", v1_clone, v2_clone)))?
+                                .is_le()
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 <= i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 <= f2,
                         (Value::Integer(i), Value::Float(f)) => (*i as f64) <= *f,
@@ -494,6 +560,11 @@ impl VM {
                     
                     // Greater than comparison
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2)
+                                .map_err(|_| VMError::TypeError(format!("Cannot compare {:?} and {:?} with >", v1_clone, v2_clone)))?
+                                .is_gt()
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 > i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 > f2,
                         (Value::Integer(i), Value::Float(f)) => (*i as f64) > *f,
@@ -515,6 +586,11 @@ impl VM {
                     
                     // Greater than or equal comparison
                     let result = match (&v1, &v2) {
+                        (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                            Self::compare_decimal(&v1, &v2)
+                                .map_err(|_| VMError::TypeError(format!("Cannot compare {:?} and {:?} with >=", v1_clone, v2_clone)))?
+                                .is_ge()
+                        },
                         (Value::Integer(i1), Value::Integer(i2)) => i1 >= i2,
                         (Value::Float(f1), Value::Float(f2)) => f1 >= f2,
                         (Value::Integer(i), Value::Float(f)) => (*i as f64) >= *f,
@@ -527,7 +603,7 @@ impl VM {
                 },
                 
                 OpCode::Jump => {
-                    let target = instruction.operands.get(0).copied().unwrap_or(0) as usize;
+                    let target = instruction.operands.first().copied().unwrap_or(0) as usize;
                     if target >= context.module().instructions.len() {
                         return Err(VMError::InvalidProgramCounter(target));
                     }
@@ -537,7 +613,7 @@ impl VM {
                 
                 OpCode::JumpIf => {
                     // Get a copy of the operand first
-                    let target_op = instruction.operands.get(0).copied().unwrap_or(0);
+                    let target_op = instruction.operands.first().copied().unwrap_or(0);
                     let condition = context.pop()?;
                     
                     if condition.is_truthy() {
@@ -552,7 +628,7 @@ impl VM {
                 
                 OpCode::JumpIfNot => {
                     // Get a copy of the operand first
-                    let target_op = instruction.operands.get(0).copied().unwrap_or(0);
+                    let target_op = instruction.operands.first().copied().unwrap_or(0);
                     let condition = context.pop()?;
                     
                     if !condition.is_truthy() {
@@ -566,7 +642,7 @@ impl VM {
                 },
                 
                 OpCode::Call => {
-                    let target = instruction.operands.get(0).copied().unwrap_or(0) as usize;
+                    let target = instruction.operands.first().copied().unwrap_or(0) as usize;
                     context.increment_pc(); // Next instruction
                     let return_address = context.pc();
                     context.push(Value::ProgramCounter(return_address))?;
@@ -593,7 +669,7 @@ impl VM {
                 // Exception handling opcodes
                 OpCode::TryBlock => {
                     // Get catch and finally handler locations from operands
-                    let catch_pc = instruction.operands.get(0).copied().map(|pc| pc as usize);
+                    let catch_pc = instruction.operands.first().copied().map(|pc| pc as usize);
                     let finally_pc = instruction.operands.get(1).copied().map(|pc| pc as usize);
                     let end_pc = instruction.operands.get(2).copied().unwrap_or(0) as usize;
                     
@@ -688,13 +764,13 @@ impl VM {
                 },
 
                 OpCode::StoreLocal => {
-                    let idx = instruction.operands.get(0).copied().unwrap_or(0) as usize;
+                    let idx = instruction.operands.first().copied().unwrap_or(0) as usize;
                     let value = context.pop()?;
                     context.store_local(idx, value)?;
                 },
                 
                 OpCode::LoadLocal => {
-                    let idx = instruction.operands.get(0).copied().unwrap_or(0) as usize;
+                    let idx = instruction.operands.first().copied().unwrap_or(0) as usize;
                     let value = context.load_local(idx)?;
                     context.push(value)?;
                 },
@@ -727,7 +803,7 @@ impl VM {
                 },
 
                 OpCode::NewArray => {
-                    let size = instruction.operands.get(0).copied().unwrap_or(0) as usize;
+                    let size = instruction.operands.first().copied().unwrap_or(0) as usize;
                     let mut array = Vec::with_capacity(size);
 
                     // Pop values in reverse order (last item first)
@@ -750,7 +826,7 @@ impl VM {
                             }
                             i as usize
                         },
-                        _ => return Err(VMError::TypeError(format!("Array index must be an integer"))),
+                        _ => return Err(VMError::TypeError("Array index must be an integer".to_string())),
                     };
 
                     // Get the array element
@@ -769,7 +845,7 @@ impl VM {
                             let char_str = chars[index].to_string();
                             context.push(Value::String(char_str))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot index into non-array value"))),
+                        _ => return Err(VMError::TypeError("Cannot index into non-array value".to_string())),
                     }
                 },
 
@@ -786,7 +862,7 @@ impl VM {
                             }
                             i as usize
                         },
-                        _ => return Err(VMError::TypeError(format!("Array index must be an integer"))),
+                        _ => return Err(VMError::TypeError("Array index must be an integer".to_string())),
                     };
 
                     // Set the array element
@@ -803,7 +879,7 @@ impl VM {
                             // Push the new array back on the stack
                             context.push(Value::Array(Arc::new(array)))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot set element of non-array value"))),
+                        _ => return Err(VMError::TypeError("Cannot set element of non-array value".to_string())),
                     }
                 },
 
@@ -817,7 +893,7 @@ impl VM {
                         Value::String(string) => {
                             context.push(Value::Integer(string.len() as i64))?;
                         },
-                        _ => return Err(VMError::TypeError(format!("Cannot get length of non-array/string value"))),
+                        _ => return Err(VMError::TypeError("Cannot get length of non-array/string value".to_string())),
                     }
                 },
 
@@ -840,6 +916,27 @@ impl VM {
         
         Ok(context.pop().unwrap_or(Value::Null))
     }
+
+    /// Convert both operands of an arithmetic opcode to exact decimals
+    fn decimal_operands(v1: &Value, v2: &Value, operation: &str) -> VMResult<(BigDecimal, BigDecimal)> {
+        match (v1.as_decimal(), v2.as_decimal()) {
+            (Ok(d1), Ok(d2)) if v1.is_numeric() && v2.is_numeric() => Ok((d1, d2)),
+            _ => Err(VMError::TypeError(format!("Cannot {} {:?} and {:?}", operation, v1, v2))),
+        }
+    }
+
+    /// Compare two numbers exactly, where at least one of them is a decimal
+    fn compare_decimal(v1: &Value, v2: &Value) -> VMResult<Ordering> {
+        let (d1, d2) = Self::decimal_operands(v1, v2, "compare")?;
+        Ok(d1.cmp(&d2))
+    }
+
+    /// Truncating division returning `(remainder, quotient)`, matching the integer DIV stack order
+    fn decimal_divmod(d1: BigDecimal, d2: BigDecimal) -> (BigDecimal, BigDecimal) {
+        let q = (&d1 / &d2).with_scale_round(0, RoundingMode::Down);
+        let r = d1 - &q * d2;
+        (r, q)
+    }
 }
 
 #[cfg(test)]
@@ -892,6 +989,7 @@ mod tests {
             (Value::Null, Value::Null) => {},
             (Value::Integer(a), Value::Integer(b)) => assert_eq!(a, b),
             (Value::Float(a), Value::Float(b)) => assert_eq!(a, b),
+            (Value::Decimal(a), Value::Decimal(b)) => assert_eq!(a.to_string(), b.to_string()),
             (Value::Boolean(a), Value::Boolean(b)) => assert_eq!(a, b),
             (Value::String(a), Value::String(b)) => assert_eq!(a, b),
             (Value::Array(a), Value::Array(b)) => {
@@ -1188,6 +1286,125 @@ mod tests {
         }
     }
 
+    fn run_binary(opcode: OpCode, constants: Vec<Constant>) -> VMResult<Value> {
+        let mut vm = create_test_vm();
+        let instructions = vec![
+            Instruction { opcode: OpCode::Push, operands: vec![0] },
+            Instruction { opcode: OpCode::Push, operands: vec![1] },
+            Instruction { opcode, operands: vec![] },
+            Instruction { opcode: OpCode::Halt, operands: vec![] },
+        ];
+        let module = create_test_module_with_instructions(instructions, constants);
+        
+        vm.load_module(module);
+        vm.execute_module("test_module")
+    }
+
+    fn decimal(text: &str) -> BigDecimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_add_decimals_is_exact() {
+        let constants = vec![Constant::Decimal(decimal("0.05")), Constant::Decimal(decimal("0.10"))];
+        let result = run_binary(OpCode::Add, constants).unwrap();
+        
+        assert_value_equals(&result, &Value::Decimal(decimal("0.15")));
+    }
+
+    #[test]
+    fn test_decimal_with_integer_and_float() {
+        let result = run_binary(OpCode::Mul, vec![Constant::Decimal(decimal("123.00")), Constant::Integer(3)]).unwrap();
+        assert_value_equals(&result, &Value::Decimal(decimal("369.00")));
+        
+        let result = run_binary(OpCode::Sub, vec![Constant::Float(0.3), Constant::Decimal(decimal("0.1"))]).unwrap();
+        assert_value_equals(&result, &Value::Decimal(decimal("0.2")));
+    }
+
+    #[test]
+    fn test_integer_overflow_promotes_to_decimal() {
+        let result = run_binary(OpCode::Add, vec![Constant::Integer(i64::MAX), Constant::Integer(1)]).unwrap();
+        assert_value_equals(&result, &Value::Decimal(decimal("9223372036854775808")));
+        
+        let result = run_binary(OpCode::Sub, vec![Constant::Integer(i64::MIN), Constant::Integer(1)]).unwrap();
+        assert_value_equals(&result, &Value::Decimal(decimal("-9223372036854775809")));
+        
+        let result = run_binary(OpCode::Mul, vec![Constant::Integer(i64::MAX), Constant::Integer(2)]).unwrap();
+        assert_value_equals(&result, &Value::Decimal(decimal("18446744073709551614")));
+        
+        // Results that fit stay integers
+        let result = run_binary(OpCode::Add, vec![Constant::Integer(i64::MAX - 1), Constant::Integer(1)]).unwrap();
+        assert_value_equals(&result, &Value::Integer(i64::MAX));
+    }
+
+    #[test]
+    fn test_neg_integer_min_promotes_to_decimal() {
+        let mut vm = create_test_vm();
+        let instructions = vec![
+            Instruction { opcode: OpCode::Push, operands: vec![0] },
+            Instruction { opcode: OpCode::Neg, operands: vec![] },
+            Instruction { opcode: OpCode::Push, operands: vec![1] },
+            Instruction { opcode: OpCode::Neg, operands: vec![] },
+            Instruction { opcode: OpCode::Add, operands: vec![] },
+            Instruction { opcode: OpCode::Halt, operands: vec![] },
+        ];
+        let constants = vec![Constant::Integer(i64::MIN), Constant::Decimal(decimal("0.5"))];
+        let module = create_test_module_with_instructions(instructions, constants);
+        
+        vm.load_module(module);
+        let result = vm.execute_module("test_module").unwrap();
+        
+        assert_value_equals(&result, &Value::Decimal(decimal("9223372036854775807.5")));
+    }
+
+    #[test]
+    fn test_div_decimals() {
+        let mut vm = create_test_vm();
+        let instructions = vec![
+            Instruction { opcode: OpCode::Push, operands: vec![0] },
+            Instruction { opcode: OpCode::Push, operands: vec![1] },
+            Instruction { opcode: OpCode::Div, operands: vec![] },
+            Instruction { opcode: OpCode::Swap, operands: vec![] },
+            Instruction { opcode: OpCode::Halt, operands: vec![] },
+        ];
+        let constants = vec![Constant::Decimal(decimal("7.5")), Constant::Integer(2)];
+        let module = create_test_module_with_instructions(instructions, constants);
+        
+        vm.load_module(module);
+        let result = vm.execute_module("test_module").unwrap();
+        
+        // Remainder ends up on top after the swap
+        assert_value_equals(&result, &Value::Decimal(decimal("1.5")));
+        
+        match run_binary(OpCode::Div, vec![Constant::Decimal(decimal("1.0")), Constant::Decimal(decimal("0.00"))]) {
+            Err(VMError::DivisionByZero) => {},
+            _ => panic!("Expected DivisionByZero error"),
+        }
+    }
+
+    #[test]
+    fn test_decimal_comparisons() {
+        let result = run_binary(OpCode::Eq, vec![Constant::Decimal(decimal("0.10")), Constant::Decimal(decimal("0.1"))]).unwrap();
+        assert_value_equals(&result, &Value::Boolean(true));
+        
+        let result = run_binary(OpCode::Eq, vec![Constant::Decimal(decimal("2.00")), Constant::Integer(2)]).unwrap();
+        assert_value_equals(&result, &Value::Boolean(true));
+        
+        let result = run_binary(OpCode::Neq, vec![Constant::Decimal(decimal("1")), Constant::String("1".to_string())]).unwrap();
+        assert_value_equals(&result, &Value::Boolean(true));
+        
+        let result = run_binary(OpCode::Lt, vec![Constant::Integer(9223372036854775807), Constant::Decimal(decimal("9223372036854775807.01"))]).unwrap();
+        assert_value_equals(&result, &Value::Boolean(true));
+        
+        let result = run_binary(OpCode::Gte, vec![Constant::Decimal(decimal("0.15")), Constant::Float(0.15)]).unwrap();
+        assert_value_equals(&result, &Value::Boolean(true));
+        
+        match run_binary(OpCode::Gt, vec![Constant::Decimal(decimal("1")), Constant::String("a".to_string())]) {
+            Err(VMError::TypeError(msg)) => assert!(msg.contains("with >")),
+            _ => panic!("Expected TypeError"),
+        }
+    }
+
     #[test]
     fn test_neg_integer() {
         let mut vm = create_test_vm();
@@ -1,5 +1,7 @@
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
 use crate::memory::ObjectReference;
 
 /// Represents exception data for structured exception handling
@@ -28,6 +30,7 @@ pub enum Value {
     Null,
     Integer(i64),
     Float(f64),
+    Decimal(BigDecimal),
     Boolean(bool),
     String(String),
     Object(ObjectReference),
@@ -76,6 +79,7 @@ impl Value {
             Value::Null => false,
             Value::Integer(i) => *i != 0,
             Value::Float(f) => *f != 0.0 && !f.is_nan(),
+            Value::Decimal(d) => !d.is_zero(),
             Value::Boolean(b) => *b,
             Value::String(s) => !s.is_empty(),
             Value::Object(_) => true,
@@ -91,6 +95,8 @@ impl Value {
         match self {
             Value::Integer(i) => Ok(*i),
             Value::Float(f) => Ok(*f as i64),
+            Value::Decimal(d) => d.with_scale(0).to_i64()
+                .ok_or_else(|| format!("Cannot convert {} to integer", self)),
             Value::Boolean(b) => Ok(if *b { 1 } else { 0 }),
             _ => Err(format!("Cannot convert {} to integer", self)),
         }
@@ -101,11 +107,31 @@ impl Value {
         match self {
             Value::Integer(i) => Ok(*i as f64),
             Value::Float(f) => Ok(*f),
+            Value::Decimal(d) => d.to_f64()
+                .ok_or_else(|| format!("Cannot convert {} to float", self)),
             Value::Boolean(b) => Ok(if *b { 1.0 } else { 0.0 }),
             _ => Err(format!("Cannot convert {} to float", self)),
         }
     }
     
+    /// Get the value as an exact decimal or error
+    pub fn as_decimal(&self) -> Result<BigDecimal, String> {
+        match self {
+            Value::Integer(i) => Ok(BigDecimal::from(*i)),
+            // Go through the shortest round-trip text so 0.1 becomes 0.1, not its binary expansion
+            Value::Float(f) if f.is_finite() => BigDecimal::from_str(&f.to_string())
+                .map_err(|_| format!("Cannot convert {} to decimal", self)),
+            Value::Decimal(d) => Ok(d.clone()),
+            Value::Boolean(b) => Ok(BigDecimal::from(if *b { 1 } else { 0 })),
+            _ => Err(format!("Cannot convert {} to decimal", self)),
+        }
+    }
+    
+    /// Check if the value is any kind of number
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::Float(_) | Value::Decimal(_))
+    }
+    
     /// Get the value as a boolean or error
     pub fn as_boolean(&self) -> Result<bool, String> {
         Ok(self.is_truthy())
@@ -134,6 +160,7 @@ impl fmt::Display for Value {
             Value::Null => write!(f, "null"),
             Value::Integer(i) => write!(f, "{}", i),
             Value::Float(fl) => write!(f, "{}", fl),
+            Value::Decimal(d) => write!(f, "{}", d),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Object(obj) => write!(f, "<object:ref={:?}>", obj),
@@ -182,6 +209,15 @@ mod tests {
         assert_eq!(negative_value.to_string(), "-2.718");
     }
 
+    #[test]
+    fn test_value_decimal_display() {
+        let value = Value::Decimal(BigDecimal::from_str("0.10").unwrap());
+        assert_eq!(value.to_string(), "0.10");
+        
+        let negative_value = Value::Decimal(BigDecimal::from_str("-123456789012345678901234567.89").unwrap());
+        assert_eq!(negative_value.to_string(), "-123456789012345678901234567.89");
+    }
+
     #[test]
     fn test_value_boolean_display() {
         let true_value = Value::Boolean(true);
@@ -349,6 +385,23 @@ mod tests {
         assert!(Value::Null.as_float().is_err());
     }
 
+    #[test]
+    fn test_value_as_decimal() {
+        let decimal = |text: &str| BigDecimal::from_str(text).unwrap();
+        assert_eq!(Value::Integer(42).as_decimal().unwrap(), decimal("42"));
+        assert_eq!(Value::Float(0.1).as_decimal().unwrap(), decimal("0.1"));
+        assert_eq!(Value::Decimal(decimal("2.50")).as_decimal().unwrap(), decimal("2.50"));
+        assert_eq!(Value::Boolean(true).as_decimal().unwrap(), decimal("1"));
+        
+        // Other conversions truncate or round like a float would
+        assert_eq!(Value::Decimal(decimal("-7.9")).as_integer().unwrap(), -7);
+        assert_eq!(Value::Decimal(decimal("2.5")).as_float().unwrap(), 2.5);
+        
+        assert!(Value::Float(f64::NAN).as_decimal().is_err());
+        assert!(Value::String("1".to_string()).as_decimal().is_err());
+        assert!(Value::Null.as_decimal().is_err());
+    }
+
     #[test]
     fn test_value_as_boolean() {
         assert_eq!(Value::Boolean(true).as_boolean().unwrap(), true);